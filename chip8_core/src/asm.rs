@@ -0,0 +1,883 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+
+use crate::instruction::Instruction;
+
+// Assembler for the Octo language (https://github.com/JohnEarnest/Octo).
+// Supported: labels, :alias, :const, :macro, :call, :byte, :org, :breakpoint,
+// all statements for CHIP-8 / SUPER-CHIP / XO-CHIP, if ... then,
+// if ... begin ... else ... end, loop ... while ... again and raw data bytes.
+
+const ORIGIN: u16 = 0x200;
+const MAX_MACRO_EXPANSIONS: usize = 100_000;  // stops recursive macros
+
+pub struct Assembly {
+    pub bytes: Vec<u8>,                     // the ROM, loaded at 0x200
+    pub labels: BTreeMap<String, u16>,
+    pub lines: BTreeMap<u16, usize>,        // address of a statement -> source line
+    pub breakpoints: BTreeMap<u16, String>, // from :breakpoint
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+pub fn assemble(source: &str) -> Result<Assembly, AsmError> {
+    let mut assembler = Assembler::new(tokenize(source));
+    assembler.run()?;
+    assembler.finish()
+}
+
+// TOKENS
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    fn error(&self, message: String) -> AsmError {
+        AsmError { line: self.line, column: self.column, message }
+    }
+}
+
+// Octo tokens are separated by whitespace, # starts a comment
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+
+        let mut start: Option<usize> = None;
+        for (index, c) in line.char_indices().chain(std::iter::once((line.len(), ' '))) {
+            match (c.is_whitespace(), start) {
+                (true, Some(begin)) => {
+                    tokens.push_back(Token {
+                        text: line[begin..index].to_string(),
+                        line: line_index + 1,
+                        column: line[..begin].chars().count() + 1,
+                    });
+                    start = None;
+                },
+                (false, None) => start = Some(index),
+                _ => {},
+            }
+        }
+    }
+
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i64::from_str_radix(binary, 2).ok()?
+    } else {
+        digits.parse::<i64>().ok()?
+    };
+
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let lower = text.to_lowercase();
+    let digit = lower.strip_prefix('v')?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+const KEYWORDS: [&str; 30] = [
+    ":", ":=", "+=", "-=", "|=", "&=", "^=", "=-", ">>=", "<<=",
+    "if", "then", "begin", "else", "end", "loop", "while", "again",
+    "key", "-key", "random", "delay", "buzzer", "hex", "bighex", "long",
+    "i", "return", "clear", "jump",
+];
+
+// CONDITIONS
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Compare {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Key,
+    NotKey,
+}
+
+impl Compare {
+    fn negate(self) -> Self {
+        match self {
+            Compare::Eq => Compare::Ne,
+            Compare::Ne => Compare::Eq,
+            Compare::Lt => Compare::Ge,
+            Compare::Ge => Compare::Lt,
+            Compare::Gt => Compare::Le,
+            Compare::Le => Compare::Gt,
+            Compare::Key => Compare::NotKey,
+            Compare::NotKey => Compare::Key,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Operand {
+    Register(u8),
+    Byte(u8),
+}
+
+#[derive(Clone, Copy)]
+struct Condition {
+    register: u8,
+    compare: Compare,
+    operand: Operand,
+}
+
+// FIXUPS
+
+enum FixupKind {
+    Address12,  // NNN in the low 12 bits of an opcode
+    Address16,  // the second word of F000 NNNN
+}
+
+struct Fixup {
+    position: usize,
+    kind: FixupKind,
+    label: Token,
+}
+
+enum Block {
+    If { jump: usize },
+    Else { jump: usize },
+    Loop { start: u16, whiles: Vec<usize> },
+}
+
+struct Macro {
+    arguments: Vec<String>,
+    body: Vec<Token>,
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    last: Option<Token>,
+
+    rom: Vec<u8>,
+    address: u16,
+
+    labels: BTreeMap<String, u16>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+
+    fixups: Vec<Fixup>,
+    blocks: Vec<(Block, Token)>,
+    lines: BTreeMap<u16, usize>,
+    breakpoints: BTreeMap<u16, String>,
+
+    // position of the jump and the first `main` seen
+    main_jump: Option<(usize, Token)>,
+    macro_expansions: usize,
+}
+
+impl Assembler {
+    fn new(tokens: VecDeque<Token>) -> Self {
+        Assembler {
+            tokens,
+            last: None,
+
+            rom: Vec::new(),
+            address: ORIGIN,
+
+            labels: BTreeMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            lines: BTreeMap::new(),
+            breakpoints: BTreeMap::new(),
+
+            main_jump: None,
+            macro_expansions: 0,
+        }
+    }
+
+    // TOKEN STREAM
+
+    fn next(&mut self) -> Result<Token, AsmError> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.last = Some(token.clone());
+                Ok(token)
+            },
+            None => {
+                let (line, column) = match &self.last {
+                    Some(last) => (last.line, last.column + last.text.chars().count()),
+                    None => (1, 1),
+                };
+                Err(AsmError { line, column, message: "unexpected end of file".to_string() })
+            }
+        }
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.front().is_some_and(|token| token.text == text)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token, AsmError> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(token.error(format!("expected '{}', found '{}'", text, token.text)));
+        }
+        Ok(token)
+    }
+
+    fn register(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        self.register_from(&token)
+    }
+
+    fn register_from(&self, token: &Token) -> Result<u8, AsmError> {
+        if let Some(register) = self.aliases.get(&token.text) {
+            return Ok(*register);
+        }
+        parse_register(&token.text).ok_or_else(|| token.error(format!("expected a register, found '{}'", token.text)))
+    }
+
+    fn is_register(&self, token: &Token) -> bool {
+        self.aliases.contains_key(&token.text) || parse_register(&token.text).is_some()
+    }
+
+    fn number_from(&self, token: &Token) -> Option<i64> {
+        parse_number(&token.text).or_else(|| self.constants.get(&token.text).copied())
+    }
+
+    fn value_in_range(&mut self, low: i64, high: i64, what: &str) -> Result<i64, AsmError> {
+        let token = self.next()?;
+        let Some(value) = self.number_from(&token) else {
+            return Err(token.error(format!("expected {}, found '{}'", what, token.text)));
+        };
+        if value < low || value > high {
+            return Err(token.error(format!("{} {} out of range ({} to {})", what, value, low, high)));
+        }
+        Ok(value)
+    }
+
+    // bytes can be written signed, -1 is 0xFF
+    fn byte(&mut self) -> Result<u8, AsmError> {
+        Ok(self.value_in_range(-128, 255, "byte")? as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AsmError> {
+        Ok(self.value_in_range(0, 15, "nibble")? as u8)
+    }
+
+    fn identifier(&mut self, what: &str) -> Result<Token, AsmError> {
+        let token = self.next()?;
+        let text = token.text.as_str();
+        if KEYWORDS.contains(&text) || text.starts_with(':') || parse_number(text).is_some() || parse_register(text).is_some() {
+            return Err(token.error(format!("'{}' can't be used as {}", text, what)));
+        }
+        Ok(token)
+    }
+
+    // OUTPUT
+
+    fn position(&self) -> usize {
+        (self.address - ORIGIN) as usize
+    }
+
+    fn emit_byte(&mut self, token: &Token, byte: u8) -> Result<(), AsmError> {
+        if self.address < ORIGIN {
+            return Err(token.error(format!("address {:#X} is below the program start", self.address)));
+        }
+        let position = self.position();
+        if self.rom.len() <= position {
+            self.rom.resize(position + 1, 0);
+        }
+        self.rom[position] = byte;
+        self.address = self.address.checked_add(1).ok_or_else(|| token.error("program exceeds 64K".to_string()))?;
+        Ok(())
+    }
+
+    fn mark_line(&mut self, token: &Token) {
+        if self.address >= ORIGIN {
+            self.lines.entry(self.address).or_insert(token.line);
+        }
+    }
+
+    fn emit(&mut self, token: &Token, instruction: Instruction) -> Result<usize, AsmError> {
+        self.mark_line(token);
+        let position = self.position();
+        for byte in instruction.to_bytes() {
+            self.emit_byte(token, byte)?;
+        }
+        Ok(position)
+    }
+
+    // an instruction with an address that may not be known yet
+    fn emit_with_address(&mut self, token: &Token, target: Token, instruction: fn(u16) -> Instruction, long: bool) -> Result<(), AsmError> {
+        let known = self.number_from(&target).or_else(|| self.labels.get(&target.text).map(|addr| *addr as i64));
+        let limit = if long { 0xFFFF } else { 0xFFF };
+
+        match known {
+            Some(addr) if addr < 0 || addr > limit => {
+                Err(target.error(format!("address {:#X} out of range", addr)))
+            },
+            Some(addr) => self.emit(token, instruction(addr as u16)).map(|_| ()),
+            None => {
+                if parse_register(&target.text).is_some() || KEYWORDS.contains(&target.text.as_str()) {
+                    return Err(target.error(format!("expected an address, found '{}'", target.text)));
+                }
+                let position = self.emit(token, instruction(0))?;
+                let kind = if long { FixupKind::Address16 } else { FixupKind::Address12 };
+                self.fixups.push(Fixup { position, kind, label: target });
+                Ok(())
+            }
+        }
+    }
+
+    fn patch_jump(&mut self, position: usize, target: u16) {
+        self.rom[position] = 0x10 | ((target >> 8) & 0xF) as u8;
+        self.rom[position + 1] = (target & 0xFF) as u8;
+    }
+
+    // MAIN LOOP
+
+    fn run(&mut self) -> Result<(), AsmError> {
+        self.reserve_main_jump();
+
+        while !self.tokens.is_empty() {
+            let token = self.next()?;
+            self.statement(token)?;
+        }
+
+        if let Some((_, token)) = self.blocks.last() {
+            return Err(token.error(format!("'{}' is never closed", token.text)));
+        }
+        Ok(())
+    }
+
+    // like Octo, execution starts at `: main`. If main isn't the
+    // first thing in the program, 0x200 gets a jump to it
+    fn reserve_main_jump(&mut self) {
+        let tokens: Vec<&Token> = self.tokens.iter().collect();
+        let declaration = tokens.windows(2).find(|pair| pair[0].text == ":" && pair[1].text == "main");
+        let main_first = tokens.len() >= 2 && tokens[0].text == ":" && tokens[1].text == "main";
+
+        if let (Some(pair), false) = (declaration, main_first) {
+            self.main_jump = Some((0, pair[1].clone()));
+            self.rom.extend_from_slice(&[0, 0]);
+            self.address += 2;
+        }
+    }
+
+    fn finish(mut self) -> Result<Assembly, AsmError> {
+        if let Some((position, token)) = self.main_jump.take() {
+            // declared in a macro that is never used
+            let Some(main) = self.labels.get("main").copied() else {
+                return Err(token.error("'main' is never defined, is it in an unused macro?".to_string()));
+            };
+            self.patch_jump(position, main);
+        }
+
+        for fixup in &self.fixups {
+            let Some(addr) = self.labels.get(&fixup.label.text).copied() else {
+                return Err(fixup.label.error(format!("undefined name '{}'", fixup.label.text)));
+            };
+            match fixup.kind {
+                FixupKind::Address12 => {
+                    if addr > 0xFFF {
+                        return Err(fixup.label.error(format!("'{}' at {:#X} is out of reach, use 'i := long'", fixup.label.text, addr)));
+                    }
+                    self.rom[fixup.position] |= ((addr >> 8) & 0xF) as u8;
+                    self.rom[fixup.position + 1] = (addr & 0xFF) as u8;
+                },
+                FixupKind::Address16 => {
+                    self.rom[fixup.position + 2] = (addr >> 8) as u8;
+                    self.rom[fixup.position + 3] = (addr & 0xFF) as u8;
+                }
+            }
+        }
+
+        Ok(Assembly {
+            bytes: self.rom,
+            labels: self.labels,
+            lines: self.lines,
+            breakpoints: self.breakpoints,
+        })
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), AsmError> {
+        // raw data
+        if let Some(value) = self.number_from(&token) {
+            if !(-128..=255).contains(&value) {
+                return Err(token.error(format!("byte {} out of range (-128 to 255)", value)));
+            }
+            self.mark_line(&token);
+            return self.emit_byte(&token, value as u8);
+        }
+
+        if self.is_register(&token) {
+            let register = self.register_from(&token)?;
+            return self.register_statement(&token, register);
+        }
+
+        match token.text.as_str() {
+            ":" => self.label(),
+            ":alias" => self.alias(),
+            ":const" => self.constant(),
+            ":macro" => self.define_macro(),
+            ":call" => {
+                let target = self.next()?;
+                self.emit_with_address(&token, target, Instruction::Call, false)
+            },
+            ":byte" => {
+                let byte = self.byte()?;
+                self.emit_byte(&token, byte)
+            },
+            ":org" => {
+                let addr = self.value_in_range(ORIGIN as i64, 0xFFFF, "address")?;
+                self.address = addr as u16;
+                Ok(())
+            },
+            ":breakpoint" => {
+                let name = self.next()?;
+                self.breakpoints.insert(self.address, name.text);
+                Ok(())
+            },
+
+            "return" | ";" => self.emit(&token, Instruction::Ret).map(|_| ()),
+            "clear" => self.emit(&token, Instruction::Cls).map(|_| ()),
+            "exit" => self.emit(&token, Instruction::Exit).map(|_| ()),
+            "lores" => self.emit(&token, Instruction::Lores).map(|_| ()),
+            "hires" => self.emit(&token, Instruction::Hires).map(|_| ()),
+            "scroll-left" => self.emit(&token, Instruction::ScrollLeft).map(|_| ()),
+            "scroll-right" => self.emit(&token, Instruction::ScrollRight).map(|_| ()),
+            "audio" => self.emit(&token, Instruction::Audio).map(|_| ()),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(&token, Instruction::ScrollDown(n)).map(|_| ())
+            },
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(&token, Instruction::ScrollUp(n)).map(|_| ())
+            },
+            "plane" => {
+                let n = self.value_in_range(0, 3, "plane")? as u8;
+                self.emit(&token, Instruction::Plane(n)).map(|_| ())
+            },
+            "jump" => {
+                let target = self.next()?;
+                self.emit_with_address(&token, target, Instruction::Jump, false)
+            },
+            "jump0" => {
+                let target = self.next()?;
+                self.emit_with_address(&token, target, |nnn| Instruction::JumpOffset((nnn >> 8) as u8, nnn), false)
+            },
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(&token, Instruction::Draw(x, y, n)).map(|_| ())
+            },
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(&token, Instruction::Bcd(x)).map(|_| ())
+            },
+            "save" | "load" => self.save_load(&token),
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(&token, Instruction::SaveFlags(x)).map(|_| ())
+            },
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(&token, Instruction::LoadFlags(x)).map(|_| ())
+            },
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                let instruction = match token.text.as_str() {
+                    "delay" => Instruction::SetDelay(x),
+                    "buzzer" => Instruction::SetSound(x),
+                    _ => Instruction::Pitch(x),
+                };
+                self.emit(&token, instruction).map(|_| ())
+            },
+            "i" => self.i_statement(&token),
+
+            "if" => self.if_statement(&token),
+            "else" => self.else_statement(&token),
+            "end" => self.end_statement(&token),
+            "loop" => {
+                self.blocks.push((Block::Loop { start: self.address, whiles: Vec::new() }, token));
+                Ok(())
+            },
+            "while" => self.while_statement(&token),
+            "again" => self.again_statement(&token),
+
+            _ => {
+                if self.macros.contains_key(&token.text) {
+                    return self.expand_macro(&token);
+                }
+                if token.text.starts_with(':') {
+                    return Err(token.error(format!("unsupported directive '{}'", token.text)));
+                }
+                if KEYWORDS.contains(&token.text.as_str()) {
+                    return Err(token.error(format!("unexpected '{}'", token.text)));
+                }
+                // a bare name calls a subroutine
+                self.emit_with_address(&token, token.clone(), Instruction::Call, false)
+            }
+        }
+    }
+
+    // DIRECTIVES
+
+    fn label(&mut self) -> Result<(), AsmError> {
+        let name = self.identifier("a label")?;
+        if self.labels.contains_key(&name.text) || self.constants.contains_key(&name.text) {
+            return Err(name.error(format!("'{}' is already defined", name.text)));
+        }
+        self.labels.insert(name.text, self.address);
+        Ok(())
+    }
+
+    fn alias(&mut self) -> Result<(), AsmError> {
+        let name = self.identifier("an alias")?;
+        let register = self.register()?;
+        self.aliases.insert(name.text, register);
+        Ok(())
+    }
+
+    fn constant(&mut self) -> Result<(), AsmError> {
+        let name = self.identifier("a constant")?;
+        let value_token = self.next()?;
+        let value = self.number_from(&value_token)
+            .or_else(|| self.labels.get(&value_token.text).map(|addr| *addr as i64))
+            .ok_or_else(|| value_token.error(format!("expected a number, found '{}'", value_token.text)))?;
+        self.constants.insert(name.text, value);
+        Ok(())
+    }
+
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.identifier("a macro name")?;
+
+        let mut arguments = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token.text == "{" {
+                break;
+            }
+            arguments.push(token.text);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self.next()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                },
+                _ => {},
+            }
+            body.push(token);
+        }
+
+        self.macros.insert(name.text, Macro { arguments, body });
+        Ok(())
+    }
+
+    fn expand_macro(&mut self, token: &Token) -> Result<(), AsmError> {
+        self.macro_expansions += 1;
+        if self.macro_expansions > MAX_MACRO_EXPANSIONS {
+            return Err(token.error(format!("macro '{}' expands forever", token.text)));
+        }
+
+        let count = self.macros[&token.text].arguments.len();
+        let mut values = Vec::new();
+        for _ in 0..count {
+            values.push(self.next()?);
+        }
+
+        let definition = &self.macros[&token.text];
+        let expanded: Vec<Token> = definition.body.iter().map(|body_token| {
+            match definition.arguments.iter().position(|argument| *argument == body_token.text) {
+                Some(index) => Token { text: values[index].text.clone(), ..body_token.clone() },
+                None => body_token.clone(),
+            }
+        }).collect();
+
+        // statements from the macro body are assembled by the main loop
+        for expanded_token in expanded.into_iter().rev() {
+            self.tokens.push_front(expanded_token);
+        }
+        Ok(())
+    }
+
+    // STATEMENTS
+
+    fn register_statement(&mut self, token: &Token, x: u8) -> Result<(), AsmError> {
+        let operator = self.next()?;
+
+        let instruction = match operator.text.as_str() {
+            ":=" => {
+                let source = self.next()?;
+                match source.text.as_str() {
+                    "random" => Instruction::Random(x, self.byte()?),
+                    "key" => Instruction::WaitKey(x),
+                    "delay" => Instruction::GetDelay(x),
+                    _ if self.is_register(&source) => Instruction::Move(x, self.register_from(&source)?),
+                    _ => Instruction::LoadImm(x, self.byte_from(&source)?),
+                }
+            },
+            "+=" => {
+                let source = self.next()?;
+                if self.is_register(&source) {
+                    Instruction::Add(x, self.register_from(&source)?)
+                } else {
+                    Instruction::AddImm(x, self.byte_from(&source)?)
+                }
+            },
+            "-=" => {
+                let source = self.next()?;
+                if self.is_register(&source) {
+                    Instruction::Sub(x, self.register_from(&source)?)
+                } else {
+                    // there is no subtract immediate, add the negative instead
+                    Instruction::AddImm(x, self.byte_from(&source)?.wrapping_neg())
+                }
+            },
+            "=-" => Instruction::SubReverse(x, self.register()?),
+            "|=" => Instruction::Or(x, self.register()?),
+            "&=" => Instruction::And(x, self.register()?),
+            "^=" => Instruction::Xor(x, self.register()?),
+            ">>=" => Instruction::ShiftRight(x, self.register()?),
+            "<<=" => Instruction::ShiftLeft(x, self.register()?),
+            _ => return Err(operator.error(format!("expected an operator after register, found '{}'", operator.text))),
+        };
+
+        self.emit(token, instruction).map(|_| ())
+    }
+
+    fn byte_from(&self, token: &Token) -> Result<u8, AsmError> {
+        match self.number_from(token) {
+            Some(value) if (-128..=255).contains(&value) => Ok(value as u8),
+            Some(value) => Err(token.error(format!("byte {} out of range (-128 to 255)", value))),
+            None => Err(token.error(format!("expected a byte, found '{}'", token.text))),
+        }
+    }
+
+    fn save_load(&mut self, token: &Token) -> Result<(), AsmError> {
+        let x = self.register()?;
+        let save = token.text == "save";
+
+        // XO-CHIP register ranges
+        if self.peek_is("-") {
+            self.next()?;
+            let y = self.register()?;
+            let instruction = if save { Instruction::SaveRange(x, y) } else { Instruction::LoadRange(x, y) };
+            return self.emit(token, instruction).map(|_| ());
+        }
+
+        let instruction = if save { Instruction::Store(x) } else { Instruction::Load(x) };
+        self.emit(token, instruction).map(|_| ())
+    }
+
+    fn i_statement(&mut self, token: &Token) -> Result<(), AsmError> {
+        let operator = self.next()?;
+        match operator.text.as_str() {
+            "+=" => {
+                let x = self.register()?;
+                self.emit(token, Instruction::AddI(x)).map(|_| ())
+            },
+            ":=" => {
+                let source = self.next()?;
+                match source.text.as_str() {
+                    "hex" => {
+                        let x = self.register()?;
+                        self.emit(token, Instruction::Font(x)).map(|_| ())
+                    },
+                    "bighex" => {
+                        let x = self.register()?;
+                        self.emit(token, Instruction::BigFont(x)).map(|_| ())
+                    },
+                    "long" => {
+                        let target = self.next()?;
+                        self.emit_with_address(token, target, Instruction::LoadLongI, true)
+                    },
+                    _ => self.emit_with_address(token, source, Instruction::LoadI, false),
+                }
+            },
+            _ => Err(operator.error(format!("expected ':=' or '+=' after i, found '{}'", operator.text))),
+        }
+    }
+
+    // CONTROL FLOW
+
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let register = self.register()?;
+        let operator = self.next()?;
+
+        let compare = match operator.text.as_str() {
+            "key" => return Ok(Condition { register, compare: Compare::Key, operand: Operand::Byte(0) }),
+            "-key" => return Ok(Condition { register, compare: Compare::NotKey, operand: Operand::Byte(0) }),
+            "==" => Compare::Eq,
+            "!=" => Compare::Ne,
+            "<" => Compare::Lt,
+            ">" => Compare::Gt,
+            "<=" => Compare::Le,
+            ">=" => Compare::Ge,
+            _ => return Err(operator.error(format!("expected a comparison, found '{}'", operator.text))),
+        };
+
+        let operand_token = self.next()?;
+        let operand = if self.is_register(&operand_token) {
+            Operand::Register(self.register_from(&operand_token)?)
+        } else {
+            Operand::Byte(self.byte_from(&operand_token)?)
+        };
+
+        Ok(Condition { register, compare, operand })
+    }
+
+    // emits the instructions that skip the next one if the condition is false
+    fn emit_condition(&mut self, token: &Token, condition: Condition) -> Result<(), AsmError> {
+        let x = condition.register;
+
+        let skip = match (condition.compare, condition.operand) {
+            (Compare::Eq, Operand::Byte(nn)) => Instruction::SkipNeImm(x, nn),
+            (Compare::Ne, Operand::Byte(nn)) => Instruction::SkipEqImm(x, nn),
+            (Compare::Eq, Operand::Register(y)) => Instruction::SkipNeReg(x, y),
+            (Compare::Ne, Operand::Register(y)) => Instruction::SkipEqReg(x, y),
+            (Compare::Key, _) => Instruction::SkipNotKey(x),
+            (Compare::NotKey, _) => Instruction::SkipKey(x),
+            // ordering goes through VF like in Octo:
+            // vf := b, then either vf -= x (VF = b >= x) or vf =- x (VF = x >= b)
+            (compare, operand) => {
+                let load = match operand {
+                    Operand::Register(y) => Instruction::Move(0xF, y),
+                    Operand::Byte(nn) => Instruction::LoadImm(0xF, nn),
+                };
+                self.emit(token, load)?;
+
+                let (subtract, skip_value) = match compare {
+                    Compare::Gt => (Instruction::Sub(0xF, x), 1),
+                    Compare::Lt => (Instruction::SubReverse(0xF, x), 1),
+                    Compare::Ge => (Instruction::SubReverse(0xF, x), 0),
+                    _ => (Instruction::Sub(0xF, x), 0),
+                };
+                self.emit(token, subtract)?;
+                Instruction::SkipEqImm(0xF, skip_value)
+            }
+        };
+
+        self.emit(token, skip).map(|_| ())
+    }
+
+    fn if_statement(&mut self, token: &Token) -> Result<(), AsmError> {
+        let condition = self.condition()?;
+        let keyword = self.next()?;
+
+        match keyword.text.as_str() {
+            "then" => {
+                // the skip needs a statement to skip
+                if self.tokens.is_empty() {
+                    return Err(keyword.error("'then' needs a statement after it".to_string()));
+                }
+                self.emit_condition(token, condition)
+            },
+            "begin" => {
+                // jump to else / end if the condition is false
+                let negated = Condition { compare: condition.compare.negate(), ..condition };
+                self.emit_condition(token, negated)?;
+                let jump = self.emit(token, Instruction::Jump(0))?;
+                self.blocks.push((Block::If { jump }, keyword));
+                Ok(())
+            },
+            _ => Err(keyword.error(format!("expected 'then' or 'begin', found '{}'", keyword.text))),
+        }
+    }
+
+    fn else_statement(&mut self, token: &Token) -> Result<(), AsmError> {
+        let Some((Block::If { jump }, _)) = self.blocks.pop() else {
+            return Err(token.error("'else' without 'if ... begin'".to_string()));
+        };
+        let else_jump = self.emit(token, Instruction::Jump(0))?;
+        self.patch_jump(jump, self.address);
+        self.blocks.push((Block::Else { jump: else_jump }, token.clone()));
+        Ok(())
+    }
+
+    fn end_statement(&mut self, token: &Token) -> Result<(), AsmError> {
+        match self.blocks.pop() {
+            Some((Block::If { jump }, _)) | Some((Block::Else { jump }, _)) => {
+                self.patch_jump(jump, self.address);
+                Ok(())
+            },
+            _ => Err(token.error("'end' without 'if ... begin'".to_string())),
+        }
+    }
+
+    fn while_statement(&mut self, token: &Token) -> Result<(), AsmError> {
+        let condition = self.condition()?;
+        let negated = Condition { compare: condition.compare.negate(), ..condition };
+        self.emit_condition(token, negated)?;
+        let jump = self.emit(token, Instruction::Jump(0))?;
+
+        let innermost_loop = self.blocks.iter_mut().rev().find_map(|(block, _)| match block {
+            Block::Loop { whiles, .. } => Some(whiles),
+            _ => None,
+        });
+        match innermost_loop {
+            Some(whiles) => {
+                whiles.push(jump);
+                Ok(())
+            },
+            None => Err(token.error("'while' outside of 'loop'".to_string())),
+        }
+    }
+
+    fn again_statement(&mut self, token: &Token) -> Result<(), AsmError> {
+        let Some((Block::Loop { start, whiles }, _)) = self.blocks.pop() else {
+            return Err(token.error("'again' without 'loop'".to_string()));
+        };
+        self.emit(token, Instruction::Jump(start))?;
+        for jump in whiles {
+            self.patch_jump(jump, self.address);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+// Tests for the Octo assembler: the bytes of every construct, error
+// positions, and disassembling and assembling the bundled ROMs.
+
+use std::fs;
+
+use crate::*;
+
+fn bytes(source: &str) -> Vec<u8> {
+    asm::assemble(source).unwrap_or_else(|error| panic!("{}: {}", source, error)).bytes
+}
+
+fn error(source: &str) -> String {
+    match asm::assemble(source) {
+        Ok(_) => panic!("{} assembled", source),
+        Err(error) => error.to_string(),
+    }
+}
+
+type Comparison = fn(u8, u8) -> bool;
+
+// runs the assembled program until it reaches its end
+fn run(source: &str) -> Emu {
+    let rom = bytes(source);
+    let mut emu = Emu::new();
+    emu.load(&rom).unwrap();
+    let end = START_ADDR + rom.len() as u16;
+    for _ in 0..100 {
+        if emu.programm_counter >= end {
+            break;
+        }
+        emu.tick().unwrap();
+    }
+    emu
+}
+
+// STATEMENTS
+
+#[test]
+fn assembles_statements() {
+    assert_eq!(bytes("v0 := 1 v1 += 2 v2 := v1 v3 := random 0x0F i := 0x300 sprite v0 v1 5 return"), [
+        0x60, 0x01, 0x71, 0x02, 0x82, 0x10, 0xC3, 0x0F, 0xA3, 0x00, 0xD0, 0x15, 0x00, 0xEE,
+    ]);
+    assert_eq!(bytes("v4 -= 1 v4 -= v5 v4 =- v5 v6 := key i := hex v6 save v6 load v2 - v3"), [
+        0x74, 0xFF, 0x84, 0x55, 0x84, 0x57, 0xF6, 0x0A, 0xF6, 0x29, 0xF6, 0x55, 0x52, 0x33,
+    ]);
+    assert_eq!(bytes("i := long 0x1234 0xFF -1"), [0xF0, 0x00, 0x12, 0x34, 0xFF, 0xFF]);
+}
+
+#[test]
+fn assembles_labels_and_main() {
+    // main isn't first, 0x200 jumps to it
+    assert_eq!(bytes(": data 1 2 : main i := data jump main"), [0x12, 0x04, 0x01, 0x02, 0xA2, 0x02, 0x12, 0x04]);
+    // a bare name calls, labels can be used before they are defined
+    assert_eq!(bytes(": main draw ; : draw return"), [0x22, 0x04, 0x00, 0xEE, 0x00, 0xEE]);
+}
+
+#[test]
+fn assembles_aliases_and_constants() {
+    assert_eq!(bytes(":alias x v3 :const speed 4 x := speed x += speed vf := x"), [0x63, 0x04, 0x73, 0x04, 0x8F, 0x30]);
+}
+
+#[test]
+fn assembles_macros() {
+    assert_eq!(bytes(":macro twice reg { reg += 1 reg += 1 } twice v2 twice v3"), [0x72, 0x01, 0x72, 0x01, 0x73, 0x01, 0x73, 0x01]);
+    // macros can use other macros
+    assert_eq!(bytes(":macro inc reg { reg += 1 } :macro both { inc v0 inc v1 } both"), [0x70, 0x01, 0x71, 0x01]);
+}
+
+// CONTROL FLOW
+
+#[test]
+fn assembles_loops() {
+    // 200 v0 += 1, 202 skip if v0 != 5, 204 jump to the end, 206 jump back
+    assert_eq!(bytes("loop v0 += 1 while v0 != 5 again"), [0x70, 0x01, 0x40, 0x05, 0x12, 0x08, 0x12, 0x00]);
+    assert_eq!(run("loop v0 += 1 while v0 != 5 again").registers[0], 5);
+}
+
+#[test]
+fn assembles_if_then() {
+    assert_eq!(bytes("if v0 == 1 then v1 := 2"), [0x40, 0x01, 0x61, 0x02]);
+    assert_eq!(bytes("if v0 != v2 then v1 := 2"), [0x50, 0x20, 0x61, 0x02]);
+    assert_eq!(bytes("if v0 -key then v1 := 2"), [0xE0, 0x9E, 0x61, 0x02]);
+    // ordering goes through VF
+    assert_eq!(bytes("if v0 > 3 then v1 := 2"), [0x6F, 0x03, 0x8F, 0x05, 0x3F, 0x01, 0x61, 0x02]);
+}
+
+#[test]
+fn assembles_if_begin_else_end() {
+    // 200 skip if a key is pressed, 202 jump to else, 204 then, 206 jump to the end, 208 else
+    assert_eq!(bytes("if v0 key begin v1 := 1 else v1 := 2 end"), [0xE0, 0x9E, 0x12, 0x08, 0x61, 0x01, 0x12, 0x0A, 0x61, 0x02]);
+    assert_eq!(bytes("if v0 == 1 begin v1 := 1 end"), [0x30, 0x01, 0x12, 0x06, 0x61, 0x01]);
+}
+
+#[test]
+fn comparisons_match_their_meaning() {
+    let comparisons: [(&str, Comparison); 6] = [
+        ("==", |a, b| a == b), ("!=", |a, b| a != b), ("<", |a, b| a < b),
+        (">", |a, b| a > b), ("<=", |a, b| a <= b), (">=", |a, b| a >= b),
+    ];
+
+    for (operator, expected) in comparisons {
+        for value in 2..=4 {
+            let then = run(&format!("v0 := {} if v0 {} 3 then v1 := 1", value, operator));
+            let begin = run(&format!("v0 := {} v2 := 3 if v0 {} v2 begin v1 := 1 else v1 := 2 end", value, operator));
+            let name = format!("{} {} 3", value, operator);
+            assert_eq!(then.registers[1] == 1, expected(value, 3), "then, {}", name);
+            assert_eq!(begin.registers[1], if expected(value, 3) { 1 } else { 2 }, "begin, {}", name);
+        }
+    }
+}
+
+// ERRORS
+
+#[test]
+fn reports_error_positions() {
+    assert_eq!(error("v0 := 1\n  v1 += key"), "2:9: expected a byte, found 'key'");
+    assert_eq!(error(": main\n  jump nowhere"), "2:8: undefined name 'nowhere'");
+    assert_eq!(error("v0 := 256"), "1:7: byte 256 out of range (-128 to 255)");
+    assert_eq!(error("loop\n  v0 += 1"), "1:1: 'loop' is never closed");
+    assert_eq!(error("else"), "1:1: 'else' without 'if ... begin'");
+    assert_eq!(error("v0 :="), "1:6: unexpected end of file");
+    assert_eq!(error(":macro forever { forever } forever"), "1:18: macro 'forever' expands forever");
+}
+
+#[test]
+fn main_in_an_unused_macro() {
+    assert_eq!(error(":macro unused {\n: main\n}\nclear\n"), "2:3: 'main' is never defined, is it in an unused macro?");
+    // used, the jump goes to it
+    assert_eq!(bytes(":macro start { : main } clear start clear"), [0x12, 0x04, 0x00, 0xE0, 0x00, 0xE0]);
+}
+
+#[test]
+fn if_then_needs_a_statement() {
+    assert_eq!(error("v0 := 1\nif v0 == 1 then"), "2:12: 'then' needs a statement after it");
+    assert_eq!(error("if v0 == 1 then # nothing left"), "1:12: 'then' needs a statement after it");
+}
+
+// ROUND TRIP
+
+#[test]
+fn disassembled_roms_assemble_to_the_same_bytes() {
+    let dir = format!("{}/../roms", env!("CARGO_MANIFEST_DIR"));
+    let mut count = 0;
+
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        if !path.is_file() {
+            continue;
+        }
+        let rom = fs::read(&path).unwrap();
+        let source = disasm::disassemble(&rom).to_octo();
+        let assembly = asm::assemble(&source).unwrap_or_else(|error| panic!("{}: {}", path.display(), error));
+        assert!(assembly.bytes == rom, "{} differs after the round trip", path.display());
+        count += 1;
+    }
+
+    assert!(count > 0, "no ROMs in {}", dir);
+}
@@ -0,0 +1,43 @@
+use std::env;
+use std::fs;
+use std::process;
+
+use chip8_core::asm;
+
+const USAGE: &str = "usage: asm <source_path> [-o <output_path>]";
+
+fn main() {
+    let arguments: Vec<String> = env::args().skip(1).collect();
+
+    let (source_path, output_path) = match &arguments[..] {
+        [source] => (source, None),
+        [source, flag, output] if flag == "-o" => (source, Some(output.clone())),
+        _ => {
+            println!("ERROR: invalid args!\n{}", USAGE);
+            process::exit(1);
+        }
+    };
+
+    let source = fs::read_to_string(source_path).unwrap_or_else(|error| {
+        println!("ERROR: Unable to read [{}]: {}", source_path, error);
+        process::exit(1);
+    });
+
+    let assembly = asm::assemble(&source).unwrap_or_else(|error| {
+        println!("ERROR: {}:{}", source_path, error);
+        process::exit(1);
+    });
+
+    // foo.8o -> foo.ch8
+    let output_path = output_path.unwrap_or_else(|| {
+        let stem = source_path.strip_suffix(".8o").unwrap_or(source_path);
+        format!("{}.ch8", stem)
+    });
+
+    fs::write(&output_path, &assembly.bytes).unwrap_or_else(|error| {
+        println!("ERROR: Unable to write [{}]: {}", output_path, error);
+        process::exit(1);
+    });
+
+    println!("INFO: Assembled {} bytes into [{}]", assembly.bytes.len(), output_path);
+}
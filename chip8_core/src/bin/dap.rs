@@ -0,0 +1,58 @@
+use std::env;
+use std::io;
+use std::net::TcpListener;
+use std::process;
+
+use chip8_core::dap::DapServer;
+
+const USAGE: &str = "usage: dap [--port <n>]";
+
+// stdout carries the protocol when serving over stdio, so all
+// messages go to stderr
+fn main() {
+    let arguments: Vec<String> = env::args().skip(1).collect();
+
+    let port = match &arguments[..] {
+        [] => None,
+        [flag, port] if flag == "--port" => match port.parse::<u16>() {
+            Ok(port) => Some(port),
+            Err(_) => usage_error(&format!("Invalid port [{}]", port)),
+        },
+        _ => usage_error("invalid args"),
+    };
+
+    let result = match port {
+        None => DapServer::new(io::stdin(), io::stdout()).run(),
+        Some(port) => {
+            // only local editors can connect
+            let listener = TcpListener::bind(("127.0.0.1", port)).unwrap_or_else(|error| {
+                eprintln!("ERROR: Unable to listen on port {}: {}", port, error);
+                process::exit(1);
+            });
+            eprintln!("INFO: Waiting for a debugger on 127.0.0.1:{}", port);
+
+            let (stream, address) = listener.accept().unwrap_or_else(|error| {
+                eprintln!("ERROR: Connection failed: {}", error);
+                process::exit(1);
+            });
+            eprintln!("INFO: Debugger connected from {}", address);
+            let _ = stream.set_nodelay(true);
+
+            let input = stream.try_clone().unwrap_or_else(|error| {
+                eprintln!("ERROR: Connection failed: {}", error);
+                process::exit(1);
+            });
+            DapServer::new(input, stream).run()
+        }
+    };
+
+    if let Err(error) = result {
+        eprintln!("ERROR: Connection lost: {}", error);
+        process::exit(1);
+    }
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("ERROR: {}!\n{}", message, USAGE);
+    process::exit(1);
+}
@@ -0,0 +1,74 @@
+use std::env;
+use std::fs;
+use std::process;
+
+use chip8_core::disasm;
+use chip8_core::Symbols;
+
+const USAGE: &str = "usage: disasm <rom_path> [-o <output_path>] [--symbols <path>]";
+
+fn main() {
+    let arguments: Vec<String> = env::args().skip(1).collect();
+
+    let mut rom_path = None;
+    let mut output_path = None;
+    let mut symbol_path = None;
+
+    let mut iter = arguments.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" => output_path = Some(expect_value(iter.next())),
+            "--symbols" => symbol_path = Some(expect_value(iter.next())),
+            _ if rom_path.is_none() && !arg.starts_with('-') => rom_path = Some(arg),
+            _ => {
+                println!("ERROR: invalid args!\n{}", USAGE);
+                process::exit(1);
+            }
+        }
+    }
+
+    let Some(rom_path) = rom_path else {
+        println!("ERROR: invalid args!\n{}", USAGE);
+        process::exit(1);
+    };
+
+    let rom = fs::read(&rom_path).unwrap_or_else(|error| {
+        println!("ERROR: Unable to read [{}]: {}", rom_path, error);
+        process::exit(1);
+    });
+
+    let mut disassembly = disasm::disassemble(&rom);
+
+    // a symbol file next to the ROM is used too, unless it is broken
+    match (symbol_path, Symbols::find_next_to(&rom_path)) {
+        (Some(path), _) => {
+            let symbols = Symbols::load(&path).unwrap_or_else(|error| {
+                println!("ERROR: Symbols [{}] could not be loaded: {}", path, error);
+                process::exit(1);
+            });
+            disassembly.apply_symbols(&symbols);
+        },
+        (None, Some(path)) => match Symbols::load(&path) {
+            Ok(symbols) => disassembly.apply_symbols(&symbols),
+            Err(error) => eprintln!("INFO: Ignoring symbols [{}]: {}", path, error),
+        },
+        (None, None) => {},
+    }
+
+    let source = disassembly.to_octo();
+
+    match output_path {
+        Some(path) => fs::write(&path, source).unwrap_or_else(|error| {
+            println!("ERROR: Unable to write [{}]: {}", path, error);
+            process::exit(1);
+        }),
+        None => print!("{}", source),
+    }
+}
+
+fn expect_value(value: Option<String>) -> String {
+    value.unwrap_or_else(|| {
+        println!("ERROR: invalid args!\n{}", USAGE);
+        process::exit(1);
+    })
+}
@@ -0,0 +1,89 @@
+use std::env;
+use std::fs;
+use std::net::TcpListener;
+use std::process;
+
+use chip8_core::*;
+use chip8_core::gdb::GdbStub;
+
+const USAGE: &str = "usage: gdbserver <rom_path> [--port <n>] [--cycles <n>] [--quirks <preset>] [--seed <n>] [--unthrottled]";
+
+const DEFAULT_PORT: u16 = 1234;
+
+fn main() {
+    let mut port = DEFAULT_PORT;
+    let mut cycles_per_frame = 10;
+    let mut quirks = Quirks::default();
+    let mut seed = None;
+    let mut unthrottled = false;
+    let mut rom_path = None;
+
+    let mut iter = env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().unwrap_or_else(|| usage_error(&format!("Missing value for [{}]", arg)));
+
+        match arg.as_str() {
+            "--port" => port = parse_number(&value()),
+            "--cycles" => cycles_per_frame = parse_number(&value()),
+            "--seed" => seed = Some(parse_number(&value())),
+            "--quirks" => {
+                let name = value();
+                quirks = Quirks::from_name(&name).unwrap_or_else(|| {
+                    usage_error(&format!("Unknown quirk preset [{}], valid presets: {}", name, QUIRK_PRESETS.join(", ")))
+                });
+            },
+            "--unthrottled" => unthrottled = true,
+            _ if arg.starts_with("--") => usage_error(&format!("Unknown option [{}]", arg)),
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => usage_error("invalid args"),
+        }
+    }
+    let rom_path = rom_path.unwrap_or_else(|| usage_error("invalid args"));
+
+    let rom = fs::read(&rom_path).unwrap_or_else(|error| {
+        println!("ERROR: Unable to read [{}]: {}", rom_path, error);
+        process::exit(1);
+    });
+
+    let mut emulator = Emu::new();
+    emulator.quirks = quirks;
+    if let Some(seed) = seed {
+        emulator.set_seed(seed);
+    }
+    if let Err(error) = emulator.load(&rom) {
+        println!("ERROR: ROM could not be loaded: {}", error);
+        process::exit(1);
+    }
+
+    // only local front-ends can connect
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap_or_else(|error| {
+        println!("ERROR: Unable to listen on port {}: {}", port, error);
+        process::exit(1);
+    });
+    println!("INFO: Waiting for a debugger on 127.0.0.1:{}", port);
+
+    let (stream, address) = listener.accept().unwrap_or_else(|error| {
+        println!("ERROR: Connection failed: {}", error);
+        process::exit(1);
+    });
+    println!("INFO: Debugger connected from {}", address);
+
+    let mut stub = GdbStub::new(stream, cycles_per_frame);
+    stub.unthrottled = unthrottled;
+    match stub.run(&mut emulator) {
+        Ok(()) => println!("INFO: Debugger detached"),
+        Err(error) => {
+            println!("ERROR: Connection lost: {}", error);
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(text: &str) -> T {
+    text.parse().unwrap_or_else(|_| usage_error(&format!("Invalid number [{}]", text)))
+}
+
+fn usage_error(message: &str) -> ! {
+    println!("ERROR: {}!\n{}", message, USAGE);
+    process::exit(1);
+}
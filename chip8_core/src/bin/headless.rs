@@ -0,0 +1,286 @@
+use std::env;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::process;
+
+use chip8_core::*;
+
+const USAGE: &str = "usage: headless <rom_path> [--frames <n>] [--cycles <n>] [--quirks <preset>] [--seed <n>]
+                [--press <key>@<frame>[-<frame>]]... [--movie <movie>]
+                [--trace <path>] [--trace-range <addr>[-<addr>]] [--trace-frames <frame>[-<frame>]] [--symbols <path>]
+                [--profile <path>] [--profile-stacks <path>]
+                [--ascii <path|->] [--png <path>] [--scale <n>] [--expect <golden.txt>]";
+
+// exit status
+const EXIT_OK: i32 = 0;
+const EXIT_USAGE: i32 = 1;      // invalid arguments or unreadable files
+const EXIT_FAULT: i32 = 2;      // the ROM crashed the emulator
+const EXIT_MISMATCH: i32 = 3;   // the framebuffer differs from --expect
+const EXIT_DESYNC: i32 = 4;     // the run went out of sync with --movie
+
+// key held from the first to the last frame, both included
+struct KeyPress {
+    key: usize,
+    first: u32,
+    last: u32,
+}
+
+struct Options {
+    rom_path: String,
+    frames: Option<u32>,    // the length of the movie or a second if not given
+    cycles_per_frame: u32,
+    quirks: Quirks,
+    seed: u64,
+    presses: Vec<KeyPress>,
+    movie: Option<String>,
+    ascii: Option<String>,
+    png: Option<String>,
+    scale: usize,
+    expect: Option<String>,
+    trace: Option<String>,
+    trace_range: Option<RangeInclusive<u16>>,
+    trace_frames: Option<RangeInclusive<u64>>,
+    symbols: Option<String>,
+    profile: Option<String>,
+    profile_stacks: Option<String>,
+}
+
+fn main() {
+    let options = parse_args();
+
+    let rom = fs::read(&options.rom_path).unwrap_or_else(|error| {
+        println!("ERROR: Unable to read [{}]: {}", options.rom_path, error);
+        process::exit(EXIT_USAGE);
+    });
+
+    let mut emulator = Emu::new();
+    emulator.quirks = options.quirks;
+    emulator.set_seed(options.seed);
+    if let Err(error) = emulator.load(&rom) {
+        println!("ERROR: ROM could not be loaded: {}", error);
+        process::exit(EXIT_FAULT);
+    }
+
+    // labels for the trace, a broken file next to the ROM is skipped
+    match (&options.symbols, Symbols::find_next_to(&options.rom_path)) {
+        (Some(path), _) => {
+            emulator.debugger.symbols = Symbols::load(path).unwrap_or_else(|error| {
+                println!("ERROR: Symbols [{}] could not be loaded: {}", path, error);
+                process::exit(EXIT_USAGE);
+            });
+        },
+        (None, Some(path)) => match Symbols::load(&path) {
+            Ok(symbols) => emulator.debugger.symbols = symbols,
+            Err(error) => println!("INFO: Ignoring symbols [{}]: {}", path, error),
+        },
+        (None, None) => {},
+    }
+
+    // a movie brings its own settings and input
+    let movie = options.movie.as_ref().map(|path| {
+        let text = fs::read_to_string(path).unwrap_or_else(|error| {
+            println!("ERROR: Unable to read [{}]: {}", path, error);
+            process::exit(EXIT_USAGE);
+        });
+        let movie = Movie::from_text(&text).and_then(|movie| movie.apply(&mut emulator).map(|_| movie));
+        movie.unwrap_or_else(|error| {
+            println!("ERROR: Movie [{}] can't be played: {}", path, error);
+            process::exit(EXIT_USAGE);
+        })
+    });
+    let cycles_per_frame = movie.as_ref().map_or(options.cycles_per_frame, |movie| movie.cycles_per_frame);
+    let frames = options.frames
+        .or(movie.as_ref().map(|movie| movie.frames.len() as u32))
+        .unwrap_or(FRAMES_PER_SECOND);
+
+    if let Some(path) = &options.trace {
+        let mut tracer = Tracer::to_file(path).unwrap_or_else(|error| {
+            println!("ERROR: Unable to write [{}]: {}", path, error);
+            process::exit(EXIT_USAGE);
+        });
+        tracer.addresses = options.trace_range.clone();
+        tracer.frames = options.trace_frames.clone();
+        emulator.tracer = Some(tracer);
+    }
+
+    if options.profile.is_some() || options.profile_stacks.is_some() {
+        emulator.profiler = Some(Profiler::new());
+    }
+
+    let mut status = EXIT_OK;
+
+    for frame in 0..frames {
+        match &movie {
+            Some(movie) => {
+                movie.press_keys(frame as usize, &mut emulator);
+            },
+            None => {
+                for key in 0..NUM_KEYS {
+                    let pressed = options.presses.iter()
+                        .any(|press| press.key == key && (press.first..=press.last).contains(&frame));
+                    emulator.keypress(key, pressed);
+                }
+            }
+        }
+
+        if let Err(error) = emulator.run_frame(cycles_per_frame) {
+            println!("ERROR: Emulator fault in frame {}: {}", frame, error);
+            status = EXIT_FAULT;
+            break;
+        }
+
+        if let Some(movie) = &movie {
+            if let Err(error) = movie.verify(frame as usize, &emulator) {
+                println!("ERROR: {}", error);
+                status = EXIT_DESYNC;
+                break;
+            }
+        }
+
+        if emulator.has_exited {
+            break;
+        }
+    }
+
+    if let (Some(tracer), Some(path)) = (emulator.tracer.take(), &options.trace) {
+        if let Err(error) = tracer.finish() {
+            println!("ERROR: Unable to write [{}]: {}", path, error);
+            process::exit(EXIT_USAGE);
+        }
+    }
+
+    if let Some(profiler) = emulator.profiler.take() {
+        if let Some(path) = &options.profile {
+            write_file(path, profiler.report(&emulator).as_bytes());
+        }
+        if let Some(path) = &options.profile_stacks {
+            write_file(path, profiler.collapsed_stacks(&emulator.debugger.symbols).as_bytes());
+        }
+    }
+
+    let ascii = screenshot::to_ascii(&emulator);
+
+    match options.ascii.as_deref() {
+        Some("-") => print!("{}", ascii),
+        Some(path) => write_file(path, ascii.as_bytes()),
+        None => {},
+    }
+    if let Some(path) = &options.png {
+        write_file(path, &screenshot::to_png(&emulator, options.scale));
+    }
+
+    if let Some(path) = &options.expect {
+        let golden = fs::read_to_string(path).unwrap_or_else(|error| {
+            println!("ERROR: Unable to read [{}]: {}", path, error);
+            process::exit(EXIT_USAGE);
+        });
+        if golden.trim_end() != ascii.trim_end() {
+            println!("ERROR: Framebuffer differs from [{}]:\n{}", path, ascii);
+            if status == EXIT_OK {
+                status = EXIT_MISMATCH;
+            }
+        }
+    }
+
+    process::exit(status);
+}
+
+fn write_file(path: &str, data: &[u8]) {
+    fs::write(path, data).unwrap_or_else(|error| {
+        println!("ERROR: Unable to write [{}]: {}", path, error);
+        process::exit(EXIT_USAGE);
+    });
+}
+
+fn parse_args() -> Options {
+    let mut options = Options {
+        rom_path: String::new(),
+        frames: None,
+        cycles_per_frame: 10,
+        quirks: Quirks::default(),
+        seed: 0,
+        presses: Vec::new(),
+        movie: None,
+        ascii: None,
+        png: None,
+        scale: 1,
+        expect: None,
+        trace: None,
+        trace_range: None,
+        trace_frames: None,
+        symbols: None,
+        profile: None,
+        profile_stacks: None,
+    };
+    let mut rom_path = None;
+
+    let mut iter = env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().unwrap_or_else(|| usage_error(&format!("Missing value for [{}]", arg)));
+
+        match arg.as_str() {
+            "--frames" => options.frames = Some(parse_number(&value())),
+            "--cycles" => options.cycles_per_frame = parse_number(&value()),
+            "--seed" => options.seed = parse_number(&value()),
+            "--scale" => options.scale = parse_number(&value()),
+            "--quirks" => {
+                let name = value();
+                options.quirks = Quirks::from_name(&name).unwrap_or_else(|| {
+                    usage_error(&format!("Unknown quirk preset [{}], valid presets: {}", name, QUIRK_PRESETS.join(", ")))
+                });
+            },
+            "--press" => options.presses.push(parse_press(&value())),
+            "--movie" => options.movie = Some(value()),
+            "--ascii" => options.ascii = Some(value()),
+            "--png" => options.png = Some(value()),
+            "--expect" => options.expect = Some(value()),
+            "--trace" => options.trace = Some(value()),
+            "--symbols" => options.symbols = Some(value()),
+            "--profile" => options.profile = Some(value()),
+            "--profile-stacks" => options.profile_stacks = Some(value()),
+            "--trace-range" => {
+                let text = value();
+                options.trace_range = Some(parse_address_range(&text)
+                    .unwrap_or_else(|| usage_error(&format!("Invalid address range [{}]", text))));
+            },
+            "--trace-frames" => {
+                let text = value();
+                options.trace_frames = Some(parse_frame_range(&text)
+                    .unwrap_or_else(|| usage_error(&format!("Invalid frame range [{}]", text))));
+            },
+            _ if arg.starts_with("--") => usage_error(&format!("Unknown option [{}]", arg)),
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => usage_error("invalid args"),
+        }
+    }
+
+    options.rom_path = rom_path.unwrap_or_else(|| usage_error("invalid args"));
+
+    // without any output the framebuffer goes to stdout
+    if options.ascii.is_none() && options.png.is_none() && options.expect.is_none() {
+        options.ascii = Some("-".to_string());
+    }
+
+    options
+}
+
+// <key>@<frame> or <key>@<first>-<last>, the key is a hex digit
+fn parse_press(text: &str) -> KeyPress {
+    let invalid = || -> ! { usage_error(&format!("Invalid key press [{}]", text)) };
+
+    let Some((key, frames)) = text.split_once('@') else { invalid() };
+    let Some(key) = usize::from_str_radix(key, 16).ok().filter(|key| *key < NUM_KEYS) else { invalid() };
+    let (first, last) = frames.split_once('-').unwrap_or((frames, frames));
+    let (Ok(first), Ok(last)) = (first.parse::<u32>(), last.parse::<u32>()) else { invalid() };
+
+    KeyPress { key, first, last }
+}
+
+fn parse_number<T: std::str::FromStr>(text: &str) -> T {
+    text.parse().unwrap_or_else(|_| usage_error(&format!("Invalid number [{}]", text)))
+}
+
+fn usage_error(message: &str) -> ! {
+    println!("ERROR: {}!\n{}", message, USAGE);
+    process::exit(EXIT_USAGE);
+}
@@ -0,0 +1,85 @@
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::process;
+
+use chip8_core::tracediff::*;
+
+const USAGE: &str = "usage: tracediff <our_trace> <reference_trace> [--ignore-timers] [--ignore-vf] [--ignore-memory]";
+
+// exit status
+const EXIT_SAME: i32 = 0;
+const EXIT_USAGE: i32 = 1;      // invalid arguments or unreadable traces
+const EXIT_DIFFERENT: i32 = 2;  // the traces diverge or one ends early
+
+fn main() {
+    let mut options = DiffOptions::default();
+    let mut paths = Vec::new();
+
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--ignore-timers" => options.ignore_timers = true,
+            "--ignore-vf" => options.ignore_vf = true,
+            "--ignore-memory" => options.ignore_memory = true,
+            _ if arg.starts_with("--") => usage_error(&format!("Unknown option [{}]", arg)),
+            _ => paths.push(arg),
+        }
+    }
+    let [ours_path, reference_path] = &paths[..] else {
+        usage_error("invalid args");
+    };
+
+    let result = diff(open_trace(ours_path), open_trace(reference_path), options).unwrap_or_else(|error| {
+        println!("ERROR: Trace could not be read: {}", error);
+        process::exit(EXIT_USAGE);
+    });
+
+    match result {
+        DiffResult::Identical { instructions } => {
+            println!("INFO: Traces match ({} instructions)", instructions);
+            process::exit(EXIT_SAME);
+        },
+        DiffResult::Length { instructions, ours_ended } => {
+            let (ended, other) = if ours_ended { (ours_path, reference_path) } else { (reference_path, ours_path) };
+            println!("ERROR: [{}] ends after {} instructions, [{}] goes on", ended, instructions, other);
+        },
+        DiffResult::Diverged(divergence) => print_divergence(&divergence, ours_path, reference_path),
+    }
+    process::exit(EXIT_DIFFERENT);
+}
+
+fn print_divergence(divergence: &Divergence, ours_path: &str, reference_path: &str) {
+    println!("ERROR: Traces diverge at instruction {}", divergence.index);
+
+    // registers are traced before an instruction runs, a wrong result
+    // shows up one instruction later
+    if let Some((ours, reference)) = &divergence.previous {
+        println!("\nprevious instruction:");
+        println!("  {}:{}: {}", ours_path, ours.line, ours.text);
+        println!("  {}:{}: {}", reference_path, reference.line, reference.text);
+    }
+
+    println!("\ndiverging instruction:");
+    println!("  {}:{}: {}", ours_path, divergence.ours.line, divergence.ours.text);
+    println!("  {}:{}: {}", reference_path, divergence.reference.line, divergence.reference.text);
+
+    println!("\ndifferences:");
+    for difference in &divergence.differences {
+        println!("  {}", difference);
+    }
+}
+
+fn open_trace(path: &str) -> TraceReader<BufReader<File>> {
+    match File::open(path) {
+        Ok(file) => TraceReader::new(BufReader::new(file)),
+        Err(error) => {
+            println!("ERROR: Unable to read [{}]: {}", path, error);
+            process::exit(EXIT_USAGE);
+        }
+    }
+}
+
+fn usage_error(message: &str) -> ! {
+    println!("ERROR: {}!\n{}", message, USAGE);
+    process::exit(EXIT_USAGE);
+}
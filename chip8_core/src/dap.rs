@@ -0,0 +1,582 @@
+// Debug Adapter Protocol server (https://microsoft.github.io/debug-adapter-protocol/).
+// Launches an Octo source file or a ROM and lets an editor set
+// breakpoints on source lines, step and inspect the emulator. PCs are
+// mapped to lines through the line table of the assembler.
+//
+// launch arguments:
+//   program          .8o source, anything else is loaded as a ROM
+//   stopOnEntry      stop before the first instruction
+//   cyclesPerFrame   instructions per 60 Hz frame, 10 by default
+//   quirks           quirk preset, see QUIRK_PRESETS
+//   seed             seed of the random number generator
+//   symbols          symbol file, see symbols.rs. A ROM finds one next to it
+//
+// Scopes: "Registers" (V0-VF, I, PC, SP, DT, ST, writable), "Stack"
+// (return addresses, innermost first) and "Memory" (16 bytes per row).
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::asm::{self, Assembly};
+use crate::*;
+
+const THREAD_ID: i64 = 1;
+
+// variablesReference of the scopes
+const REGISTERS_REF: i64 = 1;
+const STACK_REF: i64 = 2;
+const MEMORY_REF: i64 = 3;
+
+const MEMORY_ROW: usize = 16;
+
+// step over / step out give up after this many instructions
+const MAX_STEP_CYCLES: u64 = 1_000_000;
+// instructions a source line step runs at most
+const MAX_LINE_STEPS: usize = 10_000;
+
+// the launched source file
+struct Program {
+    path: String,
+    assembly: Assembly,
+}
+
+impl Program {
+    fn line_of(&self, addr: u16) -> Option<usize> {
+        self.assembly.lines.range(..=addr).next_back().map(|(_, line)| *line)
+    }
+
+    // the first statement on the line, or on the next line with code
+    fn addr_of(&self, line: usize) -> Option<(u16, usize)> {
+        self.assembly.lines.iter()
+            .filter(|(_, statement_line)| **statement_line >= line)
+            .min_by_key(|(addr, statement_line)| (**statement_line, **addr))
+            .map(|(addr, line)| (*addr, *line))
+    }
+}
+
+pub struct DapServer<W: Write> {
+    output: W,
+    requests: Receiver<Value>,
+    seq: i64,
+    // events that follow the response of the current request
+    events: Vec<Value>,
+
+    emulator: Emu,
+    program: Option<Program>,
+    source_breakpoints: BTreeSet<u16>,
+    cycles_per_frame: u32,
+    stop_on_entry: bool,
+
+    running: bool,
+    continue_started: bool,
+    // continue as fast as possible instead of in real time
+    pub unthrottled: bool,
+}
+
+impl<W: Write> DapServer<W> {
+    // reads requests from input on a separate thread, so a running
+    // ROM can be paused
+    pub fn new<R: Read + Send + 'static>(input: R, output: W) -> Self {
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || {
+            let mut input = BufReader::new(input);
+            while let Some(message) = read_message(&mut input) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        DapServer {
+            output,
+            requests,
+            seq: 1,
+            events: Vec::new(),
+
+            emulator: Emu::new(),
+            program: None,
+            source_breakpoints: BTreeSet::new(),
+            cycles_per_frame: 10,
+            stop_on_entry: false,
+
+            running: false,
+            continue_started: false,
+            unthrottled: false,
+        }
+    }
+
+    // serves requests until the client disconnects
+    pub fn run(&mut self) -> io::Result<()> {
+        let frame_duration = Duration::from_secs(1) / FRAMES_PER_SECOND;
+
+        loop {
+            let frame_start = Instant::now();
+
+            let message = if self.running {
+                match self.requests.try_recv() {
+                    Ok(message) => Some(message),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            } else {
+                match self.requests.recv() {
+                    Ok(message) => Some(message),
+                    Err(_) => return Ok(()),
+                }
+            };
+
+            if let Some(message) = message {
+                if !self.handle_message(&message)? {
+                    return Ok(());
+                }
+                continue;
+            }
+
+            self.continue_frame();
+            self.flush_events()?;
+
+            if !self.unthrottled {
+                if let Some(rest) = frame_duration.checked_sub(frame_start.elapsed()) {
+                    thread::sleep(rest);
+                }
+            }
+        }
+    }
+
+    // false once the session is over
+    fn handle_message(&mut self, message: &Value) -> io::Result<bool> {
+        if message["type"] != "request" {
+            return Ok(true);
+        }
+        let command = message["command"].as_str().unwrap_or_default().to_string();
+        let arguments = &message["arguments"];
+
+        let result = match command.as_str() {
+            "initialize" => {
+                self.events.push(event("initialized", json!({})));
+                Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsSetVariable": true,
+                    "supportsSteppingGranularity": true,
+                    "supportsConditionalBreakpoints": true,
+                    "supportsEvaluateForHovers": true,
+                }))
+            },
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    self.events.push(stopped_event("entry", None));
+                } else {
+                    self.resume();
+                }
+                Ok(json!({}))
+            },
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS_REF, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK_REF, "expensive": false },
+                { "name": "Memory", "variablesReference": MEMORY_REF, "indexedVariables": RAM_SIZE / MEMORY_ROW, "expensive": true },
+            ]})),
+            "variables" => self.variables(arguments),
+            "setVariable" => self.set_variable(arguments),
+            "evaluate" => self.evaluate(arguments),
+            "continue" => {
+                self.resume();
+                Ok(json!({ "allThreadsContinued": true }))
+            },
+            "pause" => {
+                if self.running {
+                    self.running = false;
+                    self.events.push(stopped_event("pause", None));
+                }
+                Ok(json!({}))
+            },
+            "next" | "stepIn" | "stepOut" => {
+                self.step(&command, arguments["granularity"] == "instruction");
+                Ok(json!({}))
+            },
+            "disconnect" | "terminate" => {
+                self.respond(message, Ok(json!({})))?;
+                return Ok(false);
+            },
+            _ => Err(format!("unsupported request [{}]", command)),
+        };
+
+        self.respond(message, result)?;
+        self.flush_events()?;
+        Ok(true)
+    }
+
+    // LAUNCH
+
+    fn launch(&mut self, arguments: &Value) -> Result<Value, String> {
+        let path = arguments["program"].as_str().ok_or("missing program")?.to_string();
+
+        let mut emulator = Emu::new();
+        if let Some(name) = arguments["quirks"].as_str() {
+            emulator.quirks = Quirks::from_name(name)
+                .ok_or_else(|| format!("unknown quirk preset [{}], valid presets: {}", name, QUIRK_PRESETS.join(", ")))?;
+        }
+        if let Some(seed) = arguments["seed"].as_u64() {
+            emulator.set_seed(seed);
+        }
+        if let Some(cycles) = arguments["cyclesPerFrame"].as_u64() {
+            self.cycles_per_frame = (cycles as u32).max(1);
+        }
+
+        self.program = None;
+        let rom = if path.ends_with(".8o") {
+            let source = fs::read_to_string(&path).map_err(|error| format!("unable to read [{}]: {}", path, error))?;
+            let assembly = asm::assemble(&source).map_err(|error| format!("{}:{}", path, error))?;
+            let rom = assembly.bytes.clone();
+
+            // :breakpoint statements
+            emulator.debugger.breakpoints.extend(assembly.breakpoints.keys());
+
+            emulator.debugger.symbols = Symbols::from_labels(&assembly.labels);
+            self.program = Some(Program { path, assembly });
+            rom
+        } else {
+            let rom = fs::read(&path).map_err(|error| format!("unable to read [{}]: {}", path, error))?;
+            // a broken file next to the ROM is skipped
+            if let Some(symbols) = Symbols::find_next_to(&path).and_then(|symbol_path| Symbols::load(&symbol_path).ok()) {
+                emulator.debugger.symbols = symbols;
+            }
+            rom
+        };
+        if let Some(symbol_path) = arguments["symbols"].as_str() {
+            emulator.debugger.symbols = load_symbols(symbol_path)?;
+        }
+
+        emulator.load(&rom).map_err(|error| format!("ROM could not be loaded: {}", error))?;
+        emulator.debugger.cycles_per_frame = self.cycles_per_frame;
+
+        self.emulator = emulator;
+        self.source_breakpoints.clear();
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        Ok(json!({}))
+    }
+
+    // replaces the breakpoints of the source file
+    fn set_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let requested: Vec<(usize, Option<&str>)> = arguments["breakpoints"].as_array()
+            .map(|breakpoints| breakpoints.iter()
+                .filter_map(|breakpoint| Some((breakpoint["line"].as_u64()? as usize, breakpoint["condition"].as_str())))
+                .collect())
+            .unwrap_or_default();
+
+        let debugger = &mut self.emulator.debugger;
+        for addr in &self.source_breakpoints {
+            debugger.breakpoints.remove(addr);
+            debugger.set_condition(*addr, None);
+        }
+        self.source_breakpoints.clear();
+
+        let mut breakpoints = Vec::new();
+        for (line, condition) in requested {
+            let found = self.program.as_ref()
+                .filter(|program| arguments["source"]["path"].as_str().is_none_or(|path| same_file(path, &program.path)))
+                .and_then(|program| program.addr_of(line));
+            let condition = condition.filter(|text| !text.trim().is_empty())
+                .map(|text| Expression::parse_condition(text, &debugger.symbols));
+
+            match (found, condition) {
+                (Some(_), Some(Err(error))) => {
+                    breakpoints.push(json!({ "verified": false, "line": line, "message": format!("invalid condition, {}", error) }));
+                },
+                (Some((addr, line)), condition) => {
+                    self.source_breakpoints.insert(addr);
+                    debugger.breakpoints.insert(addr);
+                    debugger.set_condition(addr, condition.and_then(Result::ok));
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "line": line,
+                        "instructionReference": format!("0x{:03X}", addr),
+                    }));
+                },
+                (None, _) => breakpoints.push(json!({ "verified": false, "line": line, "message": "no code on or after this line" })),
+            }
+        }
+
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    // RUNNING
+
+    fn resume(&mut self) {
+        self.running = true;
+        self.continue_started = false;
+    }
+
+    fn continue_frame(&mut self) {
+        let emulator = &mut self.emulator;
+
+        // run_until skips the breakpoint at PC, that is only right
+        // for the first frame of a continue
+        let reason = if self.continue_started && emulator.breakpoint_hit(emulator.programm_counter) {
+            StopReason::Breakpoint { pc: emulator.programm_counter }
+        } else {
+            emulator.run_until(self.cycles_per_frame as u64, |_| false)
+        };
+        self.continue_started = true;
+
+        if reason != StopReason::CycleLimit {
+            self.running = false;
+            self.report_stop(reason);
+        }
+    }
+
+    // steps by source line when there is a source file
+    fn step(&mut self, command: &str, by_instruction: bool) {
+        self.running = false;
+
+        let line_of = |server: &Self| server.program.as_ref().and_then(|program| program.line_of(server.emulator.programm_counter));
+        let start_line = if by_instruction { None } else { line_of(self) };
+        let depth = self.emulator.stack_pointer;
+
+        let mut reason = StopReason::StepDone;
+        for _ in 0..MAX_LINE_STEPS {
+            reason = match command {
+                "next" => self.emulator.step_over(MAX_STEP_CYCLES),
+                "stepOut" => self.emulator.step_out(MAX_STEP_CYCLES),
+                _ => self.emulator.step_into(),
+            };
+
+            // done when the line changed or a call / return happened
+            let same_line = start_line.is_some() && line_of(self) == start_line && self.emulator.stack_pointer == depth;
+            if reason != StopReason::StepDone || command == "stepOut" || !same_line {
+                break;
+            }
+        }
+
+        self.report_stop(reason);
+    }
+
+    fn report_stop(&mut self, reason: StopReason) {
+        let stopped = match reason {
+            StopReason::Breakpoint { .. } => stopped_event("breakpoint", None),
+            StopReason::MemoryRead { .. } | StopReason::MemoryWrite { .. } | StopReason::RegisterChange { .. } => {
+                stopped_event("data breakpoint", Some(reason.to_string()))
+            },
+            StopReason::Fault(error) => {
+                self.events.push(event("output", json!({ "category": "stderr", "output": format!("Emulator fault: {}\n", error) })));
+                stopped_event("exception", Some(error.to_string()))
+            },
+            StopReason::Exited => {
+                self.events.push(event("exited", json!({ "exitCode": 0 })));
+                event("terminated", json!({}))
+            },
+            StopReason::StepDone | StopReason::Condition | StopReason::CycleLimit => stopped_event("step", None),
+        };
+        self.events.push(stopped);
+    }
+
+    // INSPECTION
+
+    fn source(&self) -> Value {
+        match &self.program {
+            Some(program) => json!({ "path": program.path }),
+            None => Value::Null,
+        }
+    }
+
+    // the current instruction, then the calls that led to it
+    fn stack_trace(&self) -> Value {
+        let emulator = &self.emulator;
+        let mut addrs = vec![emulator.programm_counter];
+        addrs.extend(emulator.call_stack().iter().rev().map(|return_addr| return_addr.wrapping_sub(2)));
+
+        let frames: Vec<Value> = addrs.iter().enumerate().map(|(id, addr)| {
+            let program = self.program.as_ref();
+            let name = emulator.debugger.symbols.describe(*addr)
+                .unwrap_or_else(|| match emulator.instruction_at(*addr) {
+                    Some(instruction) => format!("{:03X} {}", addr, emulator.debugger.symbols.mnemonic(&instruction)),
+                    None => format!("{:03X}", addr),
+                });
+            let line = program.and_then(|program| program.line_of(*addr));
+
+            let mut frame = json!({
+                "id": id,
+                "name": name,
+                "line": line.unwrap_or(0),
+                "column": if line.is_some() { 1 } else { 0 },
+                "instructionPointerReference": format!("0x{:03X}", addr),
+            });
+            if line.is_some() {
+                frame["source"] = self.source();
+            }
+            frame
+        }).collect();
+
+        json!({ "stackFrames": frames, "totalFrames": addrs.len() })
+    }
+
+    fn variables(&self, arguments: &Value) -> Result<Value, String> {
+        let emulator = &self.emulator;
+
+        let variables: Vec<Value> = match arguments["variablesReference"].as_i64() {
+            Some(REGISTERS_REF) => ALL_REGISTERS.iter()
+                .map(|register| variable(&register.to_string(), format_register(*register, emulator.register(*register))))
+                .collect(),
+            Some(STACK_REF) => emulator.call_stack().iter().rev().enumerate()
+                .map(|(depth, addr)| {
+                    let label = emulator.debugger.symbols.describe(*addr);
+                    let value = match label {
+                        Some(label) => format!("0x{:03X} ({})", addr, label),
+                        None => format!("0x{:03X}", addr),
+                    };
+                    variable(&format!("[{}]", depth), value)
+                })
+                .collect(),
+            Some(MEMORY_REF) => {
+                let rows = RAM_SIZE / MEMORY_ROW;
+                let start = (arguments["start"].as_u64().unwrap_or(0) as usize).min(rows);
+                let count = arguments["count"].as_u64().map_or(rows, |count| count as usize);
+
+                (start..(start.saturating_add(count)).min(rows)).map(|row| {
+                    let addr = row * MEMORY_ROW;
+                    let bytes: Vec<String> = emulator.ram[addr..addr + MEMORY_ROW].iter().map(|byte| format!("{:02X}", byte)).collect();
+                    variable(&format!("{:04X}", addr), bytes.join(" "))
+                }).collect()
+            },
+            _ => return Err("unknown variablesReference".to_string()),
+        };
+
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn set_variable(&mut self, arguments: &Value) -> Result<Value, String> {
+        if arguments["variablesReference"].as_i64() != Some(REGISTERS_REF) {
+            return Err("only registers can be changed".to_string());
+        }
+
+        let name = arguments["name"].as_str().unwrap_or_default();
+        let register = *ALL_REGISTERS.iter()
+            .find(|register| register.to_string() == name)
+            .ok_or_else(|| format!("unknown register [{}]", name))?;
+
+        let text = arguments["value"].as_str().unwrap_or_default().trim();
+        let value = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            Some(hex) => u16::from_str_radix(hex, 16),
+            None => text.parse(),
+        };
+        let value = value.map_err(|_| format!("invalid value [{}]", text))?;
+
+        self.emulator.set_register(register, value);
+        Ok(json!({ "value": format_register(register, self.emulator.register(register)) }))
+    }
+
+    // watch, hover and repl expressions, see expr.rs
+    fn evaluate(&mut self, arguments: &Value) -> Result<Value, String> {
+        let text = arguments["expression"].as_str().unwrap_or_default();
+        let value = Expression::parse_with(text, &self.emulator.debugger.symbols)
+            .and_then(|expression| expression.evaluate(&self.emulator))
+            .map_err(|error| error.to_string())?;
+        Ok(json!({ "result": value.to_string(), "variablesReference": 0 }))
+    }
+
+    // MESSAGES
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn flush_events(&mut self) -> io::Result<()> {
+        for event in std::mem::take(&mut self.events) {
+            self.send(event)?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        message["seq"] = json!(self.seq);
+        self.seq += 1;
+
+        let body = message.to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.output.flush()
+    }
+}
+
+fn event(name: &str, body: Value) -> Value {
+    json!({ "type": "event", "event": name, "body": body })
+}
+
+fn stopped_event(reason: &str, text: Option<String>) -> Value {
+    let mut body = json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true });
+    if let Some(text) = text {
+        body["text"] = json!(text);
+    }
+    event("stopped", body)
+}
+
+fn variable(name: &str, value: String) -> Value {
+    json!({ "name": name, "value": value, "variablesReference": 0 })
+}
+
+fn format_register(register: Register, value: u16) -> String {
+    match register {
+        Register::I | Register::Pc => format!("0x{:04X}", value),
+        _ => format!("0x{:02X}", value),
+    }
+}
+
+fn load_symbols(path: &str) -> Result<Symbols, String> {
+    Symbols::load(path).map_err(|error| format!("symbols [{}] could not be loaded: {}", path, error))
+}
+
+// editors may send the path in a different but equivalent form
+fn same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+// Content-Length: <n>\r\n\r\n<json>, None at the end of the input
+fn read_message(input: &mut impl BufRead) -> Option<Value> {
+    loop {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if input.read_line(&mut header).ok()? == 0 {
+                return None;
+            }
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+
+        // a message without length can't be skipped, wait for the next one
+        let Some(length) = length else { continue };
+        let mut body = vec![0; length];
+        input.read_exact(&mut body).ok()?;
+
+        if let Ok(message) = serde_json::from_slice(&body) {
+            return Some(message);
+        }
+    }
+}
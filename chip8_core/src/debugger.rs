@@ -0,0 +1,428 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+
+use crate::*;
+
+// Breakpoints, watchpoints and stepping. The emulator checks the
+// debugger in tick(), stops are reported through run_until() and the
+// step functions, and through FrameResult::stop by run_frame().
+// Breakpoints can have a condition, see expr.rs for the language.
+
+// addresses remembered by recent_writes()
+pub const RECENT_WRITES: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Register {
+    V(u8),
+    I,
+    Pc,
+    Sp,
+    Dt,     // delay timer
+    St,     // sound timer
+}
+
+pub const ALL_REGISTERS: [Register; 21] = [
+    Register::V(0x0), Register::V(0x1), Register::V(0x2), Register::V(0x3),
+    Register::V(0x4), Register::V(0x5), Register::V(0x6), Register::V(0x7),
+    Register::V(0x8), Register::V(0x9), Register::V(0xA), Register::V(0xB),
+    Register::V(0xC), Register::V(0xD), Register::V(0xE), Register::V(0xF),
+    Register::I, Register::Pc, Register::Sp, Register::Dt, Register::St,
+];
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => write!(f, "I"),
+            Register::Pc => write!(f, "PC"),
+            Register::Sp => write!(f, "SP"),
+            Register::Dt => write!(f, "DT"),
+            Register::St => write!(f, "ST"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,     // read or write
+}
+
+// watches the addresses from start to end, both included
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryWatchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind,
+}
+
+// why run_until or a step returned. pc is the address of the
+// instruction that caused the stop
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint { pc: u16 },
+    MemoryRead { pc: u16, addr: u16, value: u8 },
+    MemoryWrite { pc: u16, addr: u16, old: u8, new: u8 },
+    RegisterChange { pc: u16, register: Register, old: u16, new: u16 },
+    Condition,      // the condition given to run_until is true
+    StepDone,
+    CycleLimit,     // the cycle budget is used up
+    Exited,         // 00FD
+    Fault(EmuError),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Breakpoint { pc } => write!(f, "breakpoint at {:03X}", pc),
+            StopReason::MemoryRead { pc, addr, value } => {
+                write!(f, "{:03X} read {:02X} from {:03X}", pc, value, addr)
+            },
+            StopReason::MemoryWrite { pc, addr, old, new } => {
+                write!(f, "{:03X} wrote {:02X} to {:03X} (was {:02X})", pc, new, addr, old)
+            },
+            StopReason::RegisterChange { pc, register, old, new } => {
+                write!(f, "{:03X} changed {} from {:X} to {:X}", pc, register, old, new)
+            },
+            StopReason::Condition => write!(f, "condition met"),
+            StopReason::StepDone => write!(f, "step"),
+            StopReason::CycleLimit => write!(f, "cycle limit reached"),
+            StopReason::Exited => write!(f, "ROM exited"),
+            StopReason::Fault(error) => write!(f, "fault: {}", error),
+        }
+    }
+}
+
+// "300", "draw+4" or "300 if V0 == 1", addresses are hex or labels.
+// Columns of errors count from the start of the whole text
+pub fn parse_breakpoint(text: &str, symbols: &Symbols) -> Result<(u16, Option<Expression>), ExprError> {
+    let (addr_text, condition) = match text.find(" if ") {
+        Some(index) => (&text[..index], Some(index + 4)),
+        None => (text, None),
+    };
+
+    let addr_text = addr_text.trim();
+    let addr = symbols.resolve(addr_text)
+        .ok_or_else(|| ExprError { column: 1, message: format!("invalid address or unknown label [{}]", addr_text) })?;
+
+    let Some(start) = condition else {
+        return Ok((addr, None));
+    };
+    let condition = Expression::parse_condition(&text[start..], symbols)
+        .map_err(|error| ExprError { column: error.column + text[..start].chars().count(), ..error })?;
+    Ok((addr, Some(condition)))
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    pub breakpoints: BTreeSet<u16>,
+    pub memory_watchpoints: Vec<MemoryWatchpoint>,
+    pub register_watchpoints: BTreeSet<Register>,
+    // a breakpoint with a condition only stops when it is true
+    pub conditions: BTreeMap<u16, Expression>,
+    // shown by the front ends, they don't stop anything
+    pub watches: Vec<Expression>,
+    // labels for the front ends and traces
+    pub symbols: Symbols,
+    // remember written addresses for a memory view, off by default
+    pub track_writes: bool,
+    recent_writes: VecDeque<u16>,
+
+    // run_until and the steps tick the timers after this many
+    // instructions, like run_frame does. 0 leaves the timers alone
+    pub cycles_per_frame: u32,
+    cycles_since_timers: u32,
+
+    stop: Option<StopReason>,
+    // the breakpoint that caused the last stop doesn't stop the next instruction
+    resume_pc: Option<u16>,
+}
+
+impl Debugger {
+    pub fn toggle_breakpoint(&mut self, addr: u16) -> bool {
+        if !self.breakpoints.remove(&addr) {
+            self.breakpoints.insert(addr);
+            return true;
+        }
+        self.conditions.remove(&addr);
+        false
+    }
+
+    // None removes the condition, the breakpoint stays
+    pub fn set_condition(&mut self, addr: u16, condition: Option<Expression>) {
+        match condition {
+            Some(condition) => self.conditions.insert(addr, condition),
+            None => self.conditions.remove(&addr),
+        };
+    }
+
+    pub fn watch_memory(&mut self, start: u16, end: u16, kind: WatchKind) {
+        self.memory_watchpoints.push(MemoryWatchpoint { start: start.min(end), end: start.max(end), kind });
+    }
+
+    pub fn watch_register(&mut self, register: Register) {
+        self.register_watchpoints.insert(register);
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.conditions.clear();
+        self.memory_watchpoints.clear();
+        self.register_watchpoints.clear();
+    }
+
+    // nothing to check, the emulator runs at full speed
+    pub(crate) fn is_idle(&self) -> bool {
+        self.breakpoints.is_empty() && self.memory_watchpoints.is_empty() && self.register_watchpoints.is_empty()
+    }
+
+    fn is_watched(&self, addr: usize, write: bool) -> bool {
+        self.memory_watchpoints.iter().any(|watch| {
+            let kind_matches = match watch.kind {
+                WatchKind::Read => !write,
+                WatchKind::Write => write,
+                WatchKind::Access => true,
+            };
+            kind_matches && (watch.start as usize..=watch.end as usize).contains(&addr)
+        })
+    }
+
+    // an address written twice only counts as the newest write
+    fn log_write(&mut self, addr: u16) {
+        self.recent_writes.retain(|other| *other != addr);
+        if self.recent_writes.len() == RECENT_WRITES {
+            self.recent_writes.pop_front();
+        }
+        self.recent_writes.push_back(addr);
+    }
+
+    // the first stop of an instruction wins
+    fn report(&mut self, stop: StopReason) {
+        if self.stop.is_none() {
+            self.stop = Some(stop);
+        }
+    }
+
+    pub(crate) fn take_stop(&mut self) -> Option<StopReason> {
+        self.stop.take()
+    }
+
+    // HOOKS, called by the emulator
+
+    pub(crate) fn on_read(&mut self, pc: u16, addr: usize, value: u8) {
+        if self.is_watched(addr, false) {
+            self.report(StopReason::MemoryRead { pc, addr: addr as u16, value });
+        }
+    }
+
+    pub(crate) fn on_write(&mut self, pc: u16, addr: usize, old: u8, new: u8) {
+        if self.is_watched(addr, true) {
+            self.report(StopReason::MemoryWrite { pc, addr: addr as u16, old, new });
+        }
+    }
+}
+
+impl Emu {
+    // REGISTERS
+
+    pub fn register(&self, register: Register) -> u16 {
+        match register {
+            Register::V(x) => self.registers[(x & 0xF) as usize] as u16,
+            Register::I => self.i_register,
+            Register::Pc => self.programm_counter,
+            Register::Sp => self.stack_pointer,
+            Register::Dt => self.delay_timer as u16,
+            Register::St => self.sound_timer as u16,
+        }
+    }
+
+    pub fn set_register(&mut self, register: Register, value: u16) {
+        match register {
+            Register::V(x) => self.registers[(x & 0xF) as usize] = value as u8,
+            Register::I => self.i_register = value,
+            Register::Pc => self.programm_counter = value,
+            Register::Sp => self.stack_pointer = value.min(STACK_SIZE as u16),
+            Register::Dt => self.delay_timer = value as u8,
+            Register::St => self.sound_timer = value as u8,
+        }
+    }
+
+    // return addresses, the innermost call last
+    pub fn call_stack(&self) -> &[u16] {
+        &self.stack[..(self.stack_pointer as usize).min(STACK_SIZE)]
+    }
+
+    // the call stack with labels, the innermost call first. Entries are
+    // the calling instructions, 2 bytes before the return addresses
+    pub fn backtrace(&self) -> Vec<String> {
+        self.call_stack().iter().rev()
+            .map(|addr| self.debugger.symbols.format(addr.wrapping_sub(2)))
+            .collect()
+    }
+
+    // the instruction at addr, without side effects
+    pub fn instruction_at(&self, addr: u16) -> Option<Instruction> {
+        let word = |addr: u16| -> Option<u16> {
+            let high = *self.ram.get(addr as usize)?;
+            let low = *self.ram.get(addr as usize + 1)?;
+            Some(((high as u16) << 8) | low as u16)
+        };
+
+        match decode(word(addr)?).ok()? {
+            Instruction::LoadLongI(_) => Some(Instruction::LoadLongI(word(addr.wrapping_add(2))?)),
+            instruction => Some(instruction),
+        }
+    }
+
+    // RUNNING
+
+    // executes instructions until a breakpoint or watchpoint triggers,
+    // `done` returns true after an instruction or max_cycles ran out.
+    // The breakpoint at the current PC is skipped, so this continues
+    // after a stop. Works while the emulator is paused
+    pub fn run_until(&mut self, max_cycles: u64, mut done: impl FnMut(&Emu) -> bool) -> StopReason {
+        let was_paused = self.is_paused;
+        self.is_paused = false;
+        self.debugger.resume_pc = Some(self.programm_counter);
+
+        let mut reason = StopReason::CycleLimit;
+        for _ in 0..max_cycles {
+            if self.has_exited {
+                reason = StopReason::Exited;
+                break;
+            }
+            if let Err(error) = self.tick() {
+                reason = StopReason::Fault(error);
+                break;
+            }
+
+            let cycles_per_frame = self.debugger.cycles_per_frame;
+            if cycles_per_frame > 0 {
+                self.debugger.cycles_since_timers += 1;
+                if self.debugger.cycles_since_timers >= cycles_per_frame {
+                    self.debugger.cycles_since_timers = 0;
+                    self.tick_timers();
+                }
+            }
+
+            if let Some(stop) = self.debugger.take_stop() {
+                reason = stop;
+                break;
+            }
+            if done(self) {
+                reason = StopReason::Condition;
+                break;
+            }
+        }
+
+        self.is_paused = was_paused;
+        reason
+    }
+
+    // one instruction
+    pub fn step_into(&mut self) -> StopReason {
+        match self.run_until(1, |_| true) {
+            StopReason::Condition => StopReason::StepDone,
+            reason => reason,
+        }
+    }
+
+    // one instruction, subroutine calls run until they return
+    pub fn step_over(&mut self, max_cycles: u64) -> StopReason {
+        let Some(Instruction::Call(_)) = self.instruction_at(self.programm_counter) else {
+            return self.step_into();
+        };
+
+        let return_addr = self.programm_counter.wrapping_add(2);
+        let depth = self.stack_pointer;
+        let reason = self.run_until(max_cycles, |emu| {
+            emu.programm_counter == return_addr && emu.stack_pointer == depth
+        });
+        match reason {
+            StopReason::Condition => StopReason::StepDone,
+            reason => reason,
+        }
+    }
+
+    // runs until the current subroutine returned
+    pub fn step_out(&mut self, max_cycles: u64) -> StopReason {
+        if self.stack_pointer == 0 {
+            return self.step_into();
+        }
+
+        let depth = self.stack_pointer;
+        match self.run_until(max_cycles, |emu| emu.stack_pointer < depth) {
+            StopReason::Condition => StopReason::StepDone,
+            reason => reason,
+        }
+    }
+
+    // true if there is a breakpoint at addr and its condition holds.
+    // A condition that fails to evaluate stops like a plain breakpoint
+    pub fn breakpoint_hit(&self, addr: u16) -> bool {
+        if !self.debugger.breakpoints.contains(&addr) {
+            return false;
+        }
+        match self.debugger.conditions.get(&addr) {
+            Some(condition) => condition.is_true(self).unwrap_or(true),
+            None => true,
+        }
+    }
+
+    // addresses written while track_writes was set, the newest first
+    pub fn recent_writes(&self) -> impl Iterator<Item = u16> + '_ {
+        self.debugger.recent_writes.iter().rev().copied()
+    }
+
+    // the watch expressions with their current values
+    pub fn watch_values(&self) -> Vec<(&Expression, Result<ExprValue, ExprError>)> {
+        self.debugger.watches.iter().map(|watch| (watch, watch.evaluate(self))).collect()
+    }
+
+    // HOOKS
+
+    // true if the instruction at current_pc must not run
+    pub(crate) fn check_breakpoint(&mut self) -> bool {
+        let pc = self.current_pc;
+        if self.debugger.resume_pc.take() == Some(pc) {
+            return false;
+        }
+        if self.breakpoint_hit(pc) {
+            self.debugger.report(StopReason::Breakpoint { pc });
+            self.debugger.resume_pc = Some(pc);
+            return true;
+        }
+        false
+    }
+
+    pub(crate) fn debug_read(&mut self, addr: usize, value: u8) {
+        if !self.debugger.memory_watchpoints.is_empty() {
+            self.debugger.on_read(self.current_pc, addr, value);
+        }
+    }
+
+    pub(crate) fn debug_write(&mut self, addr: usize, old: u8, new: u8) {
+        if !self.debugger.memory_watchpoints.is_empty() {
+            self.debugger.on_write(self.current_pc, addr, old, new);
+        }
+        if self.debugger.track_writes {
+            self.debugger.log_write(addr as u16);
+        }
+    }
+
+    pub(crate) fn watched_registers(&self) -> Vec<(Register, u16)> {
+        self.debugger.register_watchpoints.iter()
+            .map(|register| (*register, self.register(*register)))
+            .collect()
+    }
+
+    pub(crate) fn check_watched_registers(&mut self, before: Vec<(Register, u16)>) {
+        for (register, old) in before {
+            let new = self.register(register);
+            if new != old {
+                self.debugger.report(StopReason::RegisterChange { pc: self.current_pc, register, old, new });
+            }
+        }
+    }
+}
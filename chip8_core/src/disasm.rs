@@ -0,0 +1,252 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::instruction::{decode, Instruction};
+use crate::Symbols;
+
+// Result of following the control flow of a ROM from its entry point.
+// Bytes that are never reached as part of an instruction are data.
+pub struct Disassembly {
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+    pub instructions: BTreeMap<u16, Instruction>,  // start address -> instruction
+    pub labels: BTreeMap<u16, String>,
+}
+
+pub fn disassemble(rom: &[u8]) -> Disassembly {
+    disassemble_at(rom, 0x200)
+}
+
+pub fn disassemble_at(rom: &[u8], origin: u16) -> Disassembly {
+    let mut disassembly = Disassembly {
+        origin,
+        bytes: rom.to_vec(),
+        instructions: BTreeMap::new(),
+        labels: BTreeMap::new(),
+    };
+
+    let mut targets: BTreeMap<u16, &str> = BTreeMap::new();
+    let mut todo = vec![origin];
+
+    while let Some(addr) = todo.pop() {
+        if disassembly.instructions.contains_key(&addr) {
+            continue;
+        }
+        let Some(instruction) = disassembly.decode_at(addr) else {
+            continue;
+        };
+        disassembly.instructions.insert(addr, instruction);
+
+        let next = addr.wrapping_add(instruction.size());
+        match instruction {
+            Instruction::Jump(nnn) => {
+                targets.entry(nnn).or_insert("label");
+                todo.push(nnn);
+            },
+            Instruction::Call(nnn) => {
+                targets.insert(nnn, "sub");
+                todo.push(nnn);
+                todo.push(next);
+            },
+            // BNNN is mostly used for jump tables starting at NNN
+            Instruction::JumpOffset(_, nnn) => {
+                targets.entry(nnn).or_insert("table");
+                todo.push(nnn);
+            },
+            Instruction::Ret | Instruction::Exit => {},
+            Instruction::SkipEqImm(..) | Instruction::SkipNeImm(..)
+            | Instruction::SkipEqReg(..) | Instruction::SkipNeReg(..)
+            | Instruction::SkipKey(_) | Instruction::SkipNotKey(_) => {
+                todo.push(next);
+                // the skipped instruction can be 4 bytes long
+                let skipped_size = disassembly.decode_at(next).map_or(2, |skipped| skipped.size());
+                todo.push(next.wrapping_add(skipped_size));
+            },
+            Instruction::LoadI(nnn) | Instruction::LoadLongI(nnn) => {
+                targets.entry(nnn).or_insert("data");
+                todo.push(next);
+            },
+            _ => todo.push(next),
+        }
+    }
+
+    // only addresses that start a line in the listing can get a label
+    let line_starts = disassembly.line_starts();
+    for (addr, prefix) in targets {
+        if line_starts.contains(&addr) {
+            disassembly.labels.insert(addr, format!("{}_{:03X}", prefix, addr));
+        }
+    }
+
+    disassembly
+}
+
+impl Disassembly {
+    fn end(&self) -> usize {
+        self.origin as usize + self.bytes.len()
+    }
+
+    fn decode_at(&self, addr: u16) -> Option<Instruction> {
+        let offset = (addr as usize).checked_sub(self.origin as usize)?;
+        let word = |offset: usize| -> Option<u16> {
+            let bytes = self.bytes.get(offset..offset + 2)?;
+            Some(((bytes[0] as u16) << 8) | bytes[1] as u16)
+        };
+
+        match decode(word(offset)?).ok()? {
+            Instruction::LoadLongI(_) => Some(Instruction::LoadLongI(word(offset + 2)?)),
+            instruction => Some(instruction),
+        }
+    }
+
+    // walks the rom the same way to_octo() does
+    fn lines(&self) -> Vec<(u16, Option<Instruction>)> {
+        let mut lines = Vec::new();
+        let mut addr = self.origin as usize;
+
+        while addr < self.end() {
+            match self.instructions.get(&(addr as u16)) {
+                Some(instruction) if addr + instruction.size() as usize <= self.end() => {
+                    lines.push((addr as u16, Some(*instruction)));
+                    addr += instruction.size() as usize;
+                },
+                _ => {
+                    lines.push((addr as u16, None));
+                    addr += 1;
+                }
+            }
+        }
+        lines
+    }
+
+    fn line_starts(&self) -> BTreeSet<u16> {
+        self.lines().into_iter().map(|(addr, _)| addr).collect()
+    }
+
+    fn byte(&self, addr: u16) -> u8 {
+        self.bytes[(addr - self.origin) as usize]
+    }
+
+    // names from a symbol file replace the generated labels. Symbols
+    // inside an instruction or outside the ROM can't be labels
+    pub fn apply_symbols(&mut self, symbols: &Symbols) {
+        let line_starts = self.line_starts();
+        for (addr, label) in symbols.labels() {
+            if line_starts.contains(addr) {
+                self.labels.insert(*addr, label.clone());
+            }
+        }
+    }
+
+    pub fn label_or_address(&self, addr: u16) -> String {
+        match self.labels.get(&addr) {
+            Some(label) => label.clone(),
+            None => format!("0x{:03X}", addr),
+        }
+    }
+
+    // Octo source that assembles back into the same bytes. Every line
+    // carries its address, the raw bytes and the Cowgod mnemonic as comment.
+    pub fn to_octo(&self) -> String {
+        let mut out = String::new();
+
+        for (addr, instruction) in self.lines() {
+            if let Some(label) = self.labels.get(&addr) {
+                let _ = writeln!(out, ": {}", label);
+            }
+
+            match instruction {
+                Some(instruction) => {
+                    let raw: Vec<String> = (0..instruction.size())
+                        .map(|offset| format!("{:02X}", self.byte(addr + offset)))
+                        .collect();
+                    let comment = format!("# {:03X}: {}  {}", addr, raw.join(""), instruction);
+
+                    match octo_syntax(&instruction, |target| self.label_or_address(target)) {
+                        Some(source) => {
+                            let _ = writeln!(out, "\t{:<28}{}", source, comment);
+                        },
+                        // not expressible in Octo, keep the bytes
+                        None => {
+                            let bytes: Vec<String> = (0..instruction.size())
+                                .map(|offset| format!("0x{:02X}", self.byte(addr + offset)))
+                                .collect();
+                            let _ = writeln!(out, "\t{:<28}{}", bytes.join(" "), comment);
+                        }
+                    }
+                },
+                None => {
+                    let byte = self.byte(addr);
+                    let bitmap: String = (0..8)
+                        .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+                        .collect();
+                    let _ = writeln!(out, "\t{:<28}# {:03X}: {}", format!("0x{:02X}", byte), addr, bitmap);
+                }
+            }
+        }
+
+        out
+    }
+}
+
+// Octo statement for an instruction. Addresses are passed
+// through `target` so the caller can substitute labels.
+pub fn octo_syntax(instruction: &Instruction, target: impl Fn(u16) -> String) -> Option<String> {
+    use Instruction::*;
+
+    let source = match *instruction {
+        Nop => return None,
+        Cls => "clear".to_string(),
+        Ret => "return".to_string(),
+        ScrollDown(n) => format!("scroll-down {}", n),
+        ScrollUp(n) => format!("scroll-up {}", n),
+        ScrollRight => "scroll-right".to_string(),
+        ScrollLeft => "scroll-left".to_string(),
+        Exit => "exit".to_string(),
+        Lores => "lores".to_string(),
+        Hires => "hires".to_string(),
+        Jump(nnn) => format!("jump {}", target(nnn)),
+        Call(nnn) => format!(":call {}", target(nnn)),
+        SkipEqImm(x, nn) => format!("if v{:x} != 0x{:02X} then", x, nn),
+        SkipNeImm(x, nn) => format!("if v{:x} == 0x{:02X} then", x, nn),
+        SkipEqReg(x, y) => format!("if v{:x} != v{:x} then", x, y),
+        SaveRange(x, y) => format!("save v{:x} - v{:x}", x, y),
+        LoadRange(x, y) => format!("load v{:x} - v{:x}", x, y),
+        LoadImm(x, nn) => format!("v{:x} := 0x{:02X}", x, nn),
+        AddImm(x, nn) => format!("v{:x} += 0x{:02X}", x, nn),
+        Move(x, y) => format!("v{:x} := v{:x}", x, y),
+        Or(x, y) => format!("v{:x} |= v{:x}", x, y),
+        And(x, y) => format!("v{:x} &= v{:x}", x, y),
+        Xor(x, y) => format!("v{:x} ^= v{:x}", x, y),
+        Add(x, y) => format!("v{:x} += v{:x}", x, y),
+        Sub(x, y) => format!("v{:x} -= v{:x}", x, y),
+        ShiftRight(x, y) => format!("v{:x} >>= v{:x}", x, y),
+        SubReverse(x, y) => format!("v{:x} =- v{:x}", x, y),
+        ShiftLeft(x, y) => format!("v{:x} <<= v{:x}", x, y),
+        SkipNeReg(x, y) => format!("if v{:x} == v{:x} then", x, y),
+        LoadI(nnn) => format!("i := {}", target(nnn)),
+        JumpOffset(_, nnn) => format!("jump0 {}", target(nnn)),
+        Random(x, nn) => format!("v{:x} := random 0x{:02X}", x, nn),
+        Draw(x, y, n) => format!("sprite v{:x} v{:x} {}", x, y, n),
+        SkipKey(x) => format!("if v{:x} -key then", x),
+        SkipNotKey(x) => format!("if v{:x} key then", x),
+        LoadLongI(nnnn) => format!("i := long {}", target(nnnn)),
+        Plane(n) => format!("plane {}", n),
+        Audio => "audio".to_string(),
+        GetDelay(x) => format!("v{:x} := delay", x),
+        WaitKey(x) => format!("v{:x} := key", x),
+        SetDelay(x) => format!("delay := v{:x}", x),
+        SetSound(x) => format!("buzzer := v{:x}", x),
+        AddI(x) => format!("i += v{:x}", x),
+        Font(x) => format!("i := hex v{:x}", x),
+        BigFont(x) => format!("i := bighex v{:x}", x),
+        Bcd(x) => format!("bcd v{:x}", x),
+        Pitch(x) => format!("pitch := v{:x}", x),
+        Store(x) => format!("save v{:x}", x),
+        Load(x) => format!("load v{:x}", x),
+        SaveFlags(x) => format!("saveflags v{:x}", x),
+        LoadFlags(x) => format!("loadflags v{:x}", x),
+    };
+
+    Some(source)
+}
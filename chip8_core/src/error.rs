@@ -0,0 +1,36 @@
+use std::fmt;
+
+// Faults caused by the running ROM. Everything that happens while
+// executing an instruction carries the address and the opcode of it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmuError {
+    UnknownOpcode { pc: u16, opcode: u16 },
+    StackOverflow { pc: u16, opcode: u16 },
+    StackUnderflow { pc: u16, opcode: u16 },
+    MemoryOutOfBounds { pc: u16, opcode: u16, addr: usize },
+    RomTooLarge { size: usize, max: usize },
+}
+
+impl fmt::Display for EmuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmuError::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode {:04X} at {:#05X}", opcode, pc)
+            },
+            EmuError::StackOverflow { pc, opcode } => {
+                write!(f, "stack overflow by {:04X} at {:#05X}", opcode, pc)
+            },
+            EmuError::StackUnderflow { pc, opcode } => {
+                write!(f, "stack underflow by {:04X} at {:#05X}", opcode, pc)
+            },
+            EmuError::MemoryOutOfBounds { pc, opcode, addr } => {
+                write!(f, "memory access at {:#X} out of bounds by {:04X} at {:#05X}", addr, opcode, pc)
+            },
+            EmuError::RomTooLarge { size, max } => {
+                write!(f, "ROM is {} bytes, only {} bytes fit into memory", size, max)
+            },
+        }
+    }
+}
+
+impl std::error::Error for EmuError {}
@@ -0,0 +1,530 @@
+use std::fmt;
+
+use crate::*;
+
+// Expressions over the emulator state, for conditional breakpoints and
+// watches. Examples:
+//
+//   V3 == 0x10 && ram[I] > 4
+//   PC in 0x300..0x340 && DT == 0
+//
+// Names: V0-VF, I, PC, SP, DT, ST (any case), ram[addr], stack[depth].
+// With symbols loaded labels are names too, their value is the address.
+// Octo labels like draw-player work when the whole name is a label,
+// otherwise the - is a minus.
+// Numbers are decimal, 0x hex or 0b binary. Operators from lowest to
+// highest precedence:
+//
+//   ||                        bool
+//   &&                        bool
+//   == != < <= > >=  in       compare numbers, a in b..c excludes c, b..=c includes it
+//   |  ^  &                   bitwise
+//   << >>
+//   + -
+//   * / %
+//   ! - ~                     unary, ! is bool, - and ~ are numbers
+//
+// Numbers are 64 bit signed integers, arithmetic wraps around.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExprError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+fn error(column: usize, message: String) -> ExprError {
+    ExprError { column, message }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExprType {
+    Number,
+    Bool,
+}
+
+impl fmt::Display for ExprType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExprType::Number => write!(f, "number"),
+            ExprType::Bool => write!(f, "bool"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExprValue {
+    Number(i64),
+    Bool(bool),
+}
+
+impl fmt::Display for ExprValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExprValue::Number(value) if *value >= 0 => write!(f, "0x{:X} ({})", value, value),
+            ExprValue::Number(value) => write!(f, "{}", value),
+            ExprValue::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+// TOKENS
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Symbol(&'static str),
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "{}", value),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Symbol(symbol) => write!(f, "{}", symbol),
+            Token::End => write!(f, "end of expression"),
+        }
+    }
+}
+
+// longest first, so "<=" wins over "<"
+const SYMBOLS: [&str; 27] = [
+    "..=", "&&", "||", "==", "!=", "<=", ">=", "<<", ">>", "..",
+    "<", ">", "+", "-", "*", "/", "%", "&", "|", "^", "!", "~",
+    "(", ")", "[", "]", "=",
+];
+
+// tokens with their column, starting at 1. Names may contain - if the
+// symbols have a label with that name
+fn tokenize(text: &str, symbols: &Symbols) -> Result<Vec<(Token, usize)>, ExprError> {
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+
+    while index < chars.len() {
+        let column = index + 1;
+        let c = chars[index];
+
+        if c.is_whitespace() {
+            index += 1;
+        }
+        else if c.is_ascii_digit() {
+            let start = index;
+            while index < chars.len() && (chars[index].is_ascii_alphanumeric() || chars[index] == '_') {
+                index += 1;
+            }
+            let literal: String = chars[start..index].iter().filter(|c| **c != '_').collect();
+            let value = parse_number(&literal).ok_or_else(|| error(column, format!("invalid number [{}]", literal)))?;
+            tokens.push((Token::Number(value), column));
+        }
+        else if c.is_ascii_alphabetic() || c == '_' {
+            let start = index;
+            while index < chars.len() && is_name(chars[index]) {
+                index += 1;
+            }
+            // the longest label wins, draw-player-sprite over draw-player
+            let mut end = index;
+            while end + 1 < chars.len() && chars[end] == '-' && is_name(chars[end + 1]) {
+                end += 1;
+                while end < chars.len() && is_name(chars[end]) {
+                    end += 1;
+                }
+                let name: String = chars[start..end].iter().collect();
+                if symbols.addr_of(&name).is_some() {
+                    index = end;
+                }
+            }
+            tokens.push((Token::Name(chars[start..index].iter().collect()), column));
+        }
+        else {
+            let rest: String = chars[index..].iter().take(3).collect();
+            let symbol = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol))
+                .ok_or_else(|| error(column, format!("unexpected character [{}]", c)))?;
+            if *symbol == "=" {
+                return Err(error(column, "[=] is not a comparison, use [==]".to_string()));
+            }
+            tokens.push((Token::Symbol(symbol), column));
+            index += symbol.len();
+        }
+    }
+
+    tokens.push((Token::End, chars.len() + 1));
+    Ok(tokens)
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        return i64::from_str_radix(hex, 16).ok();
+    }
+    if let Some(binary) = lower.strip_prefix("0b") {
+        return i64::from_str_radix(binary, 2).ok();
+    }
+    lower.parse().ok()
+}
+
+// SYNTAX TREE
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Array {
+    Ram,
+    Stack,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Number(i64),
+    Bool(bool),
+    Register(Register),
+    Index { array: Array, index: Box<Node>, column: usize },
+    Unary { op: &'static str, operand: Box<Node> },
+    Binary { op: &'static str, left: Box<Node>, right: Box<Node>, column: usize },
+    In { value: Box<Node>, start: Box<Node>, end: Box<Node>, inclusive: bool },
+}
+
+const COMPARISONS: [&str; 6] = ["==", "!=", "<", "<=", ">", ">="];
+
+// binary operators of one precedence level, with the operand types
+struct Level {
+    ops: &'static [&'static str],
+    operands: ExprType,
+}
+
+// from lowest to highest precedence, comparisons sit between && and |
+const LOGIC_LEVELS: [Level; 2] = [
+    Level { ops: &["||"], operands: ExprType::Bool },
+    Level { ops: &["&&"], operands: ExprType::Bool },
+];
+const NUMBER_LEVELS: [Level; 6] = [
+    Level { ops: &["|"], operands: ExprType::Number },
+    Level { ops: &["^"], operands: ExprType::Number },
+    Level { ops: &["&"], operands: ExprType::Number },
+    Level { ops: &["<<", ">>"], operands: ExprType::Number },
+    Level { ops: &["+", "-"], operands: ExprType::Number },
+    Level { ops: &["*", "/", "%"], operands: ExprType::Number },
+];
+
+struct Parser<'a> {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    symbols: &'a Symbols,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].0
+    }
+
+    fn column(&self) -> usize {
+        self.tokens[self.position].1
+    }
+
+    fn next(&mut self) -> (Token, usize) {
+        let token = self.tokens[self.position].clone();
+        if token.0 != Token::End {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn peek_symbol(&self, symbols: &[&'static str]) -> Option<&'static str> {
+        match self.peek() {
+            Token::Symbol(symbol) if symbols.contains(symbol) => Some(*symbol),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<(), ExprError> {
+        let (token, column) = self.next();
+        if token != Token::Symbol(symbol) {
+            return Err(error(column, format!("expected [{}], found [{}]", symbol, token)));
+        }
+        Ok(())
+    }
+
+    fn expect_type(node: (Node, ExprType), expected: ExprType, column: usize, what: &str) -> Result<Node, ExprError> {
+        if node.1 != expected {
+            return Err(error(column, format!("{} needs a {}, found a {}", what, expected, node.1)));
+        }
+        Ok(node.0)
+    }
+
+    fn logic(&mut self, level: usize) -> Result<(Node, ExprType), ExprError> {
+        let Some(current) = LOGIC_LEVELS.get(level) else {
+            return self.comparison();
+        };
+
+        let column = self.column();
+        let mut left = self.logic(level + 1)?;
+        while let Some(op) = self.peek_symbol(current.ops) {
+            let op_column = self.column();
+            self.next();
+            let left_node = Self::expect_type(left, current.operands, column, &format!("[{}]", op))?;
+            let right_column = self.column();
+            let right = self.logic(level + 1)?;
+            let right_node = Self::expect_type(right, current.operands, right_column, &format!("[{}]", op))?;
+            left = (Node::Binary { op, left: Box::new(left_node), right: Box::new(right_node), column: op_column }, ExprType::Bool);
+        }
+        Ok(left)
+    }
+
+    fn comparison(&mut self) -> Result<(Node, ExprType), ExprError> {
+        let column = self.column();
+        let left = self.numbers(0)?;
+
+        if let Token::Name(name) = self.peek() {
+            if name == "in" {
+                self.next();
+                let value = Self::expect_type(left, ExprType::Number, column, "[in]")?;
+                let start_column = self.column();
+                let start = Self::expect_type(self.numbers(0)?, ExprType::Number, start_column, "a range")?;
+                let inclusive = match self.next() {
+                    (Token::Symbol(".."), _) => false,
+                    (Token::Symbol("..="), _) => true,
+                    (token, column) => return Err(error(column, format!("expected [..] or [..=], found [{}]", token))),
+                };
+                let end_column = self.column();
+                let end = Self::expect_type(self.numbers(0)?, ExprType::Number, end_column, "a range")?;
+                let node = Node::In { value: Box::new(value), start: Box::new(start), end: Box::new(end), inclusive };
+                return self.no_chain((node, ExprType::Bool));
+            }
+        }
+
+        let Some(op) = self.peek_symbol(&COMPARISONS) else {
+            return Ok(left);
+        };
+        let op_column = self.column();
+        self.next();
+        let left = Self::expect_type(left, ExprType::Number, column, &format!("[{}]", op))?;
+        let right_column = self.column();
+        let right = Self::expect_type(self.numbers(0)?, ExprType::Number, right_column, &format!("[{}]", op))?;
+
+        let node = Node::Binary { op, left: Box::new(left), right: Box::new(right), column: op_column };
+        self.no_chain((node, ExprType::Bool))
+    }
+
+    // a < b < c would compare a bool with a number
+    fn no_chain(&self, node: (Node, ExprType)) -> Result<(Node, ExprType), ExprError> {
+        let chained = self.peek_symbol(&COMPARISONS).is_some() || *self.peek() == Token::Name("in".to_string());
+        if chained {
+            return Err(error(self.column(), "comparisons can't be chained, combine them with [&&]".to_string()));
+        }
+        Ok(node)
+    }
+
+    fn numbers(&mut self, level: usize) -> Result<(Node, ExprType), ExprError> {
+        let Some(current) = NUMBER_LEVELS.get(level) else {
+            return self.unary();
+        };
+
+        let column = self.column();
+        let mut left = self.numbers(level + 1)?;
+        while let Some(op) = self.peek_symbol(current.ops) {
+            let op_column = self.column();
+            self.next();
+            let left_node = Self::expect_type(left, current.operands, column, &format!("[{}]", op))?;
+            let right_column = self.column();
+            let right = self.numbers(level + 1)?;
+            let right_node = Self::expect_type(right, current.operands, right_column, &format!("[{}]", op))?;
+            left = (Node::Binary { op, left: Box::new(left_node), right: Box::new(right_node), column: op_column }, ExprType::Number);
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<(Node, ExprType), ExprError> {
+        if let Some(op) = self.peek_symbol(&["!", "-", "~"]) {
+            self.next();
+            let column = self.column();
+            let operand_type = if op == "!" { ExprType::Bool } else { ExprType::Number };
+            let operand = Self::expect_type(self.unary()?, operand_type, column, &format!("[{}]", op))?;
+            return Ok((Node::Unary { op, operand: Box::new(operand) }, operand_type));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<(Node, ExprType), ExprError> {
+        let (token, column) = self.next();
+        match token {
+            Token::Number(value) => Ok((Node::Number(value), ExprType::Number)),
+            Token::Symbol("(") => {
+                let node = self.logic(0)?;
+                self.expect(")")?;
+                Ok(node)
+            },
+            Token::Name(name) => self.name(&name, column),
+            token => Err(error(column, format!("expected a value, found [{}]", token))),
+        }
+    }
+
+    fn name(&mut self, name: &str, column: usize) -> Result<(Node, ExprType), ExprError> {
+        let lower = name.to_lowercase();
+
+        let register = match lower.as_str() {
+            "true" => return Ok((Node::Bool(true), ExprType::Bool)),
+            "false" => return Ok((Node::Bool(false), ExprType::Bool)),
+            "ram" | "stack" => {
+                let array = if lower == "ram" { Array::Ram } else { Array::Stack };
+                self.expect("[")?;
+                let index_column = self.column();
+                let index = Self::expect_type(self.logic(0)?, ExprType::Number, index_column, "an index")?;
+                self.expect("]")?;
+                return Ok((Node::Index { array, index: Box::new(index), column }, ExprType::Number));
+            },
+            "i" | "i_register" => Register::I,
+            "pc" => Register::Pc,
+            "sp" | "stack_pointer" => Register::Sp,
+            "dt" | "delay_timer" => Register::Dt,
+            "st" | "sound_timer" => Register::St,
+            _ => match lower.strip_prefix('v').and_then(|digit| u8::from_str_radix(digit, 16).ok()) {
+                Some(x) if lower.len() == 2 => Register::V(x),
+                _ => match self.symbols.addr_of(name) {
+                    Some(addr) => return Ok((Node::Number(addr as i64), ExprType::Number)),
+                    None => return Err(error(column, format!("unknown name [{}], expected V0-VF, I, PC, SP, DT, ST, ram[...], stack[...] or a label", name))),
+                },
+            },
+        };
+        Ok((Node::Register(register), ExprType::Number))
+    }
+}
+
+// EXPRESSIONS
+
+#[derive(Clone, Debug)]
+pub struct Expression {
+    text: String,
+    root: Node,
+    value_type: ExprType,
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Self, ExprError> {
+        Self::parse_with(text, &Symbols::new())
+    }
+
+    // labels of the symbols can be used as names
+    pub fn parse_with(text: &str, symbols: &Symbols) -> Result<Self, ExprError> {
+        let mut parser = Parser { tokens: tokenize(text, symbols)?, position: 0, symbols };
+        if *parser.peek() == Token::End {
+            return Err(error(1, "empty expression".to_string()));
+        }
+
+        let (root, value_type) = parser.logic(0)?;
+        let (token, column) = parser.next();
+        if token != Token::End {
+            return Err(error(column, format!("unexpected [{}] after the expression", token)));
+        }
+
+        Ok(Expression { text: text.trim().to_string(), root, value_type })
+    }
+
+    // an expression that is true or false, for breakpoints
+    pub fn parse_condition(text: &str, symbols: &Symbols) -> Result<Self, ExprError> {
+        let expression = Self::parse_with(text, symbols)?;
+        if expression.value_type != ExprType::Bool {
+            return Err(error(1, format!("a condition must be a bool, found a {}", expression.value_type)));
+        }
+        Ok(expression)
+    }
+
+    pub fn value_type(&self) -> ExprType {
+        self.value_type
+    }
+
+    // reads the state without side effects, watchpoints don't trigger
+    pub fn evaluate(&self, emulator: &Emu) -> Result<ExprValue, ExprError> {
+        evaluate(&self.root, emulator)
+    }
+
+    pub fn is_true(&self, emulator: &Emu) -> Result<bool, ExprError> {
+        Ok(self.evaluate(emulator)? == ExprValue::Bool(true))
+    }
+}
+
+fn number(node: &Node, emulator: &Emu) -> Result<i64, ExprError> {
+    match evaluate(node, emulator)? {
+        ExprValue::Number(value) => Ok(value),
+        // ruled out by the type check
+        ExprValue::Bool(value) => Ok(value as i64),
+    }
+}
+
+fn boolean(node: &Node, emulator: &Emu) -> Result<bool, ExprError> {
+    match evaluate(node, emulator)? {
+        ExprValue::Bool(value) => Ok(value),
+        ExprValue::Number(value) => Ok(value != 0),
+    }
+}
+
+fn evaluate(node: &Node, emulator: &Emu) -> Result<ExprValue, ExprError> {
+    let value = match node {
+        Node::Number(value) => ExprValue::Number(*value),
+        Node::Bool(value) => ExprValue::Bool(*value),
+        Node::Register(register) => ExprValue::Number(emulator.register(*register) as i64),
+        Node::Index { array, index, column } => {
+            let index = number(index, emulator)?;
+            let value = match array {
+                Array::Ram => usize::try_from(index).ok().and_then(|index| emulator.ram.get(index)).map(|value| *value as i64),
+                Array::Stack => usize::try_from(index).ok().and_then(|index| emulator.stack.get(index)).map(|value| *value as i64),
+            };
+            let (name, size) = match array {
+                Array::Ram => ("ram", RAM_SIZE),
+                Array::Stack => ("stack", STACK_SIZE),
+            };
+            ExprValue::Number(value.ok_or_else(|| error(*column, format!("{}[{}] is out of range, the size is {}", name, index, size)))?)
+        },
+        Node::Unary { op, operand } => match *op {
+            "!" => ExprValue::Bool(!boolean(operand, emulator)?),
+            "-" => ExprValue::Number(number(operand, emulator)?.wrapping_neg()),
+            _ => ExprValue::Number(!number(operand, emulator)?),
+        },
+        Node::In { value, start, end, inclusive } => {
+            let (value, start, end) = (number(value, emulator)?, number(start, emulator)?, number(end, emulator)?);
+            ExprValue::Bool(if *inclusive { (start..=end).contains(&value) } else { (start..end).contains(&value) })
+        },
+        // && and || only evaluate the right side when needed
+        Node::Binary { op: "&&", left, right, .. } => ExprValue::Bool(boolean(left, emulator)? && boolean(right, emulator)?),
+        Node::Binary { op: "||", left, right, .. } => ExprValue::Bool(boolean(left, emulator)? || boolean(right, emulator)?),
+        Node::Binary { op, left, right, column } => {
+            let (a, b) = (number(left, emulator)?, number(right, emulator)?);
+            match *op {
+                "==" => ExprValue::Bool(a == b),
+                "!=" => ExprValue::Bool(a != b),
+                "<" => ExprValue::Bool(a < b),
+                "<=" => ExprValue::Bool(a <= b),
+                ">" => ExprValue::Bool(a > b),
+                ">=" => ExprValue::Bool(a >= b),
+                "/" | "%" if b == 0 => return Err(error(*column, "division by zero".to_string())),
+                "/" => ExprValue::Number(a.wrapping_div(b)),
+                "%" => ExprValue::Number(a.wrapping_rem(b)),
+                "<<" | ">>" if !(0..64).contains(&b) => {
+                    return Err(error(*column, format!("shift by {} is out of range (0 to 63)", b)));
+                },
+                "<<" => ExprValue::Number(a << b),
+                ">>" => ExprValue::Number(a >> b),
+                "+" => ExprValue::Number(a.wrapping_add(b)),
+                "-" => ExprValue::Number(a.wrapping_sub(b)),
+                "*" => ExprValue::Number(a.wrapping_mul(b)),
+                "&" => ExprValue::Number(a & b),
+                "|" => ExprValue::Number(a | b),
+                _ => ExprValue::Number(a ^ b),
+            }
+        },
+    };
+    Ok(value)
+}
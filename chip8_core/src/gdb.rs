@@ -0,0 +1,467 @@
+// GDB remote serial protocol stub. A debugger front-end connects over
+// TCP and controls the emulator through the usual packets:
+//
+//   g G p P       registers, numbered like ALL_REGISTERS: V0-VF are 0-15,
+//                 then I, PC, SP, DT, ST. V0-VF, SP, DT and ST are 8 bit,
+//                 I and PC 16 bit little endian
+//   m M           memory, addresses index Emu::ram
+//   Z0 z0 Z1 z1   breakpoints
+//   Z2-Z4 z2-z4   write, read and access watchpoints
+//   s c           step and continue, ctrl-c interrupts a continue
+//   ? D k         stop reason, detach, kill
+//
+// The register layout is announced through qXfer:features:read:target.xml.
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::*;
+
+const PACKET_SIZE: usize = 0x1000;
+const INTERRUPT: u8 = 0x03;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" regnum="0"/>
+    <reg name="v1" bitsize="8"/>
+    <reg name="v2" bitsize="8"/>
+    <reg name="v3" bitsize="8"/>
+    <reg name="v4" bitsize="8"/>
+    <reg name="v5" bitsize="8"/>
+    <reg name="v6" bitsize="8"/>
+    <reg name="v7" bitsize="8"/>
+    <reg name="v8" bitsize="8"/>
+    <reg name="v9" bitsize="8"/>
+    <reg name="va" bitsize="8"/>
+    <reg name="vb" bitsize="8"/>
+    <reg name="vc" bitsize="8"/>
+    <reg name="vd" bitsize="8"/>
+    <reg name="ve" bitsize="8"/>
+    <reg name="vf" bitsize="8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8"/>
+    <reg name="dt" bitsize="8"/>
+    <reg name="st" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+// what the front-end asked for
+enum Resume {
+    Step,
+    Continue,
+}
+
+pub struct GdbStub {
+    stream: TcpStream,
+    // instructions per 60 Hz frame while continuing
+    pub cycles_per_frame: u32,
+    // continue as fast as possible instead of in real time
+    pub unthrottled: bool,
+    no_ack: bool,
+}
+
+impl GdbStub {
+    pub fn new(stream: TcpStream, cycles_per_frame: u32) -> Self {
+        // packets are tiny, waiting to fill a segment only adds latency
+        let _ = stream.set_nodelay(true);
+
+        GdbStub {
+            stream,
+            cycles_per_frame: cycles_per_frame.max(1),
+            unthrottled: false,
+            no_ack: false,
+        }
+    }
+
+    // serves the front-end until it detaches, kills the emulator or
+    // closes the connection
+    pub fn run(&mut self, emulator: &mut Emu) -> io::Result<()> {
+        emulator.debugger.cycles_per_frame = self.cycles_per_frame;
+
+        loop {
+            let Some(packet) = self.read_packet()? else {
+                return Ok(());
+            };
+
+            let reply = match packet.as_bytes().first() {
+                Some(b'D') => {
+                    // the ROM keeps running without the front-end
+                    emulator.debugger.clear();
+                    self.send_packet("OK")?;
+                    return Ok(());
+                },
+                Some(b'k') => return Ok(()),
+                Some(b's') | Some(b'c') => {
+                    let resume = if packet.starts_with('s') { Resume::Step } else { Resume::Continue };
+                    // an optional address to resume at
+                    if let Some(addr) = packet.get(1..).filter(|addr| !addr.is_empty()) {
+                        match u16::from_str_radix(addr, 16) {
+                            Ok(addr) => emulator.programm_counter = addr,
+                            Err(_) => {
+                                self.send_packet("E01")?;
+                                continue;
+                            }
+                        }
+                    }
+                    self.resume(emulator, resume)?
+                },
+                _ => handle_packet(&packet, emulator),
+            };
+
+            self.send_packet(&reply)?;
+        }
+    }
+
+    fn resume(&mut self, emulator: &mut Emu, resume: Resume) -> io::Result<String> {
+        if let Resume::Step = resume {
+            return Ok(stop_reply(emulator.step_into()));
+        }
+
+        let frame_duration = Duration::from_secs(1) / FRAMES_PER_SECOND;
+        let mut first = true;
+
+        loop {
+            let frame_start = Instant::now();
+
+            // run_until skips the breakpoint at PC, that is only right
+            // for the first frame of a continue
+            if !first && emulator.breakpoint_hit(emulator.programm_counter) {
+                return Ok(stop_reply(StopReason::Breakpoint { pc: emulator.programm_counter }));
+            }
+            first = false;
+
+            match emulator.run_until(self.cycles_per_frame as u64, |_| false) {
+                StopReason::CycleLimit => {},
+                reason => return Ok(stop_reply(reason)),
+            }
+
+            if self.poll_interrupt()? {
+                return Ok("S02".to_string());
+            }
+
+            if !self.unthrottled {
+                if let Some(rest) = frame_duration.checked_sub(frame_start.elapsed()) {
+                    thread::sleep(rest);
+                }
+            }
+        }
+    }
+
+    // true if the front-end sent ctrl-c
+    fn poll_interrupt(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut byte = [0];
+        let result = self.stream.read(&mut byte);
+        self.stream.set_nonblocking(false)?;
+
+        match result {
+            Ok(0) => Err(ErrorKind::UnexpectedEof.into()),
+            Ok(_) => Ok(byte[0] == INTERRUPT),
+            Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    // PACKETS
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    // the next valid packet, None once the connection is closed
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // acks and interrupts outside of a packet are ignored
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'$') => break,
+                    Some(_) => {},
+                }
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+
+            let mut checksum = [0; 2];
+            self.stream.read_exact(&mut checksum)?;
+            let valid = std::str::from_utf8(&checksum).ok()
+                .and_then(|text| u8::from_str_radix(text, 16).ok())
+                == Some(checksum_of(&data));
+
+            if !self.no_ack {
+                self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if !valid {
+                continue;
+            }
+
+            let packet = String::from_utf8_lossy(&unescape(&data)).into_owned();
+            if packet == "QStartNoAckMode" {
+                self.send_packet("OK")?;
+                self.no_ack = true;
+                continue;
+            }
+            return Ok(Some(packet));
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        let data = escape(data.as_bytes());
+        let mut packet = vec![b'$'];
+        packet.extend_from_slice(&data);
+        packet.extend_from_slice(format!("#{:02x}", checksum_of(&data)).as_bytes());
+
+        loop {
+            self.stream.write_all(&packet)?;
+            if self.no_ack {
+                return Ok(());
+            }
+            match self.read_byte()? {
+                Some(b'-') => continue,     // resend
+                Some(_) => return Ok(()),
+                None => return Err(ErrorKind::UnexpectedEof.into()),
+            }
+        }
+    }
+}
+
+// replies to everything that doesn't resume the emulator
+fn handle_packet(packet: &str, emulator: &mut Emu) -> String {
+    // the packet was decoded lossily, the first character can be longer than a byte
+    let (command, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+
+    let reply = match command {
+        "?" => Some("S05".to_string()),
+        "g" => Some(ALL_REGISTERS.iter().map(|register| register_hex(emulator, *register)).collect()),
+        "G" => write_all_registers(emulator, args),
+        "p" => usize::from_str_radix(args, 16).ok()
+            .and_then(|number| ALL_REGISTERS.get(number))
+            .map(|register| register_hex(emulator, *register)),
+        "P" => write_register(emulator, args),
+        "m" => read_memory(emulator, args),
+        "M" => write_memory(emulator, args),
+        "Z" => set_breakpoint(emulator, args, true),
+        "z" => set_breakpoint(emulator, args, false),
+        "H" => Some("OK".to_string()),
+        "T" => Some("OK".to_string()),  // the only thread is alive
+        "q" => return query(packet),
+        // unsupported packets get an empty reply
+        _ => return String::new(),
+    };
+
+    reply.unwrap_or_else(|| "E01".to_string())
+}
+
+fn query(packet: &str) -> String {
+    if packet.starts_with("qSupported") {
+        return format!("PacketSize={:x};qXfer:features:read+;QStartNoAckMode+", PACKET_SIZE);
+    }
+    if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+        return read_target_xml(range).unwrap_or_else(|| "E01".to_string());
+    }
+
+    match packet {
+        "qAttached" => "1".to_string(),
+        "qC" => "QC1".to_string(),
+        "qfThreadInfo" => "m1".to_string(),
+        "qsThreadInfo" => "l".to_string(),
+        _ => String::new(),
+    }
+}
+
+// offset,length of the target description
+fn read_target_xml(range: &str) -> Option<String> {
+    let (offset, length) = range.split_once(',')?;
+    let offset = usize::from_str_radix(offset, 16).ok()?;
+    let length = usize::from_str_radix(length, 16).ok()?;
+
+    let xml = TARGET_XML.as_bytes();
+    let start = offset.min(xml.len());
+    let end = offset.saturating_add(length).min(xml.len());
+    let more = if end < xml.len() { "m" } else { "l" };
+    Some(format!("{}{}", more, String::from_utf8_lossy(&xml[start..end])))
+}
+
+// REGISTERS
+
+fn register_size(register: Register) -> usize {
+    match register {
+        Register::I | Register::Pc => 2,
+        _ => 1,
+    }
+}
+
+fn register_hex(emulator: &Emu, register: Register) -> String {
+    let bytes = emulator.register(register).to_le_bytes();
+    to_hex(&bytes[..register_size(register)])
+}
+
+fn parse_register(hex: &str, register: Register) -> Option<u16> {
+    let bytes = from_hex(hex)?;
+    if bytes.len() != register_size(register) {
+        return None;
+    }
+    Some(bytes.iter().rev().fold(0, |value, byte| (value << 8) | *byte as u16))
+}
+
+fn write_register(emulator: &mut Emu, args: &str) -> Option<String> {
+    let (number, value) = args.split_once('=')?;
+    let register = *ALL_REGISTERS.get(usize::from_str_radix(number, 16).ok()?)?;
+    let value = parse_register(value, register)?;
+    emulator.set_register(register, value);
+    Some("OK".to_string())
+}
+
+fn write_all_registers(emulator: &mut Emu, args: &str) -> Option<String> {
+    // parse everything first, a bad packet changes nothing
+    let mut values = Vec::new();
+    let mut rest = args;
+    for register in ALL_REGISTERS {
+        let digits = register_size(register) * 2;
+        let value = parse_register(rest.get(..digits)?, register)?;
+        values.push((register, value));
+        rest = &rest[digits..];
+    }
+
+    for (register, value) in values {
+        emulator.set_register(register, value);
+    }
+    Some("OK".to_string())
+}
+
+// MEMORY
+
+// addr,length
+fn memory_range(args: &str) -> Option<(usize, usize)> {
+    let (addr, length) = args.split_once(',')?;
+    let addr = usize::from_str_radix(addr, 16).ok()?;
+    let length = usize::from_str_radix(length, 16).ok()?;
+    if addr.checked_add(length)? > RAM_SIZE {
+        return None;
+    }
+    Some((addr, length))
+}
+
+fn read_memory(emulator: &Emu, args: &str) -> Option<String> {
+    let (addr, length) = memory_range(args)?;
+    Some(to_hex(&emulator.ram[addr..addr + length]))
+}
+
+fn write_memory(emulator: &mut Emu, args: &str) -> Option<String> {
+    let (range, data) = args.split_once(':')?;
+    let (addr, length) = memory_range(range)?;
+    let data = from_hex(data)?;
+    if data.len() != length {
+        return None;
+    }
+    emulator.ram[addr..addr + length].copy_from_slice(&data);
+    Some("OK".to_string())
+}
+
+// BREAKPOINTS
+
+// type,addr,kind
+fn set_breakpoint(emulator: &mut Emu, args: &str, insert: bool) -> Option<String> {
+    let mut fields = args.split(',');
+    let kind = fields.next()?;
+    let addr = u16::from_str_radix(fields.next()?, 16).ok()?;
+    // the length of a watchpoint, ignored for breakpoints
+    let length = u16::from_str_radix(fields.next().unwrap_or("1"), 16).ok()?.max(1);
+    let end = addr.checked_add(length - 1)?;
+
+    let debugger = &mut emulator.debugger;
+    let watch = match kind {
+        "0" | "1" => {
+            if insert {
+                debugger.breakpoints.insert(addr);
+            } else {
+                debugger.breakpoints.remove(&addr);
+            }
+            return Some("OK".to_string());
+        },
+        "2" => WatchKind::Write,
+        "3" => WatchKind::Read,
+        "4" => WatchKind::Access,
+        _ => return Some(String::new()),
+    };
+
+    let watchpoint = MemoryWatchpoint { start: addr, end, kind: watch };
+    if insert {
+        debugger.memory_watchpoints.push(watchpoint);
+    } else {
+        debugger.memory_watchpoints.retain(|other| *other != watchpoint);
+    }
+    Some("OK".to_string())
+}
+
+fn stop_reply(reason: StopReason) -> String {
+    match reason {
+        StopReason::MemoryWrite { addr, .. } => format!("T05watch:{:x};", addr),
+        StopReason::MemoryRead { addr, .. } => format!("T05rwatch:{:x};", addr),
+        StopReason::Exited => "W00".to_string(),
+        StopReason::Fault(_) => "S04".to_string(),  // SIGILL
+        _ => "S05".to_string(),     // SIGTRAP
+    }
+}
+
+// ENCODING
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+// '#', '$', '}' and '*' are sent as '}' followed by the byte xor 0x20
+fn escape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for byte in data {
+        if matches!(byte, b'#' | b'$' | b'}' | b'*') {
+            out.push(b'}');
+            out.push(byte ^ 0x20);
+        } else {
+            out.push(*byte);
+        }
+    }
+    out
+}
+
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'}' => out.extend(bytes.next().map(|byte| byte ^ 0x20)),
+            _ => out.push(*byte),
+        }
+    }
+    out
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
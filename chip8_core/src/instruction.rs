@@ -0,0 +1,342 @@
+use std::fmt;
+
+// Every opcode understood by the emulator (CHIP-8, SUPER-CHIP and XO-CHIP).
+// x and y are register indices, nn is a byte, n a nibble and nnn an address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    Nop,                            // 0000
+    Cls,                            // 00E0
+    Ret,                            // 00EE
+    ScrollDown(u8),                 // 00CN
+    ScrollUp(u8),                   // 00DN
+    ScrollRight,                    // 00FB
+    ScrollLeft,                     // 00FC
+    Exit,                           // 00FD
+    Lores,                          // 00FE
+    Hires,                          // 00FF
+    Jump(u16),                      // 1NNN
+    Call(u16),                      // 2NNN
+    SkipEqImm(u8, u8),              // 3XNN
+    SkipNeImm(u8, u8),              // 4XNN
+    SkipEqReg(u8, u8),              // 5XY0
+    SaveRange(u8, u8),              // 5XY2
+    LoadRange(u8, u8),              // 5XY3
+    LoadImm(u8, u8),                // 6XNN
+    AddImm(u8, u8),                 // 7XNN
+    Move(u8, u8),                   // 8XY0
+    Or(u8, u8),                     // 8XY1
+    And(u8, u8),                    // 8XY2
+    Xor(u8, u8),                    // 8XY3
+    Add(u8, u8),                    // 8XY4
+    Sub(u8, u8),                    // 8XY5
+    ShiftRight(u8, u8),             // 8XY6
+    SubReverse(u8, u8),             // 8XY7
+    ShiftLeft(u8, u8),              // 8XYE
+    SkipNeReg(u8, u8),              // 9XY0
+    LoadI(u16),                     // ANNN
+    JumpOffset(u8, u16),            // BNNN, x is only used with the jump quirk
+    Random(u8, u8),                 // CXNN
+    Draw(u8, u8, u8),               // DXYN
+    SkipKey(u8),                    // EX9E
+    SkipNotKey(u8),                 // EXA1
+    LoadLongI(u16),                 // F000 NNNN
+    Plane(u8),                      // FN01
+    Audio,                          // F002
+    GetDelay(u8),                   // FX07
+    WaitKey(u8),                    // FX0A
+    SetDelay(u8),                   // FX15
+    SetSound(u8),                   // FX18
+    AddI(u8),                       // FX1E
+    Font(u8),                       // FX29
+    BigFont(u8),                    // FX30
+    Bcd(u8),                        // FX33
+    Pitch(u8),                      // FX3A
+    Store(u8),                      // FX55
+    Load(u8),                       // FX65
+    SaveFlags(u8),                  // FX75
+    LoadFlags(u8),                  // FX85
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown opcode {:04X}", self.opcode)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+// F000 takes its address from the word after the opcode. decode() only
+// sees one word, so it returns LoadLongI(0) and the caller has to read
+// the address itself (see Instruction::size).
+pub fn decode(op: u16) -> Result<Instruction, DecodeError> {
+    let digit1 = (op & 0xF000) >> 12;
+    let digit2 = (op & 0x0F00) >> 8;
+    let digit3 = (op & 0x00F0) >> 4;
+    let digit4 = op & 0x000F;
+
+    let x = digit2 as u8;
+    let y = digit3 as u8;
+
+    let nnn = op & 0xFFF;
+    let nn = (op & 0xFF) as u8;
+    let n = (op & 0xF) as u8;
+
+    let instruction = match (digit1, digit2, digit3, digit4) {
+        (0, 0, 0, 0) => Instruction::Nop,
+        (0, 0, 0xE, 0) => Instruction::Cls,
+        (0, 0, 0xE, 0xE) => Instruction::Ret,
+        (0, 0, 0xC, _) => Instruction::ScrollDown(n),
+        (0, 0, 0xD, _) => Instruction::ScrollUp(n),
+        (0, 0, 0xF, 0xB) => Instruction::ScrollRight,
+        (0, 0, 0xF, 0xC) => Instruction::ScrollLeft,
+        (0, 0, 0xF, 0xD) => Instruction::Exit,
+        (0, 0, 0xF, 0xE) => Instruction::Lores,
+        (0, 0, 0xF, 0xF) => Instruction::Hires,
+        (1, _, _, _) => Instruction::Jump(nnn),
+        (2, _, _, _) => Instruction::Call(nnn),
+        (3, _, _, _) => Instruction::SkipEqImm(x, nn),
+        (4, _, _, _) => Instruction::SkipNeImm(x, nn),
+        (5, _, _, 0) => Instruction::SkipEqReg(x, y),
+        (5, _, _, 2) => Instruction::SaveRange(x, y),
+        (5, _, _, 3) => Instruction::LoadRange(x, y),
+        (6, _, _, _) => Instruction::LoadImm(x, nn),
+        (7, _, _, _) => Instruction::AddImm(x, nn),
+        (8, _, _, 0) => Instruction::Move(x, y),
+        (8, _, _, 1) => Instruction::Or(x, y),
+        (8, _, _, 2) => Instruction::And(x, y),
+        (8, _, _, 3) => Instruction::Xor(x, y),
+        (8, _, _, 4) => Instruction::Add(x, y),
+        (8, _, _, 5) => Instruction::Sub(x, y),
+        (8, _, _, 6) => Instruction::ShiftRight(x, y),
+        (8, _, _, 7) => Instruction::SubReverse(x, y),
+        (8, _, _, 0xE) => Instruction::ShiftLeft(x, y),
+        (9, _, _, 0) => Instruction::SkipNeReg(x, y),
+        (0xA, _, _, _) => Instruction::LoadI(nnn),
+        (0xB, _, _, _) => Instruction::JumpOffset(x, nnn),
+        (0xC, _, _, _) => Instruction::Random(x, nn),
+        (0xD, _, _, _) => Instruction::Draw(x, y, n),
+        (0xE, _, 9, 0xE) => Instruction::SkipKey(x),
+        (0xE, _, 0xA, 1) => Instruction::SkipNotKey(x),
+        (0xF, 0, 0, 0) => Instruction::LoadLongI(0),
+        (0xF, _, 0, 1) => Instruction::Plane(x),
+        (0xF, 0, 0, 2) => Instruction::Audio,
+        (0xF, _, 0, 7) => Instruction::GetDelay(x),
+        (0xF, _, 0, 0xA) => Instruction::WaitKey(x),
+        (0xF, _, 1, 5) => Instruction::SetDelay(x),
+        (0xF, _, 1, 8) => Instruction::SetSound(x),
+        (0xF, _, 1, 0xE) => Instruction::AddI(x),
+        (0xF, _, 2, 9) => Instruction::Font(x),
+        (0xF, _, 3, 0) => Instruction::BigFont(x),
+        (0xF, _, 3, 3) => Instruction::Bcd(x),
+        (0xF, _, 3, 0xA) => Instruction::Pitch(x),
+        (0xF, _, 5, 5) => Instruction::Store(x),
+        (0xF, _, 6, 5) => Instruction::Load(x),
+        (0xF, _, 7, 5) => Instruction::SaveFlags(x),
+        (0xF, _, 8, 5) => Instruction::LoadFlags(x),
+        (_, _, _, _) => return Err(DecodeError { opcode: op }),
+    };
+
+    Ok(instruction)
+}
+
+impl Instruction {
+    // length in bytes
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LoadLongI(_) => 4,
+            _ => 2,
+        }
+    }
+
+    // the opcode pattern like DXYN, groups instructions in the profiler
+    pub fn pattern(&self) -> &'static str {
+        use Instruction::*;
+
+        match self {
+            Nop => "0000",
+            Cls => "00E0",
+            Ret => "00EE",
+            ScrollDown(_) => "00CN",
+            ScrollUp(_) => "00DN",
+            ScrollRight => "00FB",
+            ScrollLeft => "00FC",
+            Exit => "00FD",
+            Lores => "00FE",
+            Hires => "00FF",
+            Jump(_) => "1NNN",
+            Call(_) => "2NNN",
+            SkipEqImm(..) => "3XNN",
+            SkipNeImm(..) => "4XNN",
+            SkipEqReg(..) => "5XY0",
+            SaveRange(..) => "5XY2",
+            LoadRange(..) => "5XY3",
+            LoadImm(..) => "6XNN",
+            AddImm(..) => "7XNN",
+            Move(..) => "8XY0",
+            Or(..) => "8XY1",
+            And(..) => "8XY2",
+            Xor(..) => "8XY3",
+            Add(..) => "8XY4",
+            Sub(..) => "8XY5",
+            ShiftRight(..) => "8XY6",
+            SubReverse(..) => "8XY7",
+            ShiftLeft(..) => "8XYE",
+            SkipNeReg(..) => "9XY0",
+            LoadI(_) => "ANNN",
+            JumpOffset(..) => "BNNN",
+            Random(..) => "CXNN",
+            Draw(..) => "DXYN",
+            SkipKey(_) => "EX9E",
+            SkipNotKey(_) => "EXA1",
+            LoadLongI(_) => "F000",
+            Plane(_) => "FN01",
+            Audio => "F002",
+            GetDelay(_) => "FX07",
+            WaitKey(_) => "FX0A",
+            SetDelay(_) => "FX15",
+            SetSound(_) => "FX18",
+            AddI(_) => "FX1E",
+            Font(_) => "FX29",
+            BigFont(_) => "FX30",
+            Bcd(_) => "FX33",
+            Pitch(_) => "FX3A",
+            Store(_) => "FX55",
+            Load(_) => "FX65",
+            SaveFlags(_) => "FX75",
+            LoadFlags(_) => "FX85",
+        }
+    }
+
+    // opposite of decode(), includes the address word of F000 NNNN
+    pub fn to_bytes(&self) -> Vec<u8> {
+        use Instruction::*;
+
+        let xy = |digit: u16, x: u8, y: u8, n: u16| (digit << 12) | ((x as u16) << 8) | ((y as u16) << 4) | n;
+        let xnn = |digit: u16, x: u8, nn: u8| (digit << 12) | ((x as u16) << 8) | nn as u16;
+        let fx = |x: u8, nn: u16| 0xF000 | ((x as u16) << 8) | nn;
+
+        let op: u16 = match *self {
+            Nop => 0x0000,
+            Cls => 0x00E0,
+            Ret => 0x00EE,
+            ScrollDown(n) => 0x00C0 | n as u16,
+            ScrollUp(n) => 0x00D0 | n as u16,
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            Lores => 0x00FE,
+            Hires => 0x00FF,
+            Jump(nnn) => 0x1000 | nnn,
+            Call(nnn) => 0x2000 | nnn,
+            SkipEqImm(x, nn) => xnn(3, x, nn),
+            SkipNeImm(x, nn) => xnn(4, x, nn),
+            SkipEqReg(x, y) => xy(5, x, y, 0),
+            SaveRange(x, y) => xy(5, x, y, 2),
+            LoadRange(x, y) => xy(5, x, y, 3),
+            LoadImm(x, nn) => xnn(6, x, nn),
+            AddImm(x, nn) => xnn(7, x, nn),
+            Move(x, y) => xy(8, x, y, 0),
+            Or(x, y) => xy(8, x, y, 1),
+            And(x, y) => xy(8, x, y, 2),
+            Xor(x, y) => xy(8, x, y, 3),
+            Add(x, y) => xy(8, x, y, 4),
+            Sub(x, y) => xy(8, x, y, 5),
+            ShiftRight(x, y) => xy(8, x, y, 6),
+            SubReverse(x, y) => xy(8, x, y, 7),
+            ShiftLeft(x, y) => xy(8, x, y, 0xE),
+            SkipNeReg(x, y) => xy(9, x, y, 0),
+            LoadI(nnn) => 0xA000 | nnn,
+            JumpOffset(_, nnn) => 0xB000 | nnn,
+            Random(x, nn) => xnn(0xC, x, nn),
+            Draw(x, y, n) => xy(0xD, x, y, n as u16),
+            SkipKey(x) => xnn(0xE, x, 0x9E),
+            SkipNotKey(x) => xnn(0xE, x, 0xA1),
+            LoadLongI(_) => 0xF000,
+            Plane(n) => fx(n, 0x01),
+            Audio => 0xF002,
+            GetDelay(x) => fx(x, 0x07),
+            WaitKey(x) => fx(x, 0x0A),
+            SetDelay(x) => fx(x, 0x15),
+            SetSound(x) => fx(x, 0x18),
+            AddI(x) => fx(x, 0x1E),
+            Font(x) => fx(x, 0x29),
+            BigFont(x) => fx(x, 0x30),
+            Bcd(x) => fx(x, 0x33),
+            Pitch(x) => fx(x, 0x3A),
+            Store(x) => fx(x, 0x55),
+            Load(x) => fx(x, 0x65),
+            SaveFlags(x) => fx(x, 0x75),
+            LoadFlags(x) => fx(x, 0x85),
+        };
+
+        let mut bytes = op.to_be_bytes().to_vec();
+        if let LoadLongI(nnnn) = *self {
+            bytes.extend_from_slice(&nnnn.to_be_bytes());
+        }
+        bytes
+    }
+}
+
+// mnemonics in the common Cowgod syntax, extended for SUPER-CHIP and XO-CHIP
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Nop => write!(f, "NOP"),
+            Instruction::Cls => write!(f, "CLS"),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::ScrollDown(n) => write!(f, "SCD {}", n),
+            Instruction::ScrollUp(n) => write!(f, "SCU {}", n),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::Lores => write!(f, "LOW"),
+            Instruction::Hires => write!(f, "HIGH"),
+            Instruction::Jump(nnn) => write!(f, "JP 0x{:03X}", nnn),
+            Instruction::Call(nnn) => write!(f, "CALL 0x{:03X}", nnn),
+            Instruction::SkipEqImm(x, nn) => write!(f, "SE V{:X}, 0x{:02X}", x, nn),
+            Instruction::SkipNeImm(x, nn) => write!(f, "SNE V{:X}, 0x{:02X}", x, nn),
+            Instruction::SkipEqReg(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::SaveRange(x, y) => write!(f, "LD [I], V{:X}-V{:X}", x, y),
+            Instruction::LoadRange(x, y) => write!(f, "LD V{:X}-V{:X}, [I]", x, y),
+            Instruction::LoadImm(x, nn) => write!(f, "LD V{:X}, 0x{:02X}", x, nn),
+            Instruction::AddImm(x, nn) => write!(f, "ADD V{:X}, 0x{:02X}", x, nn),
+            Instruction::Move(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::Add(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubReverse(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNeReg(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LoadI(nnn) => write!(f, "LD I, 0x{:03X}", nnn),
+            Instruction::JumpOffset(_, nnn) => write!(f, "JP V0, 0x{:03X}", nnn),
+            Instruction::Random(x, nn) => write!(f, "RND V{:X}, 0x{:02X}", x, nn),
+            Instruction::Draw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKey(x) => write!(f, "SKP V{:X}", x),
+            Instruction::SkipNotKey(x) => write!(f, "SKNP V{:X}", x),
+            Instruction::LoadLongI(nnnn) => write!(f, "LD I, LONG 0x{:04X}", nnnn),
+            Instruction::Plane(n) => write!(f, "PLANE {}", n),
+            Instruction::Audio => write!(f, "AUDIO"),
+            Instruction::GetDelay(x) => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey(x) => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelay(x) => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSound(x) => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddI(x) => write!(f, "ADD I, V{:X}", x),
+            Instruction::Font(x) => write!(f, "LD F, V{:X}", x),
+            Instruction::BigFont(x) => write!(f, "LD HF, V{:X}", x),
+            Instruction::Bcd(x) => write!(f, "LD B, V{:X}", x),
+            Instruction::Pitch(x) => write!(f, "PITCH V{:X}", x),
+            Instruction::Store(x) => write!(f, "LD [I], V{:X}", x),
+            Instruction::Load(x) => write!(f, "LD V{:X}, [I]", x),
+            Instruction::SaveFlags(x) => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags(x) => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
@@ -1,23 +1,99 @@
-use rand::Rng;
-
 mod font;
 use font::*;
 
-pub const RAM_SIZE: usize = 4096;
+mod quirks;
+pub use quirks::*;
+
+mod error;
+pub use error::*;
+
+mod rng;
+pub use rng::*;
+
+mod instruction;
+pub use instruction::*;
+
+mod savestate;
+pub use savestate::*;
+
+mod rewind;
+pub use rewind::*;
+
+mod movie;
+pub use movie::*;
+
+mod debugger;
+pub use debugger::*;
+
+mod trace;
+pub use trace::*;
+
+mod expr;
+pub use expr::*;
+
+mod symbols;
+pub use symbols::*;
+
+mod profile;
+pub use profile::*;
+
+pub mod disasm;
+pub mod asm;
+pub mod screenshot;
+pub mod gdb;
+pub mod dap;
+pub mod tracediff;
+
+#[cfg(test)]
+mod opcode_tests;
+#[cfg(test)]
+mod asm_tests;
+#[cfg(test)]
+mod rewind_tests;
+
+pub const RAM_SIZE: usize = 65536;     // XO-CHIP address space
 pub const REGISTER_COUNT: usize = 16;
 pub const STACK_SIZE: usize = 16;
 pub const NUM_KEYS: usize = 16;
+pub const RPL_FLAG_COUNT: usize = 16;
+pub const AUDIO_PATTERN_SIZE: usize = 16;
 
 pub const SCREEN_HEIGHT: usize = 32;
 pub const SCREEN_WIDTH: usize = 64;
 
+// SUPER-CHIP high resolution mode
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
+pub const HIRES_SCREEN_WIDTH: usize = 128;
+
+// the screen buffer is always big enough for hires mode
+pub const SCREEN_BUFFER_SIZE: usize = HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT;
+
+// XO-CHIP bitplanes. Every pixel of the screen buffer stores
+// one bit per plane, so a pixel value ranges from 0 to 3
+pub const NUM_PLANES: usize = 2;
+const ALL_PLANES: u8 = 0b11;
+
+const DEFAULT_PITCH: u8 = 64;
+
 const START_ADDR: u16 = 0x200;
 
+// the timers and the display run at 60 Hz
+pub const FRAMES_PER_SECOND: u32 = 60;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameResult {
+    pub visuals_modified: bool,
+    pub buzzer_active: bool,    // the sound timer was running during this frame
+    pub stop: Option<StopReason>,   // a breakpoint or watchpoint ended the frame early
+}
+
 pub struct Emu {
     pub programm_counter: u16,  // to keep count at which instruction we are
     pub ram: [u8; RAM_SIZE],
     
-    pub screen: [bool; SCREEN_WIDTH * SCREEN_HEIGHT],
+    pub screen: [u8; SCREEN_BUFFER_SIZE],
+    pub hires: bool,
+    pub plane_mask: u8,     // planes affected by drawing, selected by FN01
     pressed_keys: [bool; NUM_KEYS],
 
     pub registers: [u8; REGISTER_COUNT],
@@ -28,17 +104,43 @@ pub struct Emu {
 
     pub delay_timer: u8,    // performs any action after finished
     pub sound_timer: u8,    // plays sound after finished
+    vblank: bool,           // no sprite was drawn since the last timer tick, for the display wait quirk
+
+    pub rpl_flags: [u8; RPL_FLAG_COUNT],  // SUPER-CHIP persistent flags
+
+    pub audio_pattern: [u8; AUDIO_PATTERN_SIZE],   // XO-CHIP 1-bit sample buffer
+    pub pitch: u8,
+
+    pub quirks: Quirks,
+
+    pub rng: Rng,   // used by CXNN
+    seed: u64,      // the rng restarts from this on reset
+
+    rom_hash: u64,  // of the last loaded ROM, checked by savestates
 
     pub is_paused: bool,
+    pub has_exited: bool,   // set by 00FD
+
+    pub debugger: Debugger,
+    pub tracer: Option<Tracer>,     // writes every executed instruction when set
+    pub profiler: Option<Profiler>, // counts where the cycles go when set
+
+    // address and opcode of the instruction being executed, used for errors
+    current_pc: u16,
+    current_opcode: u16,
 }
 
 impl Default for Emu {
     fn default() -> Self {
+        let seed = rand::random();
+
         Emu {
             programm_counter: START_ADDR,  
             ram: [0; RAM_SIZE],
     
-            screen: [false; SCREEN_WIDTH * SCREEN_HEIGHT],
+            screen: [0; SCREEN_BUFFER_SIZE],
+            hires: false,
+            plane_mask: 1,
             pressed_keys: [false; NUM_KEYS],
 
             registers: [0; REGISTER_COUNT],
@@ -49,8 +151,29 @@ impl Default for Emu {
 
             delay_timer: 0,    // performs any action after finished
             sound_timer: 0,    // plays sound after finished
+            vblank: true,
+
+            rpl_flags: [0; RPL_FLAG_COUNT],
+
+            audio_pattern: [0; AUDIO_PATTERN_SIZE],
+            pitch: DEFAULT_PITCH,
+
+            quirks: Quirks::default(),
+
+            rng: Rng::from_seed(seed),
+            seed,
+
+            rom_hash: rom_hash(&[]),
             
             is_paused: false,
+            has_exited: false,
+
+            debugger: Debugger::default(),
+            tracer: None,
+            profiler: None,
+
+            current_pc: START_ADDR,
+            current_opcode: 0,
 
         }
     }
@@ -60,7 +183,7 @@ impl Emu {
     pub fn new() -> Self {
         let mut new_instance = Self::default();
 
-        new_instance.ram[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+        new_instance.load_fonts();
 
         new_instance
     }
@@ -70,7 +193,9 @@ impl Emu {
     pub fn reset(&mut self) {
         self.programm_counter = START_ADDR;
         self.ram = [0; RAM_SIZE];
-        self.screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
+        self.screen = [0; SCREEN_BUFFER_SIZE];
+        self.hires = false;
+        self.plane_mask = 1;
         self.registers = [0; REGISTER_COUNT];
         self.i_register = 0;
         self.stack_pointer = 0;
@@ -78,342 +203,660 @@ impl Emu {
         self.pressed_keys = [false; NUM_KEYS];
         self.delay_timer = 0;
         self.sound_timer = 0;
+        self.vblank = true;
+        self.audio_pattern = [0; AUDIO_PATTERN_SIZE];
+        self.pitch = DEFAULT_PITCH;
+        self.has_exited = false;
+        self.rng = Rng::from_seed(self.seed);
+
+        self.load_fonts();
+    }
 
+    fn load_fonts(&mut self) {
         self.ram[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+        self.ram[BIG_FONTSET_ADDR..BIG_FONTSET_ADDR + BIG_FONTSET_SIZE].copy_from_slice(&BIG_FONTSET);
+    }
+
+    // RANDOM NUMBERS
+
+    // makes CXNN reproducible, also applies on every reset
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = Rng::from_seed(seed);
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
     }
 
     // EXPOSE TO FRONTEND
 
-    pub fn get_display(&self) -> &[bool] {
-        &self.screen
+    // only the first screen_width() * screen_height() pixels are in use.
+    // Bit 0 of a pixel is plane 1, bit 1 is plane 2
+    pub fn get_display(&self) -> &[u8] {
+        &self.screen[..self.screen_width() * self.screen_height()]
+    }
+
+    pub fn screen_width(&self) -> usize {
+        if self.hires { HIRES_SCREEN_WIDTH } else { SCREEN_WIDTH }
+    }
+
+    pub fn screen_height(&self) -> usize {
+        if self.hires { HIRES_SCREEN_HEIGHT } else { SCREEN_HEIGHT }
+    }
+
+    // samples per second at which the audio pattern should be played
+    pub fn audio_playback_rate(&self) -> f32 {
+        4000. * 2f32.powf((self.pitch as f32 - 64.) / 48.)
     }
 
     pub fn keypress(&mut self, id: usize, pressed: bool) {
         self.pressed_keys[id] = pressed;
     }
 
-    pub fn load(&mut self, data: &[u8]) {
+    // bit n is set while key n is pressed
+    pub fn keypad(&self) -> u16 {
+        self.pressed_keys.iter()
+            .enumerate()
+            .fold(0, |keys, (id, pressed)| keys | ((*pressed as u16) << id))
+    }
+
+    pub fn load(&mut self, data: &[u8]) -> Result<(), EmuError> {
         let start = START_ADDR as usize;
         let end = (START_ADDR as usize) + data.len();
+        if end > RAM_SIZE {
+            return Err(EmuError::RomTooLarge { size: data.len(), max: RAM_SIZE - start });
+        }
         self.ram[start..end].copy_from_slice(data);
+        self.rom_hash = rom_hash(data);
+        Ok(())
     }
     
     // STACK
     
-    fn stack_push(&mut self, val:u16) {
+    fn stack_push(&mut self, val:u16) -> Result<(), EmuError> {
+        if self.stack_pointer as usize >= STACK_SIZE {
+            return Err(EmuError::StackOverflow { pc: self.current_pc, opcode: self.current_opcode });
+        }
         self.stack[self.stack_pointer as usize] = val;
         self.stack_pointer += 1;
+        Ok(())
     }
 
-    fn stack_pop(&mut self) -> u16 {
+    fn stack_pop(&mut self) -> Result<u16, EmuError> {
+        if self.stack_pointer == 0 {
+            return Err(EmuError::StackUnderflow { pc: self.current_pc, opcode: self.current_opcode });
+        }
         self.stack_pointer -= 1;
-        self.stack[self.stack_pointer as usize]
+        Ok(self.stack[self.stack_pointer as usize])
+    }
+
+    // MEMORY
+
+    fn out_of_bounds(&self, addr: usize) -> EmuError {
+        EmuError::MemoryOutOfBounds { pc: self.current_pc, opcode: self.current_opcode, addr }
+    }
+
+    fn read_ram(&mut self, addr: usize) -> Result<u8, EmuError> {
+        let value = self.ram.get(addr).copied().ok_or_else(|| self.out_of_bounds(addr))?;
+        self.debug_read(addr, value);
+        Ok(value)
+    }
+
+    fn write_ram(&mut self, addr: usize, val: u8) -> Result<(), EmuError> {
+        match self.ram.get_mut(addr) {
+            Some(byte) => {
+                let old = *byte;
+                *byte = val;
+                self.debug_write(addr, old, val);
+                self.trace_write(addr, val);
+                Ok(())
+            },
+            None => Err(self.out_of_bounds(addr)),
+        }
     }
 
     
     // CPU
     
-    pub fn tick(&mut self) -> bool {
-        if self.is_paused {
-            return false
+    pub fn tick(&mut self) -> Result<bool, EmuError> {
+        if self.is_paused || self.has_exited {
+            return Ok(false)
+        }
+        self.current_pc = self.programm_counter;
+        if self.check_breakpoint() {
+            return Ok(false)
         }
+        if self.debugger.is_idle() {
+            return self.fetch_and_execute();
+        }
+
+        let registers_before = self.watched_registers();
+        let result = self.fetch_and_execute();
+        self.check_watched_registers(registers_before);
+        result
+    }
+
+    fn fetch_and_execute(&mut self) -> Result<bool, EmuError> {
         // fetch
-        let opcode = self.fetch();
-        // decode and execute
-        self.execute(opcode)
+        let opcode = self.fetch()?;
+        self.trace_instruction(opcode);
+        self.current_opcode = opcode;
+        // decode
+        let mut instruction = decode(opcode)
+            .map_err(|_| EmuError::UnknownOpcode { pc: self.current_pc, opcode })?;
+        if let Instruction::LoadLongI(_) = instruction {
+            // the address is stored in the next word
+            instruction = Instruction::LoadLongI(self.fetch()?);
+        }
+        // execute
+        let result = self.execute(instruction);
+        self.profile_instruction(instruction, result.is_ok());
+        result
     }
 
-    fn fetch(&mut self) -> u16 {
-        let higher_byte = self.ram[self.programm_counter as usize] as u16;
-        let lower_byte = self.ram[(self.programm_counter + 1) as usize] as u16;
+    // skips the next instruction, F000 NNNN is 4 bytes long
+    fn skip_next(&mut self) {
+        let pc = self.programm_counter as usize;
+        if self.ram.get(pc) == Some(&0xF0) && self.ram.get(pc + 1) == Some(&0x00) {
+            self.programm_counter = self.programm_counter.wrapping_add(4);
+        } else {
+            self.programm_counter = self.programm_counter.wrapping_add(2);
+        }
+    }
+
+    // instruction fetches don't trigger read watchpoints
+    fn fetch(&mut self) -> Result<u16, EmuError> {
+        let pc = self.programm_counter as usize;
+        let higher_byte = self.ram.get(pc).copied().ok_or_else(|| self.out_of_bounds(pc))? as u16;
+        let lower_byte = self.ram.get(pc + 1).copied().ok_or_else(|| self.out_of_bounds(pc + 1))? as u16;
         let opcode: u16 = (higher_byte << 8) | lower_byte;
         // println!("opcode loaded: {:0x}", opcode);
-        self.programm_counter += 2;
-
-        opcode
-    }
-
-    fn execute(&mut self, op: u16) -> bool {
-        let digit1 = (op & 0xF000) >> 12;
-        let digit2 = (op & 0x0F00) >> 8;
-        let digit3 = (op & 0x00F0) >> 4;
-        let digit4 = op & 0x000F;
-        
-        let x = digit2 as usize;
-        let y = digit3 as usize;
-
-        let nnn = op & 0xFFF;
-        let nn = op & 0xFF;
-        let n = op & 0xF;
-
-        match (digit1, digit2, digit3, digit4) {
-            (0, 0, 0, 0) => self.op_0000(),
-            (0, 0, 0xE, 0) => self.op_00e0(),
-            (0, 0, 0xE, 0xE) => self.op_00ee(),
-            (1, _, _, _) => self.op_1nnn(nnn),
-            (2, _, _, _) => self.op_2nnn(nnn),
-            (3, _, _, _) => self.op_3xnn(x, nn),
-            (4, _, _, _) => self.op_4xnn(x, nn),
-            (5, _, _, _) => self.op_5xy0(x, y),
-            (6, _, _, _) => self.op_6xnn(x, nn),
-            (7, _, _, _) => self.op_7xnn(x, nn),
-            (8, _, _, 0) => self.op_8xy0(x, y),
-            (8, _, _, 1) => self.op_8xy1(x, y),
-            (8, _, _, 2) => self.op_8xy2(x, y),
-            (8, _, _, 3) => self.op_8xy3(x, y),
-            (8, _, _, 4) => self.op_8xy4(x, y),
-            (8, _, _, 5) => self.op_8xy5(x, y),
-            (8, _, _, 6) => self.op_8xy6(x, y),
-            (8, _, _, 7) => self.op_8xy7(x, y),
-            (8, _, _, 0xE) => self.op_8xye(x, y),
-            (9, _, _, 0) => self.op_9xy0(x, y),
-            (0xA, _, _, _) => self.op_annn(nnn),
-            (0xB, _, _, _) => self.op_bnnn(nnn),
-            (0xC, _, _, _) => self.op_cxnn(x, nn),
-            (0xD, _, _, _) => self.op_dxyn(x, y, n),
-            (0xE, _, 9, 0xE) => self.op_ex9e(x),
-            (0xE, _, 0xA, 1) => self.op_exa1(x),
-            (0xF, _, 0, 7) => self.op_fx07(x),
-            (0xF, _, 0, 0xA) => self.op_fx0a(x),
-            (0xF, _, 1, 5) => self.op_fx15(x),
-            (0xF, _, 1, 8) => self.op_fx18(x),
-            (0xF, _, 1, 0xE) => self.op_fx1e(x),
-            (0xF, _, 2, 9) => self.op_fx29(x),
-            (0xF, _, 3, 3) => self.op_fx33(x),
-            (0xF, _, 5, 5) => self.op_fx55(x),
-            (0xF, _, 6, 5) => self.op_fx65(x),
-            (_, _, _, _) => unimplemented!("Unimplemented opcode: {:#04x}", op),
+        self.programm_counter = self.programm_counter.wrapping_add(2);
+
+        Ok(opcode)
+    }
+
+    fn execute(&mut self, instruction: Instruction) -> Result<bool, EmuError> {
+        use Instruction::*;
+
+        match instruction {
+            Nop => self.op_0000(),
+            Cls => self.op_00e0(),
+            Ret => self.op_00ee(),
+            ScrollDown(n) => self.op_00cn(n as u16),
+            ScrollUp(n) => self.op_00dn(n as u16),
+            ScrollRight => self.op_00fb(),
+            ScrollLeft => self.op_00fc(),
+            Exit => self.op_00fd(),
+            Lores => self.op_00fe(),
+            Hires => self.op_00ff(),
+            Jump(nnn) => self.op_1nnn(nnn),
+            Call(nnn) => self.op_2nnn(nnn),
+            SkipEqImm(x, nn) => self.op_3xnn(x as usize, nn as u16),
+            SkipNeImm(x, nn) => self.op_4xnn(x as usize, nn as u16),
+            SkipEqReg(x, y) => self.op_5xy0(x as usize, y as usize),
+            SaveRange(x, y) => self.op_5xy2(x as usize, y as usize),
+            LoadRange(x, y) => self.op_5xy3(x as usize, y as usize),
+            LoadImm(x, nn) => self.op_6xnn(x as usize, nn as u16),
+            AddImm(x, nn) => self.op_7xnn(x as usize, nn as u16),
+            Move(x, y) => self.op_8xy0(x as usize, y as usize),
+            Or(x, y) => self.op_8xy1(x as usize, y as usize),
+            And(x, y) => self.op_8xy2(x as usize, y as usize),
+            Xor(x, y) => self.op_8xy3(x as usize, y as usize),
+            Add(x, y) => self.op_8xy4(x as usize, y as usize),
+            Sub(x, y) => self.op_8xy5(x as usize, y as usize),
+            ShiftRight(x, y) => self.op_8xy6(x as usize, y as usize),
+            SubReverse(x, y) => self.op_8xy7(x as usize, y as usize),
+            ShiftLeft(x, y) => self.op_8xye(x as usize, y as usize),
+            SkipNeReg(x, y) => self.op_9xy0(x as usize, y as usize),
+            LoadI(nnn) => self.op_annn(nnn),
+            JumpOffset(x, nnn) => self.op_bnnn(x as usize, nnn),
+            Random(x, nn) => self.op_cxnn(x as usize, nn as u16),
+            Draw(x, y, n) => self.op_dxyn(x as usize, y as usize, n as u16),
+            SkipKey(x) => self.op_ex9e(x as usize),
+            SkipNotKey(x) => self.op_exa1(x as usize),
+            LoadLongI(nnnn) => self.op_f000(nnnn),
+            Plane(n) => self.op_fn01(n as usize),
+            Audio => self.op_f002(),
+            GetDelay(x) => self.op_fx07(x as usize),
+            WaitKey(x) => self.op_fx0a(x as usize),
+            SetDelay(x) => self.op_fx15(x as usize),
+            SetSound(x) => self.op_fx18(x as usize),
+            AddI(x) => self.op_fx1e(x as usize),
+            Font(x) => self.op_fx29(x as usize),
+            BigFont(x) => self.op_fx30(x as usize),
+            Bcd(x) => self.op_fx33(x as usize),
+            Pitch(x) => self.op_fx3a(x as usize),
+            Store(x) => self.op_fx55(x as usize),
+            Load(x) => self.op_fx65(x as usize),
+            SaveFlags(x) => self.op_fx75(x as usize),
+            LoadFlags(x) => self.op_fx85(x as usize),
         }
     }
 
     // NOP
-    fn op_0000(&self) -> bool { false }
+    fn op_0000(&self) -> Result<bool, EmuError> { Ok(false) }
 
     // CLS
-    fn op_00e0(&mut self) -> bool {
-        self.screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
-        false
+    fn op_00e0(&mut self) -> Result<bool, EmuError> {
+        // only the selected planes are cleared
+        for pixel in self.screen.iter_mut() {
+            *pixel &= !self.plane_mask;
+        }
+        Ok(true)
     }
 
     // RETURN FROM SUBROUTINE
-    fn op_00ee(&mut self) -> bool {
-        let ret_addr = self.stack_pop();
+    fn op_00ee(&mut self) -> Result<bool, EmuError> {
+        let ret_addr = self.stack_pop()?;
         self.programm_counter = ret_addr;
-        false
+        Ok(false)
+    }
+
+    // moves the selected planes by (dx, dy), pixels moved in from outside are cleared
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let width = self.screen_width() as isize;
+        let height = self.screen_height() as isize;
+        let old = self.screen;
+        let mask = self.plane_mask;
+
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - dx, y - dy);
+                let moved = if (0..width).contains(&src_x) && (0..height).contains(&src_y) {
+                    old[(src_x + width * src_y) as usize]
+                } else {
+                    0
+                };
+
+                let idx = (x + width * y) as usize;
+                self.screen[idx] = (old[idx] & !mask) | (moved & mask);
+            }
+        }
+    }
+
+    // SCROLL DOWN N
+    fn op_00cn(&mut self, n: u16) -> Result<bool, EmuError> {
+        self.scroll(0, n as isize);
+        Ok(true)
+    }
+
+    // SCROLL UP N
+    fn op_00dn(&mut self, n: u16) -> Result<bool, EmuError> {
+        self.scroll(0, -(n as isize));
+        Ok(true)
+    }
+
+    // SCROLL RIGHT 4
+    fn op_00fb(&mut self) -> Result<bool, EmuError> {
+        self.scroll(4, 0);
+        Ok(true)
+    }
+
+    // SCROLL LEFT 4
+    fn op_00fc(&mut self) -> Result<bool, EmuError> {
+        self.scroll(-4, 0);
+        Ok(true)
+    }
+
+    // EXIT
+    fn op_00fd(&mut self) -> Result<bool, EmuError> {
+        self.has_exited = true;
+        Ok(false)
+    }
+
+    // LORES
+    fn op_00fe(&mut self) -> Result<bool, EmuError> {
+        self.hires = false;
+        self.screen = [0; SCREEN_BUFFER_SIZE];
+        Ok(true)
+    }
+
+    // HIRES
+    fn op_00ff(&mut self) -> Result<bool, EmuError> {
+        self.hires = true;
+        self.screen = [0; SCREEN_BUFFER_SIZE];
+        Ok(true)
     }
 
     // JUMP TO NNN
-    fn op_1nnn(&mut self, nnn: u16) -> bool {
+    fn op_1nnn(&mut self, nnn: u16) -> Result<bool, EmuError> {
         self.programm_counter = nnn;
-        false
+        Ok(false)
     }
 
     // CALL nnn
-    fn op_2nnn(&mut self, nnn: u16) -> bool {
-        self.stack_push(self.programm_counter);
+    fn op_2nnn(&mut self, nnn: u16) -> Result<bool, EmuError> {
+        self.stack_push(self.programm_counter)?;
         self.programm_counter = nnn;
-        false
+        Ok(false)
     }
     // SKIP VX == NN
-    fn op_3xnn(&mut self, x: usize, nn: u16) -> bool {
+    fn op_3xnn(&mut self, x: usize, nn: u16) -> Result<bool, EmuError> {
         if self.registers[x] == nn as u8 {
-            self.programm_counter += 2;
+            self.skip_next();
         };
-        false
+        Ok(false)
     }
     // SKIP VX != NN
-    fn op_4xnn(&mut self, x: usize, nn: u16) -> bool {
+    fn op_4xnn(&mut self, x: usize, nn: u16) -> Result<bool, EmuError> {
         if self.registers[x] != nn as u8 {
-            self.programm_counter += 2;
+            self.skip_next();
         };
-        false
+        Ok(false)
     }
 
     // SKIP VX == VY
-    fn op_5xy0(&mut self, x: usize, y: usize) -> bool {
+    fn op_5xy0(&mut self, x: usize, y: usize) -> Result<bool, EmuError> {
         if self.registers[x] == self.registers[y] {
-            self.programm_counter += 2;
+            self.skip_next();
         };
-        false
+        Ok(false)
+    }
+
+    // SAVE VX - VY
+    fn op_5xy2(&mut self, x: usize, y: usize) -> Result<bool, EmuError> {
+        let i = self.i_register as usize;
+        // the range can be given in reverse order
+        for (offset, reg) in Self::register_range(x, y).enumerate() {
+            self.write_ram(i + offset, self.registers[reg])?;
+        }
+        Ok(false)
+    }
+
+    // LOAD VX - VY
+    fn op_5xy3(&mut self, x: usize, y: usize) -> Result<bool, EmuError> {
+        let i = self.i_register as usize;
+        for (offset, reg) in Self::register_range(x, y).enumerate() {
+            self.registers[reg] = self.read_ram(i + offset)?;
+        }
+        Ok(false)
+    }
+
+    fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+        if x <= y {
+            Box::new(x..=y)
+        } else {
+            Box::new((y..=x).rev())
+        }
     }
 
     // VX = NN
-    fn op_6xnn(&mut self, x: usize, nn: u16) -> bool {
+    fn op_6xnn(&mut self, x: usize, nn: u16) -> Result<bool, EmuError> {
         self.registers[x] = nn as u8;
-        false
+        Ok(false)
     }
     
     // VX += NN
-    fn op_7xnn(&mut self, x: usize, nn: u16) -> bool {
+    fn op_7xnn(&mut self, x: usize, nn: u16) -> Result<bool, EmuError> {
         self.registers[x] = self.registers[x].wrapping_add(nn as u8);
-        false
+        Ok(false)
     }
 
     // VX = VY
-    fn op_8xy0(&mut self, x: usize, y:usize) -> bool {
+    fn op_8xy0(&mut self, x: usize, y:usize) -> Result<bool, EmuError> {
         self.registers[x] = self.registers[y];
-        false
+        Ok(false)
     }
 
     // VX |= VY
-    fn op_8xy1(&mut self, x: usize, y:usize) -> bool {
+    fn op_8xy1(&mut self, x: usize, y:usize) -> Result<bool, EmuError> {
         self.registers[x] |= self.registers[y];
-        false
+        self.logic_vf_reset();
+        Ok(false)
     }
     // VX &= VY
-    fn op_8xy2(&mut self, x: usize, y:usize) -> bool {
+    fn op_8xy2(&mut self, x: usize, y:usize) -> Result<bool, EmuError> {
         self.registers[x] &= self.registers[y];
-        false
+        self.logic_vf_reset();
+        Ok(false)
     }
 
     // VX ^= VY
-    fn op_8xy3(&mut self, x: usize, y:usize) -> bool {
+    fn op_8xy3(&mut self, x: usize, y:usize) -> Result<bool, EmuError> {
         self.registers[x] ^= self.registers[y];
-        false
+        self.logic_vf_reset();
+        Ok(false)
+    }
+
+    fn logic_vf_reset(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.registers[0xF] = 0;
+        }
     }
 
     // VX += VY
-    fn op_8xy4(&mut self, x: usize, y: usize) -> bool {
+    fn op_8xy4(&mut self, x: usize, y: usize) -> Result<bool, EmuError> {
         let (new_vx, carry) = self.registers[x].overflowing_add(self.registers[y]);
         let new_vf = if carry { 1 } else { 0 };
 
         self.registers[x] = new_vx;
         self.registers[0xF] = new_vf;
-        false
+        Ok(false)
     }
 
     // VX -= VY
-    fn op_8xy5(&mut self, x: usize, y: usize) -> bool {
+    fn op_8xy5(&mut self, x: usize, y: usize) -> Result<bool, EmuError> {
         let (new_vx, borrow) = self.registers[x].overflowing_sub(self.registers[y]);
         let new_vf = if borrow { 0 } else { 1 };
 
         self.registers[x] = new_vx;
         self.registers[0xF] = new_vf;
-        false
+        Ok(false)
     }
 
     // VX >>= 1
-    fn op_8xy6(&mut self, x: usize, _y: usize) -> bool {
+    fn op_8xy6(&mut self, x: usize, y: usize) -> Result<bool, EmuError> {
+        if self.quirks.shift_uses_vy {
+            self.registers[x] = self.registers[y];
+        }
         let lsb = self.registers[x] & 1;
         self.registers[x] >>= 1;
         self.registers[0xF] = lsb;
-        false
+        Ok(false)
     }
 
     // VX = VY - VX
-    fn op_8xy7(&mut self, x: usize, y: usize) -> bool {
+    fn op_8xy7(&mut self, x: usize, y: usize) -> Result<bool, EmuError> {
         let (new_vx, borrow) = self.registers[y].overflowing_sub(self.registers[x]);
         let new_vf = if borrow { 0 } else { 1 };
 
         self.registers[x] = new_vx;
         self.registers[0xF] = new_vf;
-        false
+        Ok(false)
     }
 
     // VX <<= 1
-    fn op_8xye(&mut self, x: usize, _y: usize) -> bool {
+    fn op_8xye(&mut self, x: usize, y: usize) -> Result<bool, EmuError> {
+        if self.quirks.shift_uses_vy {
+            self.registers[x] = self.registers[y];
+        }
         let msb = (self.registers[x] >> 7) & 1;
         self.registers[x] <<= 1;
         self.registers[0xF] = msb;
-        false
+        Ok(false)
     }
 
     // SKIP VX != VY
-    fn op_9xy0(&mut self, x: usize, y: usize) -> bool {
+    fn op_9xy0(&mut self, x: usize, y: usize) -> Result<bool, EmuError> {
         if self.registers[x] != self.registers[y] {
-            self.programm_counter += 2;
+            self.skip_next();
         };
-        false
+        Ok(false)
     }
 
     // I = NNN
-    fn op_annn(&mut self, nnn: u16) -> bool {
+    fn op_annn(&mut self, nnn: u16) -> Result<bool, EmuError> {
         self.i_register = nnn;
-        false
+        Ok(false)
     }
 
-    // JMP V0 + NNN
-    fn op_bnnn(&mut self, nnn: u16) -> bool {
-        self.programm_counter = (self.registers[0] as u16) + nnn;
-        false
+    // JMP V0 + NNN (or VX + XNN)
+    fn op_bnnn(&mut self, x: usize, nnn: u16) -> Result<bool, EmuError> {
+        let offset = if self.quirks.jump_uses_vx {
+            self.registers[x]
+        } else {
+            self.registers[0]
+        };
+        self.programm_counter = (offset as u16) + nnn;
+        Ok(false)
     }
 
     // VX = rand() & NN
-    fn op_cxnn(&mut self, x: usize, nn: u16) -> bool {
-        let rng: u8 = rand::thread_rng().gen();
+    fn op_cxnn(&mut self, x: usize, nn: u16) -> Result<bool, EmuError> {
+        let rng: u8 = self.rng.next_u8();
         self.registers[x] = rng & (nn as u8);
-        false
+        Ok(false)
     }
 
     // DRAW
-    fn op_dxyn(&mut self, x: usize, y: usize, n: u16) -> bool {
-        // Get the (x, y) coords for our sprite
-        let x_coord = self.registers[x] as u16;
-        let y_coord = self.registers[y] as u16;
-        // The last digit determines how many rows high our sprite is
-        let num_rows = n;
-
-        // Keep track if any pixels were flipped
+    fn op_dxyn(&mut self, x: usize, y: usize, n: u16) -> Result<bool, EmuError> {
+        // like the original interpreters, wait for the vertical blank.
+        // The opcode runs again until the next timer tick
+        let display_wait = match self.quirks.display_wait {
+            DisplayWait::Off => false,
+            DisplayWait::Lores => !self.hires,
+            DisplayWait::Always => true,
+        };
+        if display_wait {
+            if !self.vblank {
+                self.programm_counter = self.programm_counter.wrapping_sub(2);
+                return Ok(false);
+            }
+            self.vblank = false;
+        }
+
+        let width = self.screen_width();
+        let height = self.screen_height();
+
+        // Get the (x, y) coords for our sprite,
+        // the starting position always wraps around
+        let x_coord = self.registers[x] as usize % width;
+        let y_coord = self.registers[y] as usize % height;
+
+        // DXY0 draws a 16x16 sprite made of two bytes per row,
+        // otherwise the last digit is the height of an 8 wide sprite
+        let (num_rows, num_cols) = if n == 0 { (16, 16) } else { (n as usize, 8) };
+        let bytes_per_row = num_cols / 8;
+
+        // Keep track if any pixels were flipped, and in how many rows
         let mut flipped = false;
-        // Iterate over each row of our sprite
-        for y_line in 0..num_rows {
-            // Determine which memory address our row's data is stored
-            let addr = self.i_register + y_line;
-            let pixels = self.ram[addr as usize];
-            // Iterate over each column in our row
-            for x_line in 0..8 {
-                // Use a mask to fetch current pixel's bit. Only flip if a 1
-                if (pixels & (0b1000_0000 >> x_line)) != 0 {
-                    // Sprites should wrap around screen, so apply modulo
-                    let x = (x_coord + x_line) as usize % SCREEN_WIDTH;
-                    let y = (y_coord + y_line) as usize % SCREEN_HEIGHT;
-
-                    // Get our pixel's index in the 1D screen array
-                    let idx = x + SCREEN_WIDTH * y;
-                    // Check if we're about to flip the pixel and set
-                    flipped |= self.screen[idx];
-                    self.screen[idx] ^= true;
+        let mut collided_rows = 0;
+        // With both planes selected the sprite data of
+        // plane 2 directly follows the data of plane 1
+        let mut addr = self.i_register as usize;
+        for plane in 0..NUM_PLANES {
+            let plane_bit = 1 << plane;
+            if self.plane_mask & plane_bit == 0 {
+                continue;
+            }
+
+            // Iterate over each row of our sprite
+            for y_line in 0..num_rows {
+                let mut row_collided = false;
+                // rows clipped at the bottom count as collisions with the row quirk
+                if self.quirks.clip_sprites && y_coord + y_line >= height {
+                    row_collided = true;
+                }
+
+                // Determine which memory address our row's data is stored
+                let mut pixels: u16 = self.read_ram(addr)? as u16;
+                if bytes_per_row == 2 {
+                    pixels = (pixels << 8) | self.read_ram(addr + 1)? as u16;
+                }
+                addr += bytes_per_row;
+
+                // Iterate over each column in our row
+                for x_line in 0..num_cols {
+                    // Use a mask to fetch current pixel's bit. Only flip if a 1
+                    if (pixels & (1 << (num_cols - 1 - x_line))) != 0 {
+                        // Sprites either get clipped or wrap around the screen
+                        if self.quirks.clip_sprites && (x_coord + x_line >= width || y_coord + y_line >= height) {
+                            continue;
+                        }
+                        let x = (x_coord + x_line) % width;
+                        let y = (y_coord + y_line) % height;
+
+                        // Get our pixel's index in the 1D screen array
+                        let idx = x + width * y;
+                        // Check if we're about to flip the pixel and set
+                        let collided = self.screen[idx] & plane_bit != 0;
+                        flipped |= collided;
+                        row_collided |= collided;
+                        self.screen[idx] ^= plane_bit;
+                    }
+                }
+                if row_collided {
+                    collided_rows += 1;
                 }
             }
         }
         // Populate VF register
-        if flipped {
+        if self.quirks.collision_counts_rows && self.hires {
+            self.registers[0xF] = collided_rows;
+        } else if flipped {
             self.registers[0xF] = 1;
         } else {
             self.registers[0xF] = 0;
         }
 
-        true
+        Ok(true)
     }
 
     // SKIP KEY PRESS
-    fn op_ex9e(&mut self, x: usize) -> bool {
+    fn op_ex9e(&mut self, x: usize) -> Result<bool, EmuError> {
         let vx = self.registers[x];
-        let key = self.pressed_keys[vx as usize];
+        let key = self.pressed_keys[(vx & 0xF) as usize];
         if key {
-            self.programm_counter += 2;
+            self.skip_next();
         };
-        false
+        Ok(false)
     }
 
     // SKIP KEY RELEASE
-    fn op_exa1(&mut self, x: usize) -> bool {
+    fn op_exa1(&mut self, x: usize) -> Result<bool, EmuError> {
         let vx = self.registers[x];
-        let key = self.pressed_keys[vx as usize];
+        let key = self.pressed_keys[(vx & 0xF) as usize];
         if !key {
-            self.programm_counter += 2;
+            self.skip_next();
         };
-        false
+        Ok(false)
+    }
+
+    // I = NNNN (LONG)
+    fn op_f000(&mut self, nnnn: u16) -> Result<bool, EmuError> {
+        self.i_register = nnnn;
+        Ok(false)
+    }
+
+    // SELECT PLANES N
+    fn op_fn01(&mut self, n: usize) -> Result<bool, EmuError> {
+        self.plane_mask = (n as u8) & ALL_PLANES;
+        Ok(false)
+    }
+
+    // LOAD AUDIO PATTERN
+    fn op_f002(&mut self) -> Result<bool, EmuError> {
+        let i = self.i_register as usize;
+        for idx in 0..AUDIO_PATTERN_SIZE {
+            self.audio_pattern[idx] = self.read_ram(i + idx)?;
+        }
+        Ok(false)
     }
 
     // VX = DT
-    fn op_fx07(&mut self, x: usize) -> bool {
+    fn op_fx07(&mut self, x: usize) -> Result<bool, EmuError> {
         self.registers[x] = self.delay_timer;
-        false
+        Ok(false)
     }
 
     // WAIT KEY
-    fn op_fx0a(&mut self, x: usize) -> bool {
+    fn op_fx0a(&mut self, x: usize) -> Result<bool, EmuError> {
         let mut pressed = false;
         for i in 0..self.pressed_keys.len() {
             if self.pressed_keys[i] {
@@ -425,40 +868,47 @@ impl Emu {
 
         if !pressed {
             // Redo opcode
-            self.programm_counter -= 2;
+            self.programm_counter = self.programm_counter.wrapping_sub(2);
         };
 
-        false
+        Ok(false)
     }
 
     // DT = VX
-    fn op_fx15(&mut self, x: usize) -> bool {
+    fn op_fx15(&mut self, x: usize) -> Result<bool, EmuError> {
         self.delay_timer = self.registers[x];
-        false
+        Ok(false)
     }
 
     // ST = VX
-    fn op_fx18(&mut self, x: usize) -> bool {
+    fn op_fx18(&mut self, x: usize) -> Result<bool, EmuError> {
         self.sound_timer = self.registers[x];
-        false
+        Ok(false)
     }
 
     // I += VX
-    fn op_fx1e(&mut self, x: usize) -> bool {
+    fn op_fx1e(&mut self, x: usize) -> Result<bool, EmuError> {
         let vx = self.registers[x] as u16;
         self.i_register = self.i_register.wrapping_add(vx);
-        false
+        Ok(false)
     }
 
     // I = FONT
-    fn op_fx29(&mut self, x: usize) -> bool {
+    fn op_fx29(&mut self, x: usize) -> Result<bool, EmuError> {
         let c = self.registers[x] as u16;
         self.i_register = c * 5;
-        false
+        Ok(false)
     }
     
+    // I = BIG FONT
+    fn op_fx30(&mut self, x: usize) -> Result<bool, EmuError> {
+        let c = (self.registers[x] & 0xF) as u16;
+        self.i_register = BIG_FONTSET_ADDR as u16 + c * 10;
+        Ok(false)
+    }
+
     // BCD
-    fn op_fx33(&mut self, x: usize) -> bool {
+    fn op_fx33(&mut self, x: usize) -> Result<bool, EmuError> {
         let vx = self.registers[x] as f32;
 
         // Fetch the hundreds digit by dividing by 100 and tossing the decimal
@@ -468,28 +918,58 @@ impl Emu {
         // Fetch the ones digit by tossing the hundreds and the tens
         let ones = (vx % 10.0) as u8;
 
-        self.ram[self.i_register as usize] = hundreds;
-        self.ram[(self.i_register + 1) as usize] = tens;
-        self.ram[(self.i_register + 2) as usize] = ones;
-        false
+        let i = self.i_register as usize;
+        self.write_ram(i, hundreds)?;
+        self.write_ram(i + 1, tens)?;
+        self.write_ram(i + 2, ones)?;
+        Ok(false)
     }
     
+    // PITCH = VX
+    fn op_fx3a(&mut self, x: usize) -> Result<bool, EmuError> {
+        self.pitch = self.registers[x];
+        Ok(false)
+    }
+
     // STORE V0 - VX
-    fn op_fx55(&mut self, x: usize) -> bool {
+    fn op_fx55(&mut self, x: usize) -> Result<bool, EmuError> {
         let i = self.i_register as usize;
         for idx in 0..=x {
-            self.ram[i + idx] = self.registers[idx];
+            self.write_ram(i + idx, self.registers[idx])?;
         };
-        false
+        self.memory_increment(x);
+        Ok(false)
     }
 
     // LOAD V0 - VX
-    fn op_fx65(&mut self, x: usize) -> bool {
+    fn op_fx65(&mut self, x: usize) -> Result<bool, EmuError> {
         let i = self.i_register as usize;
         for idx in 0..=x {
-            self.registers[idx] = self.ram[i + idx];
+            self.registers[idx] = self.read_ram(i + idx)?;
         };
-        false
+        self.memory_increment(x);
+        Ok(false)
+    }
+
+    fn memory_increment(&mut self, x: usize) {
+        let increment = match self.quirks.memory_increment {
+            MemoryIncrement::None => 0,
+            MemoryIncrement::X => x as u16,
+            MemoryIncrement::XPlusOne => x as u16 + 1,
+        };
+        self.i_register = self.i_register.wrapping_add(increment);
+    }
+
+    // STORE V0 - VX IN RPL FLAGS
+    fn op_fx75(&mut self, x: usize) -> Result<bool, EmuError> {
+        self.rpl_flags[..=x].copy_from_slice(&self.registers[..=x]);
+        Ok(false)
+    }
+
+    // LOAD V0 - VX FROM RPL FLAGS
+    fn op_fx85(&mut self, x: usize) -> Result<bool, EmuError> {
+        self.registers[..=x].copy_from_slice(&self.rpl_flags[..=x]);
+        Ok(false)
     }
 
 
@@ -497,24 +977,48 @@ impl Emu {
 
     // TIMERS
 
-    pub fn tick_timers(&mut self) -> bool {
+    pub fn tick_timers(&mut self) {
         if self.is_paused {
-            return false;
+            return;
         }
         // println!("ticked timer");
-        let mut beep = false;
+        self.trace_frame();
+        self.profile_frame();
+        self.vblank = true;
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
 
         if self.sound_timer > 0 {
-            if self.sound_timer == 1 {
-                beep = true;
-            }
             self.sound_timer -= 1;
         }
+    }
+
+    // the buzzer sounds for as long as the sound timer is non-zero
+    pub fn buzzer_active(&self) -> bool {
+        self.sound_timer > 0 && !self.is_paused && !self.has_exited
+    }
+
+    // FRAMES
+
+    // runs one 60 Hz frame: a fixed number of instructions, then the timers
+    pub fn run_frame(&mut self, cycles_per_frame: u32) -> Result<FrameResult, EmuError> {
+        let mut result = FrameResult::default();
+        if self.is_paused {
+            return Ok(result);
+        }
+
+        for _ in 0..cycles_per_frame {
+            result.visuals_modified |= self.tick()?;
+            result.stop = self.debugger.take_stop();
+            if self.has_exited || result.stop.is_some() {
+                break;
+            }
+        }
+        result.buzzer_active = self.buzzer_active();
+        self.tick_timers();
 
-        beep
+        Ok(result)
     }
 
 }
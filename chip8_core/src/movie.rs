@@ -0,0 +1,256 @@
+use std::fmt;
+use std::fmt::Write;
+
+use crate::*;
+
+// Recorded keypad input that replays a run exactly. A movie always
+// starts right after the ROM was loaded into a fresh emulator, so the
+// seed, the quirks and the cycles per frame are all that is needed to
+// reproduce it. Every frame also stores the hash of the emulator state
+// after the frame, playback compares it to find the first desync.
+//
+// FILE FORMAT (text, one entry per line, # starts a comment)
+//
+//   chip8-movie 1
+//   rom <ROM hash, 16 hex digits>
+//   seed <decimal>
+//   cycles_per_frame <decimal>
+//   shift_uses_vy <true|false>
+//   memory_increment <none|x|x+1>
+//   logic_resets_vf <true|false>
+//   jump_uses_vx <true|false>
+//   clip_sprites <true|false>
+//   display_wait <off|lores|always>      optional, off
+//   collision_counts_rows <true|false>   optional, false
+//   frames
+//   <keypad, 4 hex digits, bit n = key n> <state hash, 16 hex digits>
+//   ...
+
+const MOVIE_HEADER: &str = "chip8-movie";
+pub const MOVIE_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MovieError {
+    Parse { line: usize, message: String },
+    // expected is always the recording
+    RomMismatch { expected: u64, found: u64 },
+    Desync { frame: usize, expected: u64, found: u64 },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            MovieError::RomMismatch { expected, found } => {
+                write!(f, "movie was recorded with ROM {:016X}, loaded ROM is {:016X}", expected, found)
+            },
+            MovieError::Desync { frame, expected, found } => {
+                write!(f, "desync in frame {}: state hash is {:016X}, recording has {:016X}", frame, found, expected)
+            },
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MovieFrame {
+    pub keys: u16,          // keypad during the frame
+    pub state_hash: u64,    // after the frame
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub seed: u64,
+    pub quirks: Quirks,
+    pub cycles_per_frame: u32,
+    pub frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    // call right after the ROM was loaded
+    pub fn new(emulator: &Emu, cycles_per_frame: u32) -> Self {
+        Movie {
+            rom_hash: emulator.rom_hash(),
+            seed: emulator.seed(),
+            quirks: emulator.quirks,
+            cycles_per_frame,
+            frames: Vec::new(),
+        }
+    }
+
+    // RECORDING
+
+    pub fn record_frame(&mut self, keys: u16, emulator: &Emu) {
+        self.frames.push(MovieFrame {
+            keys,
+            state_hash: SaveState::capture(emulator).hash(),
+        });
+    }
+
+    // PLAYBACK
+
+    // prepares an emulator with the ROM loaded for playback
+    pub fn apply(&self, emulator: &mut Emu) -> Result<(), MovieError> {
+        if emulator.rom_hash() != self.rom_hash {
+            return Err(MovieError::RomMismatch { expected: self.rom_hash, found: emulator.rom_hash() });
+        }
+        emulator.set_seed(self.seed);
+        emulator.quirks = self.quirks;
+        Ok(())
+    }
+
+    // feeds the recorded keypad of a frame to the emulator.
+    // Returns false once the movie is over
+    pub fn press_keys(&self, frame: usize, emulator: &mut Emu) -> bool {
+        let Some(recorded) = self.frames.get(frame) else {
+            return false;
+        };
+        for id in 0..NUM_KEYS {
+            emulator.keypress(id, recorded.keys & (1 << id) != 0);
+        }
+        true
+    }
+
+    // compares the emulator to the recording after a frame
+    pub fn verify(&self, frame: usize, emulator: &Emu) -> Result<(), MovieError> {
+        let Some(recorded) = self.frames.get(frame) else {
+            return Ok(());
+        };
+        let found = SaveState::capture(emulator).hash();
+        if found != recorded.state_hash {
+            return Err(MovieError::Desync { frame, expected: recorded.state_hash, found });
+        }
+        Ok(())
+    }
+
+    // FILE FORMAT
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let bool_text = |value: bool| if value { "true" } else { "false" };
+        let increment = match self.quirks.memory_increment {
+            MemoryIncrement::None => "none",
+            MemoryIncrement::X => "x",
+            MemoryIncrement::XPlusOne => "x+1",
+        };
+        let display_wait = match self.quirks.display_wait {
+            DisplayWait::Off => "off",
+            DisplayWait::Lores => "lores",
+            DisplayWait::Always => "always",
+        };
+
+        let _ = writeln!(out, "{} {}", MOVIE_HEADER, MOVIE_VERSION);
+        let _ = writeln!(out, "rom {:016X}", self.rom_hash);
+        let _ = writeln!(out, "seed {}", self.seed);
+        let _ = writeln!(out, "cycles_per_frame {}", self.cycles_per_frame);
+        let _ = writeln!(out, "shift_uses_vy {}", bool_text(self.quirks.shift_uses_vy));
+        let _ = writeln!(out, "memory_increment {}", increment);
+        let _ = writeln!(out, "logic_resets_vf {}", bool_text(self.quirks.logic_resets_vf));
+        let _ = writeln!(out, "jump_uses_vx {}", bool_text(self.quirks.jump_uses_vx));
+        let _ = writeln!(out, "clip_sprites {}", bool_text(self.quirks.clip_sprites));
+        let _ = writeln!(out, "display_wait {}", display_wait);
+        let _ = writeln!(out, "collision_counts_rows {}", bool_text(self.quirks.collision_counts_rows));
+        let _ = writeln!(out, "frames");
+        for frame in &self.frames {
+            let _ = writeln!(out, "{:04X} {:016X}", frame.keys, frame.state_hash);
+        }
+
+        out
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, MovieError> {
+        let mut movie = Movie {
+            rom_hash: 0,
+            seed: 0,
+            quirks: Quirks::default(),
+            cycles_per_frame: 0,
+            frames: Vec::new(),
+        };
+
+        let mut lines = text.lines()
+            .enumerate()
+            .map(|(idx, line)| (idx + 1, line.split('#').next().unwrap_or("").trim()))
+            .filter(|(_, line)| !line.is_empty());
+
+        let error = |line: usize, message: String| MovieError::Parse { line, message };
+
+        match lines.next() {
+            Some((line, header)) => {
+                let version = header.strip_prefix(MOVIE_HEADER)
+                    .and_then(|version| version.trim().parse::<u32>().ok())
+                    .ok_or_else(|| error(line, "not a movie file".to_string()))?;
+                if version != MOVIE_VERSION {
+                    return Err(error(line, format!("movie version {} is not supported (expected {})", version, MOVIE_VERSION)));
+                }
+            },
+            None => return Err(error(1, "not a movie file".to_string())),
+        }
+
+        let mut rom_hash = None;
+        let mut seed = None;
+        let mut cycles_per_frame = None;
+
+        for (line, text) in lines.by_ref() {
+            if text == "frames" {
+                break;
+            }
+            let (key, value) = text.split_once(char::is_whitespace)
+                .ok_or_else(|| error(line, format!("expected a value after [{}]", text)))?;
+            let value = value.trim();
+
+            let parse_bool = || match value {
+                "true" => Ok(true),
+                "false" => Ok(false),
+                _ => Err(error(line, format!("[{}] is not true or false", value))),
+            };
+            let invalid = || error(line, format!("invalid value [{}] for {}", value, key));
+
+            match key {
+                "rom" => rom_hash = Some(u64::from_str_radix(value, 16).map_err(|_| invalid())?),
+                "seed" => seed = Some(value.parse::<u64>().map_err(|_| invalid())?),
+                "cycles_per_frame" => cycles_per_frame = Some(value.parse::<u32>().map_err(|_| invalid())?),
+                "shift_uses_vy" => movie.quirks.shift_uses_vy = parse_bool()?,
+                "logic_resets_vf" => movie.quirks.logic_resets_vf = parse_bool()?,
+                "jump_uses_vx" => movie.quirks.jump_uses_vx = parse_bool()?,
+                "clip_sprites" => movie.quirks.clip_sprites = parse_bool()?,
+                "collision_counts_rows" => movie.quirks.collision_counts_rows = parse_bool()?,
+                "display_wait" => {
+                    movie.quirks.display_wait = match value {
+                        "off" => DisplayWait::Off,
+                        "lores" => DisplayWait::Lores,
+                        "always" => DisplayWait::Always,
+                        _ => return Err(invalid()),
+                    };
+                },
+                "memory_increment" => {
+                    movie.quirks.memory_increment = match value {
+                        "none" => MemoryIncrement::None,
+                        "x" => MemoryIncrement::X,
+                        "x+1" => MemoryIncrement::XPlusOne,
+                        _ => return Err(invalid()),
+                    };
+                },
+                _ => return Err(error(line, format!("unknown setting [{}]", key))),
+            }
+        }
+
+        movie.rom_hash = rom_hash.ok_or_else(|| error(1, "missing rom hash".to_string()))?;
+        movie.seed = seed.ok_or_else(|| error(1, "missing seed".to_string()))?;
+        movie.cycles_per_frame = cycles_per_frame.ok_or_else(|| error(1, "missing cycles_per_frame".to_string()))?;
+
+        for (line, text) in lines {
+            let mut fields = text.split_whitespace();
+            let keys = fields.next().and_then(|keys| u16::from_str_radix(keys, 16).ok());
+            let state_hash = fields.next().and_then(|hash| u64::from_str_radix(hash, 16).ok());
+
+            match (keys, state_hash, fields.next()) {
+                (Some(keys), Some(state_hash), None) => movie.frames.push(MovieFrame { keys, state_hash }),
+                _ => return Err(error(line, format!("invalid frame [{}]", text))),
+            }
+        }
+
+        Ok(movie)
+    }
+}
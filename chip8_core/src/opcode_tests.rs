@@ -0,0 +1,803 @@
+// One or more tests for every op_* handler. The programs are run
+// through tick() so fetch, decode and execute are covered as well.
+
+use crate::*;
+
+// emulator with the opcodes loaded at 0x200
+fn emu_with(program: &[u16]) -> Emu {
+    let mut emu = Emu::new();
+    emu.set_seed(0);
+    let bytes: Vec<u8> = program.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+    emu.load(&bytes).unwrap();
+    emu
+}
+
+fn step(emu: &mut Emu, instructions: usize) {
+    for _ in 0..instructions {
+        emu.tick().unwrap();
+    }
+}
+
+// runs the whole program, one instruction per opcode
+fn run(program: &[u16]) -> Emu {
+    let mut emu = emu_with(program);
+    step(&mut emu, program.len());
+    emu
+}
+
+fn pixel(emu: &Emu, x: usize, y: usize) -> u8 {
+    emu.get_display()[x + emu.screen_width() * y]
+}
+
+fn lit_pixels(emu: &Emu) -> usize {
+    emu.get_display().iter().filter(|pixel| **pixel != 0).count()
+}
+
+// 0000
+
+#[test]
+fn nop_only_advances_pc() {
+    let emu = run(&[0x0000]);
+    assert_eq!(emu.programm_counter, 0x202);
+    assert_eq!(emu.registers, [0; REGISTER_COUNT]);
+}
+
+// 00E0
+
+#[test]
+fn cls_clears_screen() {
+    let mut emu = emu_with(&[0x00E0]);
+    emu.screen[0] = 1;
+    emu.screen[100] = 1;
+    assert!(emu.tick().unwrap());
+    assert_eq!(lit_pixels(&emu), 0);
+}
+
+#[test]
+fn cls_only_clears_selected_planes() {
+    let mut emu = emu_with(&[0xF201, 0x00E0]);
+    emu.screen[0] = 0b11;
+    step(&mut emu, 2);
+    assert_eq!(emu.screen[0], 0b01);
+}
+
+// 2NNN / 00EE
+
+#[test]
+fn call_and_return() {
+    // 200: call 206, 202: v0 := 1, 204: jump 204, 206: return
+    let mut emu = emu_with(&[0x2206, 0x6001, 0x1204, 0x00EE]);
+    step(&mut emu, 1);
+    assert_eq!(emu.programm_counter, 0x206);
+    assert_eq!(emu.stack_pointer, 1);
+    assert_eq!(emu.stack[0], 0x202);
+
+    step(&mut emu, 2);
+    assert_eq!(emu.programm_counter, 0x204);
+    assert_eq!(emu.stack_pointer, 0);
+    assert_eq!(emu.registers[0], 1);
+}
+
+#[test]
+fn return_on_empty_stack_is_an_error() {
+    let mut emu = emu_with(&[0x00EE]);
+    assert_eq!(emu.tick(), Err(EmuError::StackUnderflow { pc: 0x200, opcode: 0x00EE }));
+}
+
+#[test]
+fn call_overflows_stack() {
+    // calls itself forever
+    let mut emu = emu_with(&[0x2200]);
+    step(&mut emu, STACK_SIZE);
+    assert_eq!(emu.tick(), Err(EmuError::StackOverflow { pc: 0x200, opcode: 0x2200 }));
+}
+
+// 00CN / 00DN / 00FB / 00FC
+
+#[test]
+fn scroll_down() {
+    let mut emu = emu_with(&[0x00C3]);
+    emu.screen[5] = 1;
+    step(&mut emu, 1);
+    assert_eq!(pixel(&emu, 5, 0), 0);
+    assert_eq!(pixel(&emu, 5, 3), 1);
+}
+
+#[test]
+fn scroll_up() {
+    let mut emu = emu_with(&[0x00D2]);
+    emu.screen[5 + SCREEN_WIDTH * 10] = 1;
+    step(&mut emu, 1);
+    assert_eq!(pixel(&emu, 5, 8), 1);
+    assert_eq!(lit_pixels(&emu), 1);
+}
+
+#[test]
+fn scroll_right_and_left() {
+    let mut emu = emu_with(&[0x00FB, 0x00FC, 0x00FC]);
+    emu.screen[2] = 1;
+    step(&mut emu, 1);
+    assert_eq!(pixel(&emu, 6, 0), 1);
+    step(&mut emu, 1);
+    assert_eq!(pixel(&emu, 2, 0), 1);
+    // scrolled out of the screen
+    step(&mut emu, 1);
+    assert_eq!(lit_pixels(&emu), 0);
+}
+
+#[test]
+fn scroll_in_hires_uses_the_big_screen() {
+    let mut emu = emu_with(&[0x00FF, 0x00C1]);
+    step(&mut emu, 1);
+    emu.screen[100] = 1;
+    step(&mut emu, 1);
+    assert_eq!(pixel(&emu, 100, 1), 1);
+}
+
+#[test]
+fn scroll_only_moves_selected_planes() {
+    let mut emu = emu_with(&[0xF201, 0x00C1]);
+    emu.screen[0] = 0b11;
+    step(&mut emu, 2);
+    assert_eq!(pixel(&emu, 0, 0), 0b01);
+    assert_eq!(pixel(&emu, 0, 1), 0b10);
+}
+
+// 00FD
+
+#[test]
+fn exit_stops_execution() {
+    let mut emu = emu_with(&[0x00FD, 0x6001]);
+    step(&mut emu, 2);
+    assert!(emu.has_exited);
+    assert_eq!(emu.registers[0], 0);
+    assert_eq!(emu.programm_counter, 0x202);
+}
+
+// 00FE / 00FF
+
+#[test]
+fn hires_and_lores_switch_resolution_and_clear() {
+    let mut emu = emu_with(&[0x00FF, 0x00FE]);
+    emu.screen[0] = 1;
+    step(&mut emu, 1);
+    assert!(emu.hires);
+    assert_eq!(emu.get_display().len(), HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT);
+    assert_eq!(lit_pixels(&emu), 0);
+
+    emu.screen[0] = 1;
+    step(&mut emu, 1);
+    assert!(!emu.hires);
+    assert_eq!(emu.get_display().len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+    assert_eq!(lit_pixels(&emu), 0);
+}
+
+// 1NNN
+
+#[test]
+fn jump() {
+    let emu = run(&[0x1ABC]);
+    assert_eq!(emu.programm_counter, 0xABC);
+}
+
+// 3XNN / 4XNN / 5XY0 / 9XY0
+
+#[test]
+fn skip_if_equal_immediate() {
+    let emu = run(&[0x6A42, 0x3A42]);
+    assert_eq!(emu.programm_counter, 0x206);
+    let emu = run(&[0x6A42, 0x3A43]);
+    assert_eq!(emu.programm_counter, 0x204);
+}
+
+#[test]
+fn skip_if_not_equal_immediate() {
+    let emu = run(&[0x6A42, 0x4A43]);
+    assert_eq!(emu.programm_counter, 0x206);
+    let emu = run(&[0x6A42, 0x4A42]);
+    assert_eq!(emu.programm_counter, 0x204);
+}
+
+#[test]
+fn skip_if_registers_equal() {
+    let emu = run(&[0x6107, 0x6207, 0x5120]);
+    assert_eq!(emu.programm_counter, 0x208);
+    let emu = run(&[0x6107, 0x6208, 0x5120]);
+    assert_eq!(emu.programm_counter, 0x206);
+}
+
+#[test]
+fn skip_if_registers_differ() {
+    let emu = run(&[0x6107, 0x6208, 0x9120]);
+    assert_eq!(emu.programm_counter, 0x208);
+    let emu = run(&[0x6107, 0x6207, 0x9120]);
+    assert_eq!(emu.programm_counter, 0x206);
+}
+
+#[test]
+fn skip_jumps_over_long_load() {
+    // F000 NNNN is 4 bytes long
+    let mut emu = emu_with(&[0x3000, 0xF000, 0x1234]);
+    step(&mut emu, 1);
+    assert_eq!(emu.programm_counter, 0x206);
+}
+
+// 5XY2 / 5XY3
+
+#[test]
+fn save_and_load_register_range() {
+    let mut emu = emu_with(&[0xA300, 0x5242, 0x6200, 0x6300, 0x6400, 0x5243]);
+    emu.registers[2] = 1;
+    emu.registers[3] = 2;
+    emu.registers[4] = 3;
+    step(&mut emu, 2);
+    assert_eq!(emu.ram[0x300..0x303], [1, 2, 3]);
+    // I is not changed
+    assert_eq!(emu.i_register, 0x300);
+
+    step(&mut emu, 4);
+    assert_eq!(emu.registers[2..5], [1, 2, 3]);
+}
+
+#[test]
+fn save_register_range_in_reverse() {
+    let mut emu = emu_with(&[0xA300, 0x5422]);
+    emu.registers[2] = 1;
+    emu.registers[3] = 2;
+    emu.registers[4] = 3;
+    step(&mut emu, 2);
+    assert_eq!(emu.ram[0x300..0x303], [3, 2, 1]);
+}
+
+// 6XNN / 7XNN
+
+#[test]
+fn load_immediate() {
+    let emu = run(&[0x6E99]);
+    assert_eq!(emu.registers[0xE], 0x99);
+}
+
+#[test]
+fn add_immediate_wraps_without_carry() {
+    let emu = run(&[0x6AFF, 0x6F00, 0x7A02]);
+    assert_eq!(emu.registers[0xA], 0x01);
+    assert_eq!(emu.registers[0xF], 0);
+}
+
+// 8XY0 - 8XY3
+
+#[test]
+fn move_register() {
+    let emu = run(&[0x6133, 0x8210]);
+    assert_eq!(emu.registers[2], 0x33);
+}
+
+#[test]
+fn logic_operations() {
+    let emu = run(&[0x610C, 0x620A, 0x8121]);
+    assert_eq!(emu.registers[1], 0x0E);
+    let emu = run(&[0x610C, 0x620A, 0x8122]);
+    assert_eq!(emu.registers[1], 0x08);
+    let emu = run(&[0x610C, 0x620A, 0x8123]);
+    assert_eq!(emu.registers[1], 0x06);
+}
+
+#[test]
+fn logic_resets_vf_with_quirk() {
+    for opcode in [0x8121, 0x8122, 0x8123] {
+        let emu = run(&[0x6F05, opcode]);
+        assert_eq!(emu.registers[0xF], 5);
+
+        let mut emu = emu_with(&[0x6F05, opcode]);
+        emu.quirks.logic_resets_vf = true;
+        step(&mut emu, 2);
+        assert_eq!(emu.registers[0xF], 0);
+    }
+}
+
+// 8XY4
+
+#[test]
+fn add_sets_carry() {
+    let emu = run(&[0x61F0, 0x6220, 0x8124]);
+    assert_eq!(emu.registers[1], 0x10);
+    assert_eq!(emu.registers[0xF], 1);
+
+    let emu = run(&[0x6110, 0x6220, 0x6F01, 0x8124]);
+    assert_eq!(emu.registers[1], 0x30);
+    assert_eq!(emu.registers[0xF], 0);
+}
+
+#[test]
+fn add_into_vf_keeps_the_flag() {
+    // the flag is written after the result
+    let emu = run(&[0x6FF0, 0x6120, 0x8F14]);
+    assert_eq!(emu.registers[0xF], 1);
+    let emu = run(&[0x6F10, 0x6120, 0x8F14]);
+    assert_eq!(emu.registers[0xF], 0);
+}
+
+#[test]
+fn add_vf_as_operand() {
+    let emu = run(&[0x6F01, 0x61FF, 0x81F4]);
+    assert_eq!(emu.registers[1], 0x00);
+    assert_eq!(emu.registers[0xF], 1);
+}
+
+// 8XY5 / 8XY7
+
+#[test]
+fn sub_sets_not_borrow() {
+    let emu = run(&[0x6130, 0x6210, 0x8125]);
+    assert_eq!(emu.registers[1], 0x20);
+    assert_eq!(emu.registers[0xF], 1);
+
+    let emu = run(&[0x6110, 0x6230, 0x8125]);
+    assert_eq!(emu.registers[1], 0xE0);
+    assert_eq!(emu.registers[0xF], 0);
+
+    // equal values don't borrow
+    let emu = run(&[0x6110, 0x6210, 0x8125]);
+    assert_eq!(emu.registers[1], 0x00);
+    assert_eq!(emu.registers[0xF], 1);
+}
+
+#[test]
+fn sub_into_vf_keeps_the_flag() {
+    let emu = run(&[0x6F10, 0x6130, 0x8F15]);
+    assert_eq!(emu.registers[0xF], 0);
+    let emu = run(&[0x6F30, 0x6110, 0x8F15]);
+    assert_eq!(emu.registers[0xF], 1);
+}
+
+#[test]
+fn reverse_sub_sets_not_borrow() {
+    let emu = run(&[0x6110, 0x6230, 0x8127]);
+    assert_eq!(emu.registers[1], 0x20);
+    assert_eq!(emu.registers[0xF], 1);
+
+    let emu = run(&[0x6130, 0x6210, 0x8127]);
+    assert_eq!(emu.registers[1], 0xE0);
+    assert_eq!(emu.registers[0xF], 0);
+}
+
+#[test]
+fn reverse_sub_into_vf_keeps_the_flag() {
+    let emu = run(&[0x6F30, 0x6110, 0x8F17]);
+    assert_eq!(emu.registers[0xF], 0);
+}
+
+// 8XY6 / 8XYE
+
+#[test]
+fn shift_right() {
+    let emu = run(&[0x6105, 0x8106]);
+    assert_eq!(emu.registers[1], 0x02);
+    assert_eq!(emu.registers[0xF], 1);
+
+    let emu = run(&[0x6104, 0x8106]);
+    assert_eq!(emu.registers[1], 0x02);
+    assert_eq!(emu.registers[0xF], 0);
+}
+
+#[test]
+fn shift_left() {
+    let emu = run(&[0x6181, 0x810E]);
+    assert_eq!(emu.registers[1], 0x02);
+    assert_eq!(emu.registers[0xF], 1);
+
+    let emu = run(&[0x6141, 0x810E]);
+    assert_eq!(emu.registers[1], 0x82);
+    assert_eq!(emu.registers[0xF], 0);
+}
+
+#[test]
+fn shift_uses_vy_with_quirk() {
+    let mut emu = emu_with(&[0x6101, 0x6206, 0x8126]);
+    emu.quirks.shift_uses_vy = true;
+    step(&mut emu, 3);
+    assert_eq!(emu.registers[1], 0x03);
+    assert_eq!(emu.registers[0xF], 0);
+
+    let mut emu = emu_with(&[0x6101, 0x6281, 0x812E]);
+    emu.quirks.shift_uses_vy = true;
+    step(&mut emu, 3);
+    assert_eq!(emu.registers[1], 0x02);
+    assert_eq!(emu.registers[0xF], 1);
+}
+
+#[test]
+fn shift_vf_keeps_the_flag() {
+    let emu = run(&[0x6F02, 0x8F06]);
+    assert_eq!(emu.registers[0xF], 0);
+    let emu = run(&[0x6F80, 0x8F0E]);
+    assert_eq!(emu.registers[0xF], 1);
+}
+
+// ANNN / BNNN
+
+#[test]
+fn load_i() {
+    let emu = run(&[0xA123]);
+    assert_eq!(emu.i_register, 0x123);
+}
+
+#[test]
+fn jump_with_offset() {
+    let emu = run(&[0x6010, 0x6320, 0xB300]);
+    assert_eq!(emu.programm_counter, 0x310);
+}
+
+#[test]
+fn jump_with_offset_uses_vx_with_quirk() {
+    let mut emu = emu_with(&[0x6010, 0x6320, 0xB300]);
+    emu.quirks.jump_uses_vx = true;
+    step(&mut emu, 3);
+    assert_eq!(emu.programm_counter, 0x320);
+}
+
+// CXNN
+
+#[test]
+fn random_is_masked_and_seeded() {
+    let first = run(&[0xC10F, 0xC2F0, 0xC300]);
+    assert_eq!(first.registers[1] & 0xF0, 0);
+    assert_eq!(first.registers[2] & 0x0F, 0);
+    assert_eq!(first.registers[3], 0);
+
+    // the same seed gives the same numbers
+    let second = run(&[0xC10F, 0xC2F0, 0xC300]);
+    assert_eq!(first.registers, second.registers);
+}
+
+// DXYN
+
+#[test]
+fn draw_sprite() {
+    // the font glyph for 0 is F0 90 90 90 F0
+    let mut emu = emu_with(&[0x6000, 0xF029, 0x6102, 0x6203, 0xD125]);
+    step(&mut emu, 5);
+    assert_eq!(lit_pixels(&emu), 14);
+    assert_eq!(pixel(&emu, 2, 3), 1);
+    assert_eq!(pixel(&emu, 5, 3), 1);
+    assert_eq!(pixel(&emu, 6, 3), 0);
+    assert_eq!(pixel(&emu, 3, 4), 0);
+    assert_eq!(emu.registers[0xF], 0);
+}
+
+#[test]
+fn draw_collision_sets_vf() {
+    let mut emu = emu_with(&[0xF029, 0xD005, 0xD005]);
+    step(&mut emu, 2);
+    assert_eq!(emu.registers[0xF], 0);
+    step(&mut emu, 1);
+    assert_eq!(emu.registers[0xF], 1);
+    assert_eq!(lit_pixels(&emu), 0);
+}
+
+#[test]
+fn draw_with_vf_as_coordinate() {
+    let mut emu = emu_with(&[0x6F08, 0xF029, 0xDFF1]);
+    step(&mut emu, 3);
+    assert_eq!(pixel(&emu, 8, 8), 1);
+    assert_eq!(emu.registers[0xF], 0);
+}
+
+#[test]
+fn draw_wraps_around_the_edges() {
+    // top row of the glyph is 4 pixels wide, starts 2 pixels before the edge
+    let mut emu = emu_with(&[0x603E, 0x611F, 0xF229, 0xD012]);
+    step(&mut emu, 4);
+    assert_eq!(pixel(&emu, 62, 31), 1);
+    assert_eq!(pixel(&emu, 63, 31), 1);
+    assert_eq!(pixel(&emu, 0, 31), 1);
+    assert_eq!(pixel(&emu, 1, 31), 1);
+    // the second row wrapped to the top
+    assert_eq!(pixel(&emu, 62, 0), 1);
+}
+
+#[test]
+fn draw_clips_at_the_edges_with_quirk() {
+    let mut emu = emu_with(&[0x603E, 0x611F, 0xF229, 0xD012]);
+    emu.quirks.clip_sprites = true;
+    step(&mut emu, 4);
+    assert_eq!(pixel(&emu, 62, 31), 1);
+    assert_eq!(pixel(&emu, 63, 31), 1);
+    assert_eq!(pixel(&emu, 0, 31), 0);
+    assert_eq!(pixel(&emu, 62, 0), 0);
+    assert_eq!(lit_pixels(&emu), 2);
+}
+
+#[test]
+fn draw_waits_for_the_display_with_quirk() {
+    let mut emu = emu_with(&[0xD011, 0xD011, 0x6001]);
+    emu.quirks.display_wait = DisplayWait::Always;
+    // the first sprite is drawn, the second waits for the next frame
+    step(&mut emu, 3);
+    assert_eq!(emu.programm_counter, 0x202);
+    assert_eq!(emu.registers[0], 0);
+
+    emu.tick_timers();
+    step(&mut emu, 2);
+    assert_eq!(emu.registers[0], 1);
+    assert_eq!(lit_pixels(&emu), 0);
+}
+
+#[test]
+fn draw_waits_only_in_lores_with_quirk() {
+    let mut emu = emu_with(&[0x00FF, 0xD011, 0xD011, 0x6001]);
+    emu.quirks.display_wait = DisplayWait::Lores;
+    step(&mut emu, 4);
+    assert_eq!(emu.registers[0], 1);
+}
+
+#[test]
+fn draw_counts_collided_rows_in_hires_with_quirk() {
+    // three rows drawn twice, the last one clipped at the bottom
+    let mut emu = emu_with(&[0x00FF, 0x6100, 0x623E, 0xA300, 0xD123, 0xD123]);
+    emu.ram[0x300..0x303].copy_from_slice(&[0xFF, 0xFF, 0xFF]);
+    emu.quirks.clip_sprites = true;
+    emu.quirks.collision_counts_rows = true;
+    step(&mut emu, 5);
+    assert_eq!(emu.registers[0xF], 1);
+    step(&mut emu, 1);
+    assert_eq!(emu.registers[0xF], 3);
+
+    // lores only has the flag
+    let mut emu = emu_with(&[0xA300, 0xD013, 0xD013]);
+    emu.ram[0x300..0x303].copy_from_slice(&[0xFF, 0xFF, 0xFF]);
+    emu.quirks.collision_counts_rows = true;
+    step(&mut emu, 3);
+    assert_eq!(emu.registers[0xF], 1);
+}
+
+#[test]
+fn schip_presets_differ() {
+    let legacy = Quirks::from_name("schip-legacy").unwrap();
+    let modern = Quirks::from_name("schip-modern").unwrap();
+    assert_ne!(legacy, modern);
+    assert_eq!(legacy.display_wait, DisplayWait::Lores);
+    assert_eq!(modern.display_wait, DisplayWait::Off);
+    assert!(legacy.collision_counts_rows && !modern.collision_counts_rows);
+}
+
+#[test]
+fn draw_start_position_wraps() {
+    // 64 + 1 starts at x = 1
+    let mut emu = emu_with(&[0x6041, 0x6100, 0xF229, 0xD011]);
+    step(&mut emu, 4);
+    assert_eq!(pixel(&emu, 1, 0), 1);
+}
+
+#[test]
+fn draw_big_sprite_in_hires() {
+    let mut emu = emu_with(&[0x00FF, 0xA300, 0xD000]);
+    emu.ram[0x300..0x320].copy_from_slice(&[0xFF; 32]);
+    step(&mut emu, 3);
+    assert_eq!(lit_pixels(&emu), 256);
+    assert_eq!(pixel(&emu, 15, 15), 1);
+    assert_eq!(pixel(&emu, 16, 0), 0);
+}
+
+#[test]
+fn draw_second_plane() {
+    let mut emu = emu_with(&[0xF201, 0xF029, 0xD001]);
+    step(&mut emu, 3);
+    assert_eq!(pixel(&emu, 0, 0), 0b10);
+}
+
+#[test]
+fn draw_both_planes_reads_consecutive_data() {
+    let mut emu = emu_with(&[0xF301, 0xA300, 0xD001]);
+    emu.ram[0x300] = 0x80;
+    emu.ram[0x301] = 0x40;
+    step(&mut emu, 3);
+    assert_eq!(pixel(&emu, 0, 0), 0b01);
+    assert_eq!(pixel(&emu, 1, 0), 0b10);
+}
+
+#[test]
+fn draw_out_of_memory_is_an_error() {
+    let mut emu = emu_with(&[0xF000, 0xFFFF, 0xD002]);
+    step(&mut emu, 1);
+    assert_eq!(emu.tick(), Err(EmuError::MemoryOutOfBounds { pc: 0x204, opcode: 0xD002, addr: RAM_SIZE }));
+}
+
+// EX9E / EXA1
+
+#[test]
+fn skip_if_key_pressed() {
+    let mut emu = emu_with(&[0x6A05, 0xEA9E]);
+    emu.keypress(5, true);
+    step(&mut emu, 2);
+    assert_eq!(emu.programm_counter, 0x206);
+
+    let emu = run(&[0x6A05, 0xEA9E]);
+    assert_eq!(emu.programm_counter, 0x204);
+}
+
+#[test]
+fn skip_if_key_not_pressed() {
+    let emu = run(&[0x6A05, 0xEAA1]);
+    assert_eq!(emu.programm_counter, 0x206);
+
+    let mut emu = emu_with(&[0x6A05, 0xEAA1]);
+    emu.keypress(5, true);
+    step(&mut emu, 2);
+    assert_eq!(emu.programm_counter, 0x204);
+}
+
+// F000 NNNN / FN01 / F002
+
+#[test]
+fn long_load_i() {
+    let mut emu = emu_with(&[0xF000, 0xBEEF]);
+    step(&mut emu, 1);
+    assert_eq!(emu.i_register, 0xBEEF);
+    assert_eq!(emu.programm_counter, 0x204);
+}
+
+#[test]
+fn select_planes() {
+    let emu = run(&[0xF301]);
+    assert_eq!(emu.plane_mask, 0b11);
+    let emu = run(&[0xF001]);
+    assert_eq!(emu.plane_mask, 0);
+}
+
+#[test]
+fn load_audio_pattern() {
+    let mut emu = emu_with(&[0xA300, 0xF002]);
+    for idx in 0..AUDIO_PATTERN_SIZE {
+        emu.ram[0x300 + idx] = idx as u8;
+    }
+    step(&mut emu, 2);
+    assert_eq!(emu.audio_pattern[15], 15);
+    assert_eq!(emu.audio_pattern[1], 1);
+}
+
+// FX07 / FX15 / FX18
+
+#[test]
+fn timers() {
+    let mut emu = emu_with(&[0x6A20, 0xFA15, 0xFA18]);
+    step(&mut emu, 3);
+    assert_eq!(emu.delay_timer, 0x20);
+    assert_eq!(emu.sound_timer, 0x20);
+    assert!(emu.buzzer_active());
+
+    emu.tick_timers();
+    assert_eq!(emu.delay_timer, 0x1F);
+    assert_eq!(emu.sound_timer, 0x1F);
+}
+
+#[test]
+fn get_delay() {
+    let mut emu = emu_with(&[0xF307]);
+    emu.delay_timer = 42;
+    step(&mut emu, 1);
+    assert_eq!(emu.registers[3], 42);
+}
+
+// FX0A
+
+#[test]
+fn wait_key_blocks_until_pressed() {
+    let mut emu = emu_with(&[0xF30A]);
+    step(&mut emu, 3);
+    assert_eq!(emu.programm_counter, 0x200);
+
+    emu.keypress(0xB, true);
+    step(&mut emu, 1);
+    assert_eq!(emu.programm_counter, 0x202);
+    assert_eq!(emu.registers[3], 0xB);
+}
+
+// FX1E / FX29 / FX30
+
+#[test]
+fn add_to_i() {
+    let emu = run(&[0xA0FF, 0x6102, 0xF11E]);
+    assert_eq!(emu.i_register, 0x101);
+    // VF is not touched
+    assert_eq!(emu.registers[0xF], 0);
+}
+
+#[test]
+fn font_address() {
+    let emu = run(&[0x610A, 0xF129]);
+    assert_eq!(emu.i_register, 50);
+    assert_eq!(emu.ram[50], 0xF0);
+}
+
+#[test]
+fn big_font_address() {
+    let emu = run(&[0x6101, 0xF130]);
+    assert_eq!(emu.i_register, (BIG_FONTSET_ADDR + 10) as u16);
+}
+
+// FX33
+
+#[test]
+fn bcd() {
+    let emu = run(&[0x61FE, 0xA300, 0xF133]);
+    assert_eq!(emu.ram[0x300..0x303], [2, 5, 4]);
+    let emu = run(&[0x6107, 0xA300, 0xF133]);
+    assert_eq!(emu.ram[0x300..0x303], [0, 0, 7]);
+}
+
+// FX3A
+
+#[test]
+fn set_pitch() {
+    let emu = run(&[0x6170, 0xF13A]);
+    assert_eq!(emu.pitch, 0x70);
+}
+
+// FX55 / FX65
+
+#[test]
+fn store_and_load_registers() {
+    let mut emu = emu_with(&[0xA300, 0xF255, 0x6000, 0x6100, 0x6200, 0xF265]);
+    emu.registers[0] = 7;
+    emu.registers[1] = 8;
+    emu.registers[2] = 9;
+    emu.registers[3] = 10;
+    step(&mut emu, 2);
+    assert_eq!(emu.ram[0x300..0x304], [7, 8, 9, 0]);
+    assert_eq!(emu.i_register, 0x300);
+
+    step(&mut emu, 4);
+    assert_eq!(emu.registers[..4], [7, 8, 9, 10]);
+}
+
+#[test]
+fn store_and_load_increment_i_with_quirk() {
+    for (increment, expected) in [(MemoryIncrement::X, 0x302), (MemoryIncrement::XPlusOne, 0x303)] {
+        let mut emu = emu_with(&[0xA300, 0xF255]);
+        emu.quirks.memory_increment = increment;
+        step(&mut emu, 2);
+        assert_eq!(emu.i_register, expected);
+
+        let mut emu = emu_with(&[0xA300, 0xF265]);
+        emu.quirks.memory_increment = increment;
+        step(&mut emu, 2);
+        assert_eq!(emu.i_register, expected);
+    }
+}
+
+#[test]
+fn store_out_of_memory_is_an_error() {
+    let mut emu = emu_with(&[0xF000, 0xFFFF, 0xF155]);
+    step(&mut emu, 1);
+    assert_eq!(emu.tick(), Err(EmuError::MemoryOutOfBounds { pc: 0x204, opcode: 0xF155, addr: RAM_SIZE }));
+}
+
+// FX75 / FX85
+
+#[test]
+fn rpl_flags() {
+    let mut emu = emu_with(&[0xF275, 0x6000, 0x6100, 0x6200, 0xF185]);
+    emu.registers[0] = 1;
+    emu.registers[1] = 2;
+    emu.registers[2] = 3;
+    step(&mut emu, 5);
+    assert_eq!(emu.rpl_flags[..3], [1, 2, 3]);
+    // only V0 and V1 were loaded back
+    assert_eq!(emu.registers[..3], [1, 2, 0]);
+}
+
+// ERRORS
+
+#[test]
+fn unknown_opcode_is_an_error() {
+    let mut emu = emu_with(&[0x5121]);
+    assert_eq!(emu.tick(), Err(EmuError::UnknownOpcode { pc: 0x200, opcode: 0x5121 }));
+}
+
+#[test]
+fn rom_too_large() {
+    let mut emu = Emu::new();
+    let rom = vec![0; RAM_SIZE];
+    assert!(matches!(emu.load(&rom), Err(EmuError::RomTooLarge { .. })));
+}
@@ -0,0 +1,227 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::*;
+
+// Execution profiler. Counts every executed instruction by address and
+// by opcode pattern, and follows 2NNN / 00EE to know which subroutines
+// are running:
+//
+//   inclusive   cycles spent in a subroutine and everything it calls
+//   exclusive   cycles spent in the subroutine itself
+//
+// A CALL counts for the caller, the RET for the subroutine. The cycles
+// are kept per call path, which is also what the collapsed-stack export
+// writes, one "main;update;draw_player 1234" line per path. Tools like
+// flamegraph.pl and speedscope read that format.
+//
+// Per 60 Hz frame the draw calls and the cycles spent waiting in FX0A
+// are counted.
+
+// lines in the hot spot list of the report
+const HOT_SPOTS: usize = 20;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameProfile {
+    pub cycles: u64,
+    pub draws: u64,
+    pub wait_cycles: u64,   // FX0A without a key pressed
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubroutineProfile {
+    pub addr: u16,
+    pub calls: u64,
+    pub inclusive: u64,
+    pub exclusive: u64,
+}
+
+#[derive(Default)]
+pub struct Profiler {
+    pub pc_counts: HashMap<u16, u64>,
+    pub pattern_counts: HashMap<&'static str, u64>,
+    pub frames: Vec<FrameProfile>,      // finished frames, the current one is not included
+
+    cycles: u64,
+    current_frame: FrameProfile,
+    // subroutines being executed, outermost first, with the stack
+    // pointer after their call
+    call_stack: Vec<u16>,
+    call_depths: Vec<u16>,
+    // cycles per call path, the empty path is the top level
+    paths: HashMap<Vec<u16>, u64>,
+    calls: HashMap<u16, u64>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // sorted by inclusive cycles, the most expensive first
+    pub fn subroutines(&self) -> Vec<SubroutineProfile> {
+        let mut subroutines: HashMap<u16, SubroutineProfile> = HashMap::new();
+        let new = |addr: u16| SubroutineProfile {
+            addr,
+            calls: self.calls.get(&addr).copied().unwrap_or(0),
+            inclusive: 0,
+            exclusive: 0,
+        };
+
+        for (path, cycles) in &self.paths {
+            // recursive calls are counted once per path
+            let unique: HashSet<u16> = path.iter().copied().collect();
+            for addr in unique {
+                subroutines.entry(addr).or_insert_with(|| new(addr)).inclusive += cycles;
+            }
+            if let Some(addr) = path.last() {
+                subroutines.entry(*addr).or_insert_with(|| new(*addr)).exclusive += cycles;
+            }
+        }
+
+        let mut subroutines: Vec<SubroutineProfile> = subroutines.into_values().collect();
+        subroutines.sort_by_key(|subroutine| (Reverse(subroutine.inclusive), subroutine.addr));
+        subroutines
+    }
+
+    // one "outer;inner cycles" line per call path, sorted by path. The top
+    // level is named after the start address
+    pub fn collapsed_stacks(&self, symbols: &Symbols) -> String {
+        let root = symbols.format(START_ADDR);
+        let mut lines: Vec<String> = self.paths.iter()
+            .map(|(path, cycles)| {
+                let names: Vec<String> = path.iter().map(|addr| symbols.format(*addr)).collect();
+                let mut stack = root.clone();
+                for name in names {
+                    stack.push(';');
+                    stack.push_str(&name);
+                }
+                format!("{} {}", stack, cycles)
+            })
+            .collect();
+        lines.sort();
+
+        let mut text = lines.join("\n");
+        text.push('\n');
+        text
+    }
+
+    // readable summary, instructions and labels are looked up in the emulator
+    pub fn report(&self, emulator: &Emu) -> String {
+        let symbols = &emulator.debugger.symbols;
+        let total = self.cycles.max(1);
+        let percent = |count: u64| count as f64 * 100.0 / total as f64;
+        let mut text = String::new();
+
+        let frames = self.frames.len() as u64;
+        let _ = writeln!(text, "# chip8-profile");
+        let _ = writeln!(text, "cycles {}, frames {}", self.cycles, frames);
+
+        // HOT SPOTS
+        let mut pcs: Vec<(u16, u64)> = self.pc_counts.iter().map(|(pc, count)| (*pc, *count)).collect();
+        pcs.sort_by_key(|(pc, count)| (Reverse(*count), *pc));
+        let _ = writeln!(text, "\nhot spots, {} of {} addresses:", pcs.len().min(HOT_SPOTS), pcs.len());
+        let _ = writeln!(text, "{:>10} {:>6}  addr  {:<16} instruction", "cycles", "%", "label");
+        for (pc, count) in pcs.iter().take(HOT_SPOTS) {
+            let instruction = match emulator.instruction_at(*pc) {
+                Some(instruction) => symbols.mnemonic(&instruction),
+                None => "unknown".to_string(),
+            };
+            let label = symbols.describe(*pc).unwrap_or_default();
+            let _ = writeln!(text, "{:>10} {:>5.1}%  {:04X}  {:<16} {}", count, percent(*count), pc, label, instruction);
+        }
+
+        // OPCODES
+        let mut patterns: Vec<(&str, u64)> = self.pattern_counts.iter().map(|(pattern, count)| (*pattern, *count)).collect();
+        patterns.sort_by_key(|(pattern, count)| (Reverse(*count), *pattern));
+        let _ = writeln!(text, "\nopcodes:");
+        let _ = writeln!(text, "{:>10} {:>6}  pattern", "cycles", "%");
+        for (pattern, count) in patterns {
+            let _ = writeln!(text, "{:>10} {:>5.1}%  {}", count, percent(count), pattern);
+        }
+
+        // SUBROUTINES
+        let _ = writeln!(text, "\nsubroutines:");
+        let _ = writeln!(text, "{:>8} {:>10} {:>6} {:>10} {:>6}  subroutine", "calls", "inclusive", "%", "exclusive", "%");
+        let top_level = self.paths.get([].as_slice()).copied().unwrap_or(0);
+        let _ = writeln!(text, "{:>8} {:>10} {:>5.1}% {:>10} {:>5.1}%  (top level)", "", self.cycles, 100.0, top_level, percent(top_level));
+        for subroutine in self.subroutines() {
+            let _ = writeln!(
+                text, "{:>8} {:>10} {:>5.1}% {:>10} {:>5.1}%  {}",
+                subroutine.calls, subroutine.inclusive, percent(subroutine.inclusive),
+                subroutine.exclusive, percent(subroutine.exclusive), symbols.format(subroutine.addr),
+            );
+        }
+
+        // FRAMES
+        let _ = writeln!(text, "\nframes:");
+        let draws: u64 = self.frames.iter().map(|frame| frame.draws).sum();
+        let waits: u64 = self.frames.iter().map(|frame| frame.wait_cycles).sum();
+        let busiest = self.frames.iter().enumerate().max_by_key(|(index, frame)| (frame.draws, Reverse(*index)));
+        let waiting = self.frames.iter().filter(|frame| frame.cycles > 0 && frame.wait_cycles == frame.cycles).count();
+        let per_frame = |count: u64| count as f64 / frames.max(1) as f64;
+
+        let _ = write!(text, "draw calls      {}, {:.1} per frame", draws, per_frame(draws));
+        if let Some((index, frame)) = busiest {
+            let _ = write!(text, ", at most {} in frame {}", frame.draws, index);
+        }
+        let _ = writeln!(text);
+        let _ = writeln!(text, "key wait cycles {}, {:.1} per frame, {} frames only waited for a key", waits, per_frame(waits), waiting);
+
+        text
+    }
+}
+
+impl Emu {
+    // HOOKS
+
+    // called after the instruction at current_pc was executed
+    pub(crate) fn profile_instruction(&mut self, instruction: Instruction, executed: bool) {
+        let Some(profiler) = self.profiler.as_mut() else {
+            return;
+        };
+
+        profiler.cycles += 1;
+        *profiler.pc_counts.entry(self.current_pc).or_insert(0) += 1;
+        *profiler.pattern_counts.entry(instruction.pattern()).or_insert(0) += 1;
+        match profiler.paths.get_mut(profiler.call_stack.as_slice()) {
+            Some(cycles) => *cycles += 1,
+            None => {
+                profiler.paths.insert(profiler.call_stack.clone(), 1);
+            },
+        }
+
+        let frame = &mut profiler.current_frame;
+        frame.cycles += 1;
+        match instruction {
+            // a sprite waiting for the vertical blank isn't drawn yet
+            Instruction::Draw(..) if self.programm_counter != self.current_pc => frame.draws += 1,
+            // still waiting when the instruction runs again
+            Instruction::WaitKey(_) if self.programm_counter == self.current_pc => frame.wait_cycles += 1,
+            Instruction::Call(nnn) if executed => {
+                *profiler.calls.entry(nnn).or_insert(0) += 1;
+                profiler.call_stack.push(nnn);
+                profiler.call_depths.push(self.stack_pointer);
+            },
+            _ => {},
+        }
+        // follows returns, resets and loaded savestates. Started in the
+        // middle of a subroutine the outer ones are unknown
+        while profiler.call_depths.last().is_some_and(|depth| *depth > self.stack_pointer) {
+            profiler.call_stack.pop();
+            profiler.call_depths.pop();
+        }
+    }
+
+    pub(crate) fn profile_frame(&mut self) {
+        if let Some(profiler) = self.profiler.as_mut() {
+            let frame = std::mem::take(&mut profiler.current_frame);
+            profiler.frames.push(frame);
+        }
+    }
+}
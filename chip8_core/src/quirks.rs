@@ -0,0 +1,127 @@
+// Behaviour of opcodes that differ between the CHIP-8 platforms.
+// See Timendus' quirks test for what each of these does.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryIncrement {
+    None,       // I is left unchanged
+    X,          // I += X
+    XPlusOne,   // I += X + 1
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayWait {
+    Off,        // sprites are drawn right away
+    Lores,      // only in lores, like SUPER-CHIP 1.1
+    Always,     // DXYN waits for the next frame, at most one sprite per frame
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    pub shift_uses_vy: bool,    // 8XY6 / 8XYE shift VY into VX
+    pub memory_increment: MemoryIncrement,  // I after FX55 / FX65
+    pub logic_resets_vf: bool,  // 8XY1 / 8XY2 / 8XY3 set VF to 0
+    pub jump_uses_vx: bool,     // BXNN jumps to XNN + VX instead of NNN + V0
+    pub clip_sprites: bool,     // sprites are cut off at the screen edge instead of wrapping
+    pub display_wait: DisplayWait,  // DXYN waits for the vertical blank
+    pub collision_counts_rows: bool,    // hires DXYN sets VF to the rows that collided or were clipped at the bottom
+}
+
+pub const QUIRK_PRESETS: [&str; 6] = [
+    "default",
+    "cosmac-vip",
+    "chip-48",
+    "schip-legacy",
+    "schip-modern",
+    "xo-chip",
+];
+
+// the original behaviour of this emulator
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            memory_increment: MemoryIncrement::None,
+            logic_resets_vf: false,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            display_wait: DisplayWait::Off,
+            collision_counts_rows: false,
+        }
+    }
+}
+
+impl Quirks {
+    pub fn cosmac_vip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            memory_increment: MemoryIncrement::XPlusOne,
+            logic_resets_vf: true,
+            jump_uses_vx: false,
+            clip_sprites: true,
+            display_wait: DisplayWait::Always,
+            collision_counts_rows: false,
+        }
+    }
+
+    pub fn chip_48() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            memory_increment: MemoryIncrement::X,
+            logic_resets_vf: false,
+            jump_uses_vx: true,
+            clip_sprites: true,
+            display_wait: DisplayWait::Off,
+            collision_counts_rows: false,
+        }
+    }
+
+    pub fn schip_legacy() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            memory_increment: MemoryIncrement::None,
+            logic_resets_vf: false,
+            jump_uses_vx: true,
+            clip_sprites: true,
+            display_wait: DisplayWait::Lores,
+            collision_counts_rows: true,
+        }
+    }
+
+    // SUPER-CHIP as Octo runs it: no display wait and a plain
+    // collision flag in hires
+    pub fn schip_modern() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            memory_increment: MemoryIncrement::None,
+            logic_resets_vf: false,
+            jump_uses_vx: true,
+            clip_sprites: true,
+            display_wait: DisplayWait::Off,
+            collision_counts_rows: false,
+        }
+    }
+
+    pub fn xo_chip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            memory_increment: MemoryIncrement::XPlusOne,
+            logic_resets_vf: false,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            display_wait: DisplayWait::Off,
+            collision_counts_rows: false,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "default" => Some(Self::default()),
+            "cosmac-vip" | "vip" => Some(Self::cosmac_vip()),
+            "chip-48" | "chip48" => Some(Self::chip_48()),
+            "schip-legacy" | "schip" => Some(Self::schip_legacy()),
+            "schip-modern" => Some(Self::schip_modern()),
+            "xo-chip" | "xochip" => Some(Self::xo_chip()),
+            _ => None,
+        }
+    }
+}
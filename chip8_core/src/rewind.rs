@@ -0,0 +1,144 @@
+use std::collections::VecDeque;
+
+use crate::*;
+
+// Ring buffer of savestates for rewinding. Only the newest state is kept
+// as a whole, every older one is stored as the difference to the state
+// after it. Consecutive frames differ in a few bytes, so a snapshot
+// usually shrinks from ~74 KB to a few dozen bytes.
+pub struct RewindBuffer {
+    capacity: usize,
+    newest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,  // oldest first
+}
+
+impl RewindBuffer {
+    // capacity is the number of snapshots, one per frame
+    pub fn new(capacity: usize) -> Self {
+        RewindBuffer {
+            capacity: capacity.max(1),
+            newest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    pub fn with_seconds(seconds: f32) -> Self {
+        Self::new((seconds * FRAMES_PER_SECOND as f32) as usize)
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.newest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+    }
+
+    // size of all snapshots in bytes
+    pub fn memory_usage(&self) -> usize {
+        self.newest.as_ref().map_or(0, |state| state.len())
+            + self.deltas.iter().map(|delta| delta.len()).sum::<usize>()
+    }
+
+    pub fn push(&mut self, emulator: &Emu) {
+        let state = SaveState::capture(emulator).to_bytes();
+
+        if let Some(newest) = self.newest.take() {
+            self.deltas.push_back(compress_delta(&state, &newest));
+        }
+        self.newest = Some(state);
+
+        while self.len() > self.capacity {
+            self.deltas.pop_front();
+        }
+    }
+
+    // restores the snapshot before the newest one and drops the newest.
+    // Returns false when there is nothing left to go back to
+    pub fn step_back(&mut self, emulator: &mut Emu) -> bool {
+        let (Some(newest), Some(delta)) = (self.newest.as_ref(), self.deltas.pop_back()) else {
+            return false;
+        };
+
+        let previous = apply_delta(newest, &delta);
+        match SaveState::from_bytes(&previous).and_then(|save| save.restore(emulator)) {
+            Ok(()) => {
+                self.newest = Some(previous);
+                true
+            },
+            // the buffer belongs to another ROM
+            Err(_) => {
+                self.clear();
+                false
+            }
+        }
+    }
+}
+
+// The delta is `base XOR target`, run length encoded as pairs of
+//   varint  number of unchanged bytes
+//   varint  number of changed bytes, followed by those bytes
+// Both states have the same length, they come from the same format.
+pub(crate) fn compress_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let diff: Vec<u8> = base.iter().zip(target).map(|(a, b)| a ^ b).collect();
+    let mut out = Vec::new();
+    let mut pos = 0;
+
+    while pos < diff.len() {
+        let unchanged = diff[pos..].iter().take_while(|byte| **byte == 0).count();
+        pos += unchanged;
+        let changed = diff[pos..].iter().take_while(|byte| **byte != 0).count();
+
+        write_varint(&mut out, unchanged);
+        write_varint(&mut out, changed);
+        out.extend_from_slice(&diff[pos..pos + changed]);
+        pos += changed;
+    }
+
+    out
+}
+
+pub(crate) fn apply_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut out = base.to_vec();
+    let mut pos = 0;
+    let mut reader = delta.iter().copied();
+
+    while let Some(unchanged) = read_varint(&mut reader) {
+        pos += unchanged;
+        let changed = read_varint(&mut reader).unwrap_or(0);
+        for _ in 0..changed {
+            if let (Some(byte), Some(diff)) = (out.get_mut(pos), reader.next()) {
+                *byte ^= diff;
+            }
+            pos += 1;
+        }
+    }
+
+    out
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(reader: &mut impl Iterator<Item = u8>) -> Option<usize> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = reader.next()?;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+    }
+}
@@ -0,0 +1,85 @@
+// Tests for the deltas between rewind snapshots and the ring buffer.
+
+use crate::*;
+use crate::rewind::{apply_delta, compress_delta};
+
+fn round_trip(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let delta = compress_delta(base, target);
+    assert_eq!(apply_delta(base, &delta), target);
+    delta
+}
+
+// DELTAS
+
+#[test]
+fn identical_states_give_one_run() {
+    let state = vec![0x5A; 1000];
+    // 1000 unchanged bytes, then none changed
+    assert_eq!(round_trip(&state, &state), [0xE8, 0x07, 0x00]);
+}
+
+#[test]
+fn long_runs_use_varint_continuation() {
+    let base = vec![0; 1000];
+    let mut target = base.clone();
+    for byte in &mut target[200..500] {
+        *byte = 0xFF;
+    }
+
+    let delta = round_trip(&base, &target);
+    // 200 unchanged, 300 changed, the changed bytes, 500 unchanged, 0 changed
+    assert_eq!(delta[..4], [0xC8, 0x01, 0xAC, 0x02]);
+    assert!(delta[4..304].iter().all(|byte| *byte == 0xFF));
+    assert_eq!(delta[304..], [0xF4, 0x03, 0x00]);
+}
+
+#[test]
+fn changes_at_both_ends() {
+    let base: Vec<u8> = (0..=255).collect();
+    let mut target = base.clone();
+    target[0] ^= 0x80;
+    target[255] ^= 0x01;
+
+    assert_eq!(round_trip(&base, &target), [0x00, 0x01, 0x80, 0xFE, 0x01, 0x01, 0x01]);
+}
+
+// RING BUFFER
+
+fn emu_with_v0(value: u8) -> Emu {
+    let mut emu = Emu::new();
+    emu.load(&[0x12, 0x00]).unwrap();
+    emu.registers[0] = value;
+    emu
+}
+
+#[test]
+fn steps_back_until_the_capacity_runs_out() {
+    let mut buffer = RewindBuffer::new(3);
+    let mut emu = emu_with_v0(0);
+    for value in 1..=5 {
+        buffer.push(&emu_with_v0(value));
+    }
+    // only the newest three are kept
+    assert_eq!(buffer.len(), 3);
+
+    assert!(buffer.step_back(&mut emu));
+    assert_eq!(emu.registers[0], 4);
+    assert!(buffer.step_back(&mut emu));
+    assert_eq!(emu.registers[0], 3);
+    // 2 and 1 were evicted
+    assert!(!buffer.step_back(&mut emu));
+    assert_eq!(emu.registers[0], 3);
+    assert_eq!(buffer.len(), 1);
+}
+
+#[test]
+fn states_of_another_rom_clear_the_buffer() {
+    let mut buffer = RewindBuffer::new(3);
+    buffer.push(&emu_with_v0(1));
+    buffer.push(&emu_with_v0(2));
+
+    let mut other = Emu::new();
+    other.load(&[0x13, 0x00]).unwrap();
+    assert!(!buffer.step_back(&mut other));
+    assert!(buffer.is_empty());
+}
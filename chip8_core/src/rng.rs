@@ -0,0 +1,47 @@
+// xorshift64* generator used by CXNN. Its whole state is one u64,
+// so it can be stored in savestates and replayed exactly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn from_seed(seed: u64) -> Self {
+        // run the seed through splitmix64 so that small seeds
+        // (and 0, which xorshift can't leave) give a usable state
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        Rng {
+            state: if z == 0 { 1 } else { z },
+        }
+    }
+
+    pub fn from_entropy() -> Self {
+        Self::from_seed(rand::random())
+    }
+
+    pub fn from_state(state: u64) -> Self {
+        Rng {
+            state: if state == 0 { 1 } else { state },
+        }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        // the high bits are the best ones
+        (self.next_u64() >> 56) as u8
+    }
+}
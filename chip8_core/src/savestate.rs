@@ -0,0 +1,259 @@
+use std::fmt;
+
+use crate::*;
+
+// Snapshot of everything a running ROM can observe. Quirks, the
+// pause flag and the pressed keys belong to the frontend and are left out.
+
+const MAGIC: &[u8; 4] = b"C8SS";
+pub const SAVESTATE_VERSION: u16 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SaveStateError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    InvalidStackPointer(u16),
+    RomMismatch { expected: u64, found: u64 },
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveStateError::BadMagic => write!(f, "not a savestate file"),
+            SaveStateError::UnsupportedVersion(version) => {
+                write!(f, "savestate version {} is not supported (expected {})", version, SAVESTATE_VERSION)
+            },
+            SaveStateError::Truncated => write!(f, "savestate file is truncated"),
+            SaveStateError::InvalidStackPointer(sp) => {
+                write!(f, "savestate stack pointer {} is larger than the stack ({})", sp, STACK_SIZE)
+            },
+            SaveStateError::RomMismatch { expected, found } => {
+                write!(f, "savestate belongs to ROM {:016X}, loaded ROM is {:016X}", found, expected)
+            },
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+// FNV-1a
+fn hash_bytes(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+    }
+    hash
+}
+
+// identifies the ROM a savestate or recording was made with
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    hash_bytes(rom)
+}
+
+#[derive(Clone)]
+pub struct SaveState {
+    pub rom_hash: u64,
+
+    programm_counter: u16,
+    ram: [u8; RAM_SIZE],
+    registers: [u8; REGISTER_COUNT],
+    i_register: u16,
+    stack_pointer: u16,
+    stack: [u16; STACK_SIZE],
+    screen: [u8; SCREEN_BUFFER_SIZE],
+    hires: bool,
+    plane_mask: u8,
+    delay_timer: u8,
+    sound_timer: u8,
+    rpl_flags: [u8; RPL_FLAG_COUNT],
+    rng: Rng,
+    audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    pitch: u8,
+    has_exited: bool,
+    vblank: bool,
+}
+
+impl SaveState {
+    pub fn capture(emulator: &Emu) -> Self {
+        SaveState {
+            rom_hash: emulator.rom_hash(),
+
+            programm_counter: emulator.programm_counter,
+            ram: emulator.ram,
+            registers: emulator.registers,
+            i_register: emulator.i_register,
+            stack_pointer: emulator.stack_pointer,
+            stack: emulator.stack,
+            screen: emulator.screen,
+            hires: emulator.hires,
+            plane_mask: emulator.plane_mask,
+            delay_timer: emulator.delay_timer,
+            sound_timer: emulator.sound_timer,
+            rpl_flags: emulator.rpl_flags,
+            rng: emulator.rng,
+            audio_pattern: emulator.audio_pattern,
+            pitch: emulator.pitch,
+            has_exited: emulator.has_exited,
+            vblank: emulator.vblank,
+        }
+    }
+
+    // refuses states that were made with another ROM
+    pub fn restore(&self, emulator: &mut Emu) -> Result<(), SaveStateError> {
+        if self.rom_hash != emulator.rom_hash() {
+            return Err(SaveStateError::RomMismatch { expected: emulator.rom_hash(), found: self.rom_hash });
+        }
+
+        emulator.programm_counter = self.programm_counter;
+        emulator.ram = self.ram;
+        emulator.registers = self.registers;
+        emulator.i_register = self.i_register;
+        emulator.stack_pointer = self.stack_pointer;
+        emulator.stack = self.stack;
+        emulator.screen = self.screen;
+        emulator.hires = self.hires;
+        emulator.plane_mask = self.plane_mask;
+        emulator.delay_timer = self.delay_timer;
+        emulator.sound_timer = self.sound_timer;
+        emulator.rpl_flags = self.rpl_flags;
+        emulator.rng = self.rng;
+        emulator.audio_pattern = self.audio_pattern;
+        emulator.pitch = self.pitch;
+        emulator.has_exited = self.has_exited;
+        emulator.vblank = self.vblank;
+        Ok(())
+    }
+
+    // two emulators with the same hash behave the same from here on
+    pub fn hash(&self) -> u64 {
+        hash_bytes(&self.to_bytes())
+    }
+
+    // FILE FORMAT
+    //
+    // all numbers little endian
+    //   "C8SS"  magic
+    //   u16     version
+    //   u64     ROM hash
+    //   u16     PC, I, SP
+    //   u16     stack[STACK_SIZE]
+    //   u8      V0-VF, delay timer, sound timer, hires, plane mask, pitch, exited, vblank
+    //
+    // version 2 added vblank
+    //   u64     rng state
+    //   u8      rpl flags, audio pattern, ram, screen
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(RAM_SIZE + SCREEN_BUFFER_SIZE + 128);
+
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&SAVESTATE_VERSION.to_le_bytes());
+        out.extend_from_slice(&self.rom_hash.to_le_bytes());
+
+        for word in [self.programm_counter, self.i_register, self.stack_pointer] {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        for word in self.stack {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+
+        out.extend_from_slice(&self.registers);
+        out.extend_from_slice(&[
+            self.delay_timer,
+            self.sound_timer,
+            self.hires as u8,
+            self.plane_mask,
+            self.pitch,
+            self.has_exited as u8,
+            self.vblank as u8,
+        ]);
+        out.extend_from_slice(&self.rng.state().to_le_bytes());
+
+        out.extend_from_slice(&self.rpl_flags);
+        out.extend_from_slice(&self.audio_pattern);
+        out.extend_from_slice(&self.ram);
+        out.extend_from_slice(&self.screen);
+
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, SaveStateError> {
+        let mut reader = Reader { data, pos: 0 };
+
+        if reader.bytes(MAGIC.len()) != Ok(&MAGIC[..]) {
+            return Err(SaveStateError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != SAVESTATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+
+        let rom_hash = reader.u64()?;
+
+        let programm_counter = reader.u16()?;
+        let i_register = reader.u16()?;
+        let stack_pointer = reader.u16()?;
+        // a corrupt one would make the next return panic
+        if stack_pointer as usize > STACK_SIZE {
+            return Err(SaveStateError::InvalidStackPointer(stack_pointer));
+        }
+        let mut stack = [0; STACK_SIZE];
+        for word in stack.iter_mut() {
+            *word = reader.u16()?;
+        }
+
+        let registers = reader.array()?;
+        let [delay_timer, sound_timer, hires, plane_mask, pitch, has_exited, vblank] = reader.array()?;
+        let rng = Rng::from_state(reader.u64()?);
+
+        Ok(SaveState {
+            rom_hash,
+            programm_counter,
+            i_register,
+            stack_pointer,
+            stack,
+            registers,
+            delay_timer,
+            sound_timer,
+            hires: hires != 0,
+            plane_mask,
+            pitch,
+            has_exited: has_exited != 0,
+            vblank: vblank != 0,
+            rng,
+            rpl_flags: reader.array()?,
+            audio_pattern: reader.array()?,
+            ram: reader.array()?,
+            screen: reader.array()?,
+        })
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], SaveStateError> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or(SaveStateError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SaveStateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    fn u16(&mut self) -> Result<u16, SaveStateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+}
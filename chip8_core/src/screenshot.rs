@@ -0,0 +1,111 @@
+// Framebuffer dumps for tests and the headless runner.
+
+use crate::Emu;
+
+// one character per pixel, one line per row.
+// '.' is off, '#' plane 1, '+' plane 2, '@' both planes
+pub fn to_ascii(emulator: &Emu) -> String {
+    let width = emulator.screen_width();
+    let mut out = String::with_capacity((width + 1) * emulator.screen_height());
+
+    for row in emulator.get_display().chunks(width) {
+        for pixel in row {
+            out.push(match pixel & 0b11 {
+                0 => '.',
+                1 => '#',
+                2 => '+',
+                _ => '@',
+            });
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+// 8 bit grayscale PNG, every pixel is scaled up to scale x scale
+pub fn to_png(emulator: &Emu, scale: usize) -> Vec<u8> {
+    let scale = scale.max(1);
+    let width = emulator.screen_width() * scale;
+    let height = emulator.screen_height() * scale;
+
+    // every scanline starts with filter type 0
+    let mut raw = Vec::with_capacity((width + 1) * height);
+    for row in emulator.get_display().chunks(emulator.screen_width()) {
+        let mut line = vec![0];
+        for pixel in row {
+            let gray = match pixel & 0b11 {
+                0 => 0x00,
+                1 => 0xFF,
+                2 => 0xAA,
+                _ => 0x55,
+            };
+            line.extend(std::iter::repeat_n(gray, scale));
+        }
+        for _ in 0..scale {
+            raw.extend_from_slice(&line);
+        }
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 0, 0, 0, 0]);    // 8 bit, grayscale, deflate, no filter, no interlace
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+
+    let mut crc_data = kind.to_vec();
+    crc_data.extend_from_slice(data);
+    png.extend_from_slice(&crc32(&crc_data).to_be_bytes());
+}
+
+// zlib stream made of uncompressed deflate blocks, screenshots are small
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
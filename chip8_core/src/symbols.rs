@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::asm;
+use crate::Instruction;
+
+// Labels for addresses, loaded from a symbol file next to the ROM.
+// Two formats are read:
+//
+//   Octo source (.8o)  the labels of the assembled program
+//   text               one "addr label" pair per line, the address in hex:
+//
+//     # comment
+//     200 main
+//     0x2A4 draw_player
+//
+// Addresses between two labels are shown relative to the one before,
+// like main+4.
+
+// tried in this order, game.ch8 -> game.sym, game.ch8.sym, game.8o, game.ch8.8o
+const SYMBOL_EXTENSIONS: [&str; 2] = ["sym", "8o"];
+
+#[derive(Debug)]
+pub enum SymbolError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolError::Io(error) => write!(f, "{}", error),
+            SymbolError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for SymbolError {}
+
+impl From<io::Error> for SymbolError {
+    fn from(error: io::Error) -> Self {
+        SymbolError::Io(error)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Symbols {
+    labels: BTreeMap<u16, String>,      // address -> label
+    addrs: BTreeMap<String, u16>,       // label -> address
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Symbols::default()
+    }
+
+    // the labels of an assembled Octo program
+    pub fn from_labels(labels: &BTreeMap<String, u16>) -> Self {
+        let mut symbols = Symbols::new();
+        for (label, addr) in labels {
+            symbols.insert(*addr, label);
+        }
+        symbols
+    }
+
+    // the "addr label" text format
+    pub fn parse(text: &str) -> Result<Self, SymbolError> {
+        let mut symbols = Symbols::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| SymbolError::Parse { line: index + 1, message };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [addr, label] = fields[..] else {
+                return Err(error(format!("expected [addr label], found [{}]", line)));
+            };
+            let addr = u16::from_str_radix(addr.trim_start_matches("0x"), 16)
+                .map_err(|_| error(format!("invalid address [{}]", addr)))?;
+            if symbols.addrs.get(label).is_some_and(|other| *other != addr) {
+                return Err(error(format!("label [{}] is defined twice", label)));
+            }
+            symbols.insert(addr, label);
+        }
+
+        Ok(symbols)
+    }
+
+    // .8o files are assembled, anything else is the text format
+    pub fn load(path: &str) -> Result<Self, SymbolError> {
+        let text = fs::read_to_string(path)?;
+        if !path.ends_with(".8o") {
+            return Self::parse(&text);
+        }
+
+        let assembly = asm::assemble(&text).map_err(|error| SymbolError::Parse { line: error.line, message: error.message })?;
+        Ok(Self::from_labels(&assembly.labels))
+    }
+
+    // the symbol file next to a ROM, if there is one
+    pub fn find_next_to(rom_path: &str) -> Option<String> {
+        let rom = Path::new(rom_path);
+        let file_name = rom.file_name()?.to_string_lossy().into_owned();
+        SYMBOL_EXTENSIONS.iter()
+            .flat_map(|extension| [rom.with_extension(extension), rom.with_file_name(format!("{}.{}", file_name, extension))])
+            .find(|path| path.is_file() && path != rom)
+            .map(|path| path.to_string_lossy().into_owned())
+    }
+
+    // a second label for an address replaces the first
+    pub fn insert(&mut self, addr: u16, label: &str) {
+        if let Some(old) = self.labels.insert(addr, label.to_string()) {
+            self.addrs.remove(&old);
+        }
+        self.addrs.insert(label.to_string(), addr);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    // address -> label, sorted by address
+    pub fn labels(&self) -> &BTreeMap<u16, String> {
+        &self.labels
+    }
+
+    // the label exactly at addr
+    pub fn label(&self, addr: u16) -> Option<&str> {
+        self.labels.get(&addr).map(String::as_str)
+    }
+
+    pub fn addr_of(&self, label: &str) -> Option<u16> {
+        self.addrs.get(label).copied()
+    }
+
+    // main or main+4, None before the first label
+    pub fn describe(&self, addr: u16) -> Option<String> {
+        self.labels.range(..=addr).next_back().map(|(label_addr, label)| {
+            match addr - label_addr {
+                0 => label.clone(),
+                offset => format!("{}+{}", label, offset),
+            }
+        })
+    }
+
+    // the description, or the hex address without symbols
+    pub fn format(&self, addr: u16) -> String {
+        self.describe(addr).unwrap_or_else(|| format!("{:03X}", addr))
+    }
+
+    // the mnemonic with the address of a jump, call or LD I as label
+    pub fn mnemonic(&self, instruction: &Instruction) -> String {
+        let text = instruction.to_string();
+        let (addr, written) = match *instruction {
+            Instruction::Jump(nnn) | Instruction::Call(nnn) | Instruction::LoadI(nnn) | Instruction::JumpOffset(_, nnn) => {
+                (nnn, format!("0x{:03X}", nnn))
+            },
+            Instruction::LoadLongI(nnnn) => (nnnn, format!("0x{:04X}", nnnn)),
+            _ => return text,
+        };
+
+        match self.label(addr) {
+            Some(label) => text.replacen(&written, label, 1),
+            None => text,
+        }
+    }
+
+    // "draw", "draw+4" or a hex address
+    pub fn resolve(&self, text: &str) -> Option<u16> {
+        let text = text.trim();
+        let (label, offset) = match text.split_once('+') {
+            Some((label, offset)) => (label.trim(), parse_offset(offset.trim())?),
+            None => (text, 0),
+        };
+
+        match self.addr_of(label) {
+            Some(addr) => addr.checked_add(offset),
+            None if offset == 0 => u16::from_str_radix(text.trim_start_matches("0x"), 16).ok(),
+            None => None,
+        }
+    }
+}
+
+// decimal like main+4, or 0x hex
+fn parse_offset(text: &str) -> Option<u16> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
@@ -0,0 +1,158 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+
+use crate::*;
+
+// Instruction trace, one line per executed instruction. The format is
+// stable, version 2 looks like this:
+//
+//   # chip8-trace 2
+//   cycle=0 frame=0 pc=0200 op=6001 v=00000000000000000000000000000000 i=0000 sp=0 dt=00 st=00 ; LD V0, 0x01
+//
+//   cycle   instructions executed before this one, decimal
+//   frame   60 Hz frames (timer ticks) before this one, decimal
+//   pc      address of the instruction, hex
+//   op      first opcode word, hex. F000 NNNN is traced as F000
+//   v       V0 to VF, two hex digits each
+//   i       I register, hex
+//   sp      stack pointer, hex
+//   dt st   delay and sound timer, hex
+//   sym     pc as label+offset, only with symbols loaded and after the first label
+//   ;       the rest of the line is the mnemonic, or "unknown". Target
+//           addresses with a label are written as the label
+//
+// Registers and timers are the values before the instruction runs.
+// Every byte the instruction writes to memory follows as its own line:
+//
+//   write addr=0300 value=01
+//
+// Lines starting with '#' are comments. See tracediff for a parser.
+//
+// Version 2 added the sym field and the labels in the mnemonic, version
+// 1 traces are the same without them. Readers skip fields they don't know.
+
+pub const TRACE_VERSION: u32 = 2;
+pub const TRACE_HEADER: &str = "# chip8-trace 2";
+
+pub struct Tracer {
+    output: Box<dyn Write + Send>,
+    // only instructions inside these ranges are written
+    pub addresses: Option<RangeInclusive<u16>>,
+    pub frames: Option<RangeInclusive<u64>>,
+
+    cycle: u64,
+    frame: u64,
+    // the current instruction was written, so are its memory writes
+    tracing_instruction: bool,
+    // the first write error, tracing stops after it
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn new(output: impl Write + Send + 'static) -> Self {
+        let mut tracer = Tracer {
+            output: Box::new(output),
+            addresses: None,
+            frames: None,
+            cycle: 0,
+            frame: 0,
+            tracing_instruction: false,
+            error: None,
+        };
+        tracer.write_line(TRACE_HEADER);
+        tracer
+    }
+
+    pub fn to_file(path: &str) -> io::Result<Self> {
+        Ok(Tracer::new(BufWriter::new(File::create(path)?)))
+    }
+
+    // flushes the output, reports the first error that happened
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.output.flush()
+    }
+
+    fn write_line(&mut self, line: &str) {
+        if self.error.is_none() {
+            if let Err(error) = writeln!(self.output, "{}", line) {
+                self.error = Some(error);
+            }
+        }
+    }
+
+    fn is_traced(&self, pc: u16) -> bool {
+        self.addresses.as_ref().is_none_or(|range| range.contains(&pc))
+            && self.frames.as_ref().is_none_or(|range| range.contains(&self.frame))
+    }
+}
+
+// 200-2FF, hex addresses, both included. A single address is allowed
+pub fn parse_address_range(text: &str) -> Option<RangeInclusive<u16>> {
+    let (first, last) = text.split_once('-').unwrap_or((text, text));
+    let first = u16::from_str_radix(first.trim_start_matches("0x"), 16).ok()?;
+    let last = u16::from_str_radix(last.trim_start_matches("0x"), 16).ok()?;
+    (first <= last).then_some(first..=last)
+}
+
+// 10-20, decimal frames, both included. A single frame is allowed
+pub fn parse_frame_range(text: &str) -> Option<RangeInclusive<u64>> {
+    let (first, last) = text.split_once('-').unwrap_or((text, text));
+    let (first, last) = (first.parse().ok()?, last.parse().ok()?);
+    (first <= last).then_some(first..=last)
+}
+
+impl Emu {
+    // HOOKS
+
+    // called right after the opcode at current_pc was fetched
+    pub(crate) fn trace_instruction(&mut self, opcode: u16) {
+        let line = match &self.tracer {
+            Some(tracer) if tracer.is_traced(self.current_pc) => Some(self.trace_line(tracer, opcode)),
+            Some(_) => None,
+            None => return,
+        };
+
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.tracing_instruction = line.is_some();
+            if let Some(line) = line {
+                tracer.write_line(&line);
+            }
+            tracer.cycle += 1;
+        }
+    }
+
+    pub(crate) fn trace_write(&mut self, addr: usize, value: u8) {
+        if let Some(tracer) = self.tracer.as_mut().filter(|tracer| tracer.tracing_instruction) {
+            tracer.write_line(&format!("write addr={:04X} value={:02X}", addr, value));
+        }
+    }
+
+    fn trace_line(&self, tracer: &Tracer, opcode: u16) -> String {
+        let registers: String = self.registers.iter().map(|register| format!("{:02X}", register)).collect();
+        let mnemonic = match self.instruction_at(self.current_pc) {
+            Some(instruction) => self.debugger.symbols.mnemonic(&instruction),
+            None => "unknown".to_string(),
+        };
+
+        let symbol = match self.debugger.symbols.describe(self.current_pc) {
+            Some(symbol) => format!(" sym={}", symbol),
+            None => String::new(),
+        };
+
+        format!(
+            "cycle={} frame={} pc={:04X} op={:04X} v={} i={:04X} sp={:X} dt={:02X} st={:02X}{} ; {}",
+            tracer.cycle, tracer.frame, self.current_pc, opcode, registers,
+            self.i_register, self.stack_pointer, self.delay_timer, self.sound_timer, symbol, mnemonic,
+        )
+    }
+
+    pub(crate) fn trace_frame(&mut self) {
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.frame += 1;
+        }
+    }
+}
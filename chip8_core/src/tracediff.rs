@@ -0,0 +1,293 @@
+// Lines up two instruction traces (see trace.rs for the format) and
+// finds the first instruction where they diverge. Traces of other
+// emulators only need the fields they have, a field is compared when
+// both traces contain it. cycle and frame are never compared, emulators
+// count them differently. Traces without write lines need ignore_memory.
+// Our traces of version 1 and 2 are read, later versions are refused.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, BufRead};
+
+use crate::{REGISTER_COUNT, TRACE_VERSION};
+
+#[derive(Debug)]
+pub enum TraceError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TraceError::Io(error) => write!(f, "{}", error),
+            TraceError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+impl From<io::Error> for TraceError {
+    fn from(error: io::Error) -> Self {
+        TraceError::Io(error)
+    }
+}
+
+// one instruction with the memory it wrote
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub line: usize,    // in the trace file
+    pub text: String,   // the instruction line as written
+
+    pub cycle: Option<u64>,
+    pub frame: Option<u64>,
+    pub pc: Option<u16>,
+    pub opcode: Option<u16>,
+    pub registers: Option<[u8; REGISTER_COUNT]>,
+    pub i_register: Option<u16>,
+    pub stack_pointer: Option<u16>,
+    pub delay_timer: Option<u8>,
+    pub sound_timer: Option<u8>,
+    pub mnemonic: Option<String>,
+
+    pub writes: Vec<(u16, u8)>,     // address, value in the order written
+}
+
+impl TraceEntry {
+    fn parse(line: usize, text: &str) -> Result<Self, TraceError> {
+        let error = |message: String| TraceError::Parse { line, message };
+
+        let (fields, mnemonic) = match text.split_once(';') {
+            Some((fields, mnemonic)) => (fields, Some(mnemonic.trim().to_string())),
+            None => (text, None),
+        };
+        let mut entry = TraceEntry { line, text: text.to_string(), mnemonic, ..TraceEntry::default() };
+
+        for field in fields.split_whitespace() {
+            let (key, value) = field.split_once('=').ok_or_else(|| error(format!("invalid field [{}]", field)))?;
+            let invalid = || error(format!("invalid value [{}]", field));
+            let hex16 = || u16::from_str_radix(value, 16).map_err(|_| invalid());
+            let hex8 = || u8::from_str_radix(value, 16).map_err(|_| invalid());
+
+            match key {
+                "cycle" => entry.cycle = Some(value.parse().map_err(|_| invalid())?),
+                "frame" => entry.frame = Some(value.parse().map_err(|_| invalid())?),
+                "pc" => entry.pc = Some(hex16()?),
+                "op" => entry.opcode = Some(hex16()?),
+                "i" => entry.i_register = Some(hex16()?),
+                "sp" => entry.stack_pointer = Some(hex16()?),
+                "dt" => entry.delay_timer = Some(hex8()?),
+                "st" => entry.sound_timer = Some(hex8()?),
+                "v" => {
+                    // other tools write these, slicing needs ASCII
+                    if value.len() != REGISTER_COUNT * 2 || !value.is_ascii() {
+                        return Err(invalid());
+                    }
+                    let mut registers = [0; REGISTER_COUNT];
+                    for (index, register) in registers.iter_mut().enumerate() {
+                        *register = u8::from_str_radix(&value[index * 2..index * 2 + 2], 16).map_err(|_| invalid())?;
+                    }
+                    entry.registers = Some(registers);
+                },
+                // fields added by later versions
+                _ => {},
+            }
+        }
+
+        Ok(entry)
+    }
+}
+
+// reads a trace one instruction at a time, traces get big
+pub struct TraceReader<R: BufRead> {
+    input: R,
+    line: usize,
+    pending: Option<TraceEntry>,
+}
+
+impl<R: BufRead> TraceReader<R> {
+    pub fn new(input: R) -> Self {
+        TraceReader { input, line: 0, pending: None }
+    }
+
+    fn next_entry(&mut self) -> Result<Option<TraceEntry>, TraceError> {
+        loop {
+            let mut text = String::new();
+            if self.input.read_line(&mut text)? == 0 {
+                return Ok(self.pending.take());
+            }
+            self.line += 1;
+            let text = text.trim();
+
+            if let Some(version) = text.strip_prefix("# chip8-trace ") {
+                match version.parse::<u32>() {
+                    Ok(version) if (1..=TRACE_VERSION).contains(&version) => {},
+                    _ => return Err(TraceError::Parse { line: self.line, message: format!("unsupported trace version [{}]", version) }),
+                }
+                continue;
+            }
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+
+            if let Some(fields) = text.strip_prefix("write ") {
+                let write = parse_write(fields).ok_or_else(|| TraceError::Parse { line: self.line, message: format!("invalid write [{}]", text) })?;
+                match self.pending.as_mut() {
+                    Some(entry) => entry.writes.push(write),
+                    None => return Err(TraceError::Parse { line: self.line, message: "write before the first instruction".to_string() }),
+                }
+                continue;
+            }
+
+            // the writes of the pending entry are complete
+            let entry = TraceEntry::parse(self.line, text)?;
+            if let Some(finished) = self.pending.replace(entry) {
+                return Ok(Some(finished));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for TraceReader<R> {
+    type Item = Result<TraceEntry, TraceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().transpose()
+    }
+}
+
+// addr=0300 value=01
+fn parse_write(fields: &str) -> Option<(u16, u8)> {
+    let mut addr = None;
+    let mut value = None;
+    for field in fields.split_whitespace() {
+        match field.split_once('=')? {
+            ("addr", text) => addr = Some(u16::from_str_radix(text, 16).ok()?),
+            ("value", text) => value = Some(u8::from_str_radix(text, 16).ok()?),
+            _ => {},
+        }
+    }
+    Some((addr?, value?))
+}
+
+// loose matching
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DiffOptions {
+    pub ignore_timers: bool,
+    pub ignore_vf: bool,
+    pub ignore_memory: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Difference {
+    // name is V0-VF, I, PC, SP, DT, ST or opcode
+    Register { name: String, ours: u16, reference: u16 },
+    // written by the instruction, None if only one trace wrote it
+    Memory { addr: u16, ours: Option<u8>, reference: Option<u8> },
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let byte = |value: &Option<u8>| match value {
+            Some(value) => format!("{:02X}", value),
+            None => "not written".to_string(),
+        };
+
+        match self {
+            Difference::Register { name, ours, reference } => {
+                write!(f, "{}: ours {:X}, reference {:X}", name, ours, reference)
+            },
+            Difference::Memory { addr, ours, reference } => {
+                write!(f, "memory {:04X}: ours {}, reference {}", addr, byte(ours), byte(reference))
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DiffResult {
+    Identical { instructions: usize },
+    // one trace ended early
+    Length { instructions: usize, ours_ended: bool },
+    Diverged(Box<Divergence>),
+}
+
+#[derive(Debug)]
+pub struct Divergence {
+    pub index: usize,   // instructions before the divergence
+    pub ours: TraceEntry,
+    pub reference: TraceEntry,
+    // the instruction before, its result shows up in the registers
+    pub previous: Option<(TraceEntry, TraceEntry)>,
+    pub differences: Vec<Difference>,
+}
+
+pub fn diff<A: BufRead, B: BufRead>(mut ours: TraceReader<A>, mut reference: TraceReader<B>, options: DiffOptions) -> Result<DiffResult, TraceError> {
+    let mut previous = None;
+    let mut index = 0;
+
+    loop {
+        let (a, b) = match (ours.next().transpose()?, reference.next().transpose()?) {
+            (Some(a), Some(b)) => (a, b),
+            (None, None) => return Ok(DiffResult::Identical { instructions: index }),
+            (a, _) => return Ok(DiffResult::Length { instructions: index, ours_ended: a.is_none() }),
+        };
+
+        let differences = compare(&a, &b, options);
+        if !differences.is_empty() {
+            return Ok(DiffResult::Diverged(Box::new(Divergence { index, ours: a, reference: b, previous, differences })));
+        }
+
+        previous = Some((a, b));
+        index += 1;
+    }
+}
+
+fn compare(ours: &TraceEntry, reference: &TraceEntry, options: DiffOptions) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    let mut check = |name: &str, a: Option<u16>, b: Option<u16>| {
+        if let (Some(a), Some(b)) = (a, b) {
+            if a != b {
+                differences.push(Difference::Register { name: name.to_string(), ours: a, reference: b });
+            }
+        }
+    };
+
+    check("PC", ours.pc, reference.pc);
+    check("opcode", ours.opcode, reference.opcode);
+    if let (Some(a), Some(b)) = (ours.registers, reference.registers) {
+        for index in 0..REGISTER_COUNT {
+            if options.ignore_vf && index == 0xF {
+                continue;
+            }
+            check(&format!("V{:X}", index), Some(a[index] as u16), Some(b[index] as u16));
+        }
+    }
+    check("I", ours.i_register, reference.i_register);
+    check("SP", ours.stack_pointer, reference.stack_pointer);
+    if !options.ignore_timers {
+        check("DT", ours.delay_timer.map(u16::from), reference.delay_timer.map(u16::from));
+        check("ST", ours.sound_timer.map(u16::from), reference.sound_timer.map(u16::from));
+    }
+
+    if !options.ignore_memory {
+        differences.extend(compare_writes(&ours.writes, &reference.writes));
+    }
+    differences
+}
+
+// the last value written to every address counts
+fn compare_writes(ours: &[(u16, u8)], reference: &[(u16, u8)]) -> Vec<Difference> {
+    let ours: BTreeMap<u16, u8> = ours.iter().copied().collect();
+    let reference: BTreeMap<u16, u8> = reference.iter().copied().collect();
+
+    let mut addrs: Vec<u16> = ours.keys().chain(reference.keys()).copied().collect();
+    addrs.sort_unstable();
+    addrs.dedup();
+
+    addrs.into_iter()
+        .filter(|addr| ours.get(addr) != reference.get(addr))
+        .map(|addr| Difference::Memory { addr, ours: ours.get(&addr).copied(), reference: reference.get(&addr).copied() })
+        .collect()
+}
@@ -0,0 +1,39 @@
+// Helpers shared by the integration tests. Every test file compiles
+// them on its own and uses only some, hence the allow(dead_code)
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use chip8_core::*;
+
+// emulator with the opcodes loaded at 0x200
+#[allow(dead_code)]
+pub fn emu_with(program: &[u16]) -> Emu {
+    let mut emu = Emu::new();
+    let bytes: Vec<u8> = program.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+    emu.load(&bytes).unwrap();
+    emu
+}
+
+// collects a trace in memory
+#[allow(dead_code)]
+#[derive(Clone, Default)]
+pub struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
+impl Buffer {
+    pub fn lines(&self) -> Vec<String> {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap().lines().map(str::to_string).collect()
+    }
+}
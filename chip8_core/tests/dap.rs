@@ -0,0 +1,271 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::thread;
+
+use serde_json::{json, Value};
+
+use chip8_core::dap::DapServer;
+
+// 200 line 2, 202 line 3, 204 line 4, 206 line 5,
+// 208 line 8, 20A line 9, 20C line 10
+const SOURCE: &str = ": main
+  v0 := 1
+  sub
+  v1 := 2
+  loop again
+
+: sub
+  i := 0x300
+  save v1
+  return
+";
+
+// a minimal editor, talks to a server running in another thread
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    seq: i64,
+    events: VecDeque<Value>,
+    source_path: PathBuf,
+}
+
+impl Client {
+    fn connect(name: &str) -> Self {
+        let source_path = std::env::temp_dir().join(format!("chip8_dap_{}_{}.8o", name, std::process::id()));
+        fs::write(&source_path, SOURCE).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            stream.set_nodelay(true).unwrap();
+            let mut server = DapServer::new(stream.try_clone().unwrap(), stream);
+            server.unthrottled = true;
+            server.run().unwrap();
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_nodelay(true).unwrap();
+        Client {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+            seq: 1,
+            events: VecDeque::new(),
+            source_path,
+        }
+    }
+
+    fn read_message(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            self.reader.read_line(&mut header).unwrap();
+            if header.trim().is_empty() {
+                break;
+            }
+            length = header.trim().strip_prefix("Content-Length: ").unwrap().parse().unwrap();
+        }
+        let mut body = vec![0; length];
+        self.reader.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    // the body of the response, events are kept for wait_event
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        let seq = self.seq;
+        self.seq += 1;
+        let body = json!({ "seq": seq, "type": "request", "command": command, "arguments": arguments }).to_string();
+        write!(self.writer, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+
+        loop {
+            let message = self.read_message();
+            if message["type"] == "event" {
+                self.events.push_back(message);
+            } else if message["request_seq"] == seq {
+                assert_eq!(message["success"], true, "{}", message);
+                return message["body"].clone();
+            }
+        }
+    }
+
+    fn wait_event(&mut self, name: &str) -> Value {
+        loop {
+            let message = match self.events.pop_front() {
+                Some(message) => message,
+                None => self.read_message(),
+            };
+            if message["event"] == name {
+                return message["body"].clone();
+            }
+        }
+    }
+
+    fn launch(&mut self, stop_on_entry: bool) {
+        self.request("initialize", json!({ "adapterID": "chip8" }));
+        self.wait_event("initialized");
+        let path = self.source_path.to_str().unwrap().to_string();
+        self.request("launch", json!({ "program": path, "stopOnEntry": stop_on_entry }));
+    }
+
+    fn set_breakpoints(&mut self, lines: &[u64]) -> Value {
+        let path = self.source_path.to_str().unwrap().to_string();
+        let breakpoints: Vec<Value> = lines.iter().map(|line| json!({ "line": line })).collect();
+        self.request("setBreakpoints", json!({ "source": { "path": path }, "breakpoints": breakpoints }))
+    }
+
+    fn top_frame(&mut self) -> Value {
+        self.request("stackTrace", json!({ "threadId": 1 }))["stackFrames"][0].clone()
+    }
+
+    fn register(&mut self, name: &str) -> String {
+        let variables = self.request("variables", json!({ "variablesReference": 1 }));
+        let variable = variables["variables"].as_array().unwrap().iter().find(|variable| variable["name"] == name).unwrap().clone();
+        variable["value"].as_str().unwrap().to_string()
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.source_path);
+    }
+}
+
+#[test]
+fn source_breakpoints_map_to_lines() {
+    let mut client = Client::connect("breakpoints");
+    client.launch(false);
+
+    // lines 6 and 7 have no code, the breakpoint moves to line 8
+    let breakpoints = client.set_breakpoints(&[9, 6]);
+    assert_eq!(breakpoints["breakpoints"][0]["line"], 9);
+    assert_eq!(breakpoints["breakpoints"][1]["line"], 8);
+    assert_eq!(breakpoints["breakpoints"][1]["verified"], true);
+
+    client.request("configurationDone", json!({}));
+    assert_eq!(client.wait_event("stopped")["reason"], "breakpoint");
+
+    let frame = client.top_frame();
+    assert_eq!(frame["line"], 8);
+    assert_eq!(frame["name"], "sub");
+    assert_eq!(frame["instructionPointerReference"], "0x208");
+
+    // the caller is the second frame
+    let frames = client.request("stackTrace", json!({ "threadId": 1 }));
+    assert_eq!(frames["stackFrames"][1]["line"], 3);
+    assert_eq!(frames["stackFrames"][1]["name"], "main+2");
+
+    client.request("continue", json!({ "threadId": 1 }));
+    client.wait_event("stopped");
+    assert_eq!(client.top_frame()["line"], 9);
+}
+
+#[test]
+fn steps_by_line() {
+    let mut client = Client::connect("steps");
+    client.launch(true);
+    client.request("configurationDone", json!({}));
+    assert_eq!(client.wait_event("stopped")["reason"], "entry");
+    assert_eq!(client.top_frame()["line"], 2);
+
+    client.request("next", json!({ "threadId": 1 }));
+    assert_eq!(client.wait_event("stopped")["reason"], "step");
+    assert_eq!(client.top_frame()["line"], 3);
+
+    // over the call
+    client.request("next", json!({ "threadId": 1 }));
+    client.wait_event("stopped");
+    assert_eq!(client.top_frame()["line"], 4);
+    assert_eq!(client.register("V1"), "0x00");
+    assert_eq!(client.register("I"), "0x0300");
+}
+
+#[test]
+fn step_in_and_out() {
+    let mut client = Client::connect("step_in");
+    client.launch(true);
+    client.request("configurationDone", json!({}));
+    client.wait_event("stopped");
+
+    client.request("next", json!({ "threadId": 1 }));
+    client.wait_event("stopped");
+    client.request("stepIn", json!({ "threadId": 1 }));
+    client.wait_event("stopped");
+    assert_eq!(client.top_frame()["line"], 8);
+
+    let stack = client.request("variables", json!({ "variablesReference": 2 }));
+    assert_eq!(stack["variables"][0]["value"], "0x204 (main+4)");
+
+    client.request("stepOut", json!({ "threadId": 1 }));
+    client.wait_event("stopped");
+    assert_eq!(client.top_frame()["line"], 4);
+}
+
+#[test]
+fn registers_and_memory() {
+    let mut client = Client::connect("variables");
+    client.launch(true);
+    client.request("configurationDone", json!({}));
+    client.wait_event("stopped");
+
+    let scopes = client.request("scopes", json!({ "frameId": 0 }));
+    assert_eq!(scopes["scopes"][0]["name"], "Registers");
+
+    let set = client.request("setVariable", json!({ "variablesReference": 1, "name": "V3", "value": "0x2a" }));
+    assert_eq!(set["value"], "0x2A");
+    assert_eq!(client.register("V3"), "0x2A");
+    assert_eq!(client.register("PC"), "0x0200");
+
+    // row 0x20 is the program at 0x200
+    let memory = client.request("variables", json!({ "variablesReference": 3, "start": 0x20, "count": 1 }));
+    assert_eq!(memory["variables"][0]["name"], "0200");
+    assert!(memory["variables"][0]["value"].as_str().unwrap().starts_with("60 01 22 08"));
+}
+
+#[test]
+fn pause_stops_a_running_rom() {
+    let mut client = Client::connect("pause");
+    client.launch(false);
+    client.request("configurationDone", json!({}));
+    // the ROM ends in a loop on line 5, the stack trace of a running
+    // ROM shows when it got there
+    let mut polls = 0;
+    while client.top_frame()["line"] != 5 {
+        polls += 1;
+        assert!(polls < 10_000, "the ROM never reached its loop");
+    }
+
+    client.request("pause", json!({ "threadId": 1 }));
+    assert_eq!(client.wait_event("stopped")["reason"], "pause");
+    assert_eq!(client.top_frame()["line"], 5);
+
+    client.request("disconnect", json!({}));
+}
+
+#[test]
+fn conditional_breakpoints_and_evaluate() {
+    let mut client = Client::connect("conditions");
+    client.launch(false);
+
+    let path = client.source_path.to_str().unwrap().to_string();
+    let breakpoints = client.request("setBreakpoints", json!({ "source": { "path": path }, "breakpoints": [
+        { "line": 9, "condition": "V1 != 0" },
+        { "line": 4, "condition": "V0 == 1 && ram[0x300] == 1" },
+        { "line": 5, "condition": "V1 +" },
+    ]}));
+    assert_eq!(breakpoints["breakpoints"][0]["verified"], true);
+    assert_eq!(breakpoints["breakpoints"][2]["verified"], false);
+    assert_eq!(breakpoints["breakpoints"][2]["message"], "invalid condition, column 5: expected a value, found [end of expression]");
+
+    // V1 is still 0 on line 9
+    client.request("configurationDone", json!({}));
+    assert_eq!(client.wait_event("stopped")["reason"], "breakpoint");
+    assert_eq!(client.top_frame()["line"], 4);
+
+    let result = client.request("evaluate", json!({ "expression": "ram[0x300] + V0", "context": "watch" }));
+    assert_eq!(result["result"], "0x2 (2)");
+    let result = client.request("evaluate", json!({ "expression": "PC in 0x200..0x208" }));
+    assert_eq!(result["result"], "true");
+}
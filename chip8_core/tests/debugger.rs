@@ -0,0 +1,158 @@
+use chip8_core::*;
+
+mod common;
+use common::emu_with;
+
+// 200: v0 := 1
+// 202: call 20A
+// 204: v1 := 2
+// 206: jump 206
+// 208: nop
+// 20A: i := 300
+// 20C: save v1
+// 20E: return
+const PROGRAM: [u16; 8] = [0x6001, 0x220A, 0x6102, 0x1206, 0x0000, 0xA300, 0xF155, 0x00EE];
+
+#[test]
+fn breakpoint_stops_and_resumes() {
+    let mut emu = emu_with(&PROGRAM);
+    emu.debugger.toggle_breakpoint(0x204);
+
+    assert_eq!(emu.run_until(100, |_| false), StopReason::Breakpoint { pc: 0x204 });
+    assert_eq!(emu.programm_counter, 0x204);
+    assert_eq!(emu.registers[1], 0);
+
+    // continuing runs over the breakpoint
+    assert_eq!(emu.run_until(100, |_| false), StopReason::CycleLimit);
+    assert_eq!(emu.registers[1], 2);
+}
+
+#[test]
+fn run_frame_stops_at_breakpoint() {
+    let mut emu = emu_with(&PROGRAM);
+    emu.debugger.toggle_breakpoint(0x20A);
+
+    let frame = emu.run_frame(10).unwrap();
+    assert_eq!(frame.stop, Some(StopReason::Breakpoint { pc: 0x20A }));
+    assert_eq!(emu.programm_counter, 0x20A);
+
+    let frame = emu.run_frame(10).unwrap();
+    assert_eq!(frame.stop, None);
+    assert_eq!(emu.programm_counter, 0x206);
+}
+
+#[test]
+fn memory_write_watchpoint() {
+    let mut emu = emu_with(&PROGRAM);
+    emu.debugger.watch_memory(0x301, 0x301, WatchKind::Write);
+
+    assert_eq!(emu.run_until(100, |_| false), StopReason::MemoryWrite { pc: 0x20C, addr: 0x301, old: 0, new: 0 });
+    // the instruction has finished
+    assert_eq!(emu.programm_counter, 0x20E);
+}
+
+#[test]
+fn memory_read_watchpoint_ignores_fetches() {
+    let mut emu = emu_with(&[0xA200, 0xF065, 0x1204]);
+    emu.debugger.watch_memory(0x200, 0x201, WatchKind::Read);
+
+    assert_eq!(emu.run_until(100, |_| false), StopReason::MemoryRead { pc: 0x202, addr: 0x200, value: 0xA2 });
+}
+
+#[test]
+fn register_watchpoint() {
+    let mut emu = emu_with(&PROGRAM);
+    emu.debugger.watch_register(Register::I);
+
+    assert_eq!(emu.run_until(100, |_| false), StopReason::RegisterChange { pc: 0x20A, register: Register::I, old: 0, new: 0x300 });
+}
+
+#[test]
+fn step_over_runs_the_whole_subroutine() {
+    let mut emu = emu_with(&PROGRAM);
+    assert_eq!(emu.step_into(), StopReason::StepDone);
+    assert_eq!(emu.step_over(100), StopReason::StepDone);
+    assert_eq!(emu.programm_counter, 0x204);
+    assert_eq!(emu.ram[0x300], 1);
+}
+
+#[test]
+fn step_over_stops_at_breakpoint_inside() {
+    let mut emu = emu_with(&PROGRAM);
+    emu.debugger.toggle_breakpoint(0x20C);
+    emu.step_into();
+    assert_eq!(emu.step_over(100), StopReason::Breakpoint { pc: 0x20C });
+}
+
+#[test]
+fn step_into_and_out_of_subroutine() {
+    let mut emu = emu_with(&PROGRAM);
+    emu.step_into();
+    emu.step_into();
+    assert_eq!(emu.programm_counter, 0x20A);
+    assert_eq!(emu.call_stack(), &[0x204]);
+
+    assert_eq!(emu.step_out(100), StopReason::StepDone);
+    assert_eq!(emu.programm_counter, 0x204);
+    assert!(emu.call_stack().is_empty());
+}
+
+#[test]
+fn steps_work_while_paused() {
+    let mut emu = emu_with(&PROGRAM);
+    emu.is_paused = true;
+    emu.step_into();
+    assert_eq!(emu.registers[0], 1);
+    assert!(emu.is_paused);
+}
+
+#[test]
+fn run_until_condition_exit_and_fault() {
+    let mut emu = emu_with(&PROGRAM);
+    assert_eq!(emu.run_until(100, |emu| emu.registers[1] == 2), StopReason::Condition);
+    assert_eq!(emu.programm_counter, 0x206);
+
+    let mut emu = emu_with(&[0x00FD]);
+    assert_eq!(emu.run_until(100, |_| false), StopReason::Exited);
+
+    let mut emu = emu_with(&[0x00EE]);
+    assert_eq!(emu.run_until(100, |_| false), StopReason::Fault(EmuError::StackUnderflow { pc: 0x200, opcode: 0x00EE }));
+}
+
+#[test]
+fn timers_tick_while_debugging() {
+    let mut emu = emu_with(&[0x6005, 0xF015, 0x1204]);
+    emu.debugger.cycles_per_frame = 10;
+    emu.run_until(22, |_| false);
+    assert_eq!(emu.delay_timer, 3);
+}
+
+#[test]
+fn registers_by_name() {
+    let mut emu = emu_with(&PROGRAM);
+    emu.set_register(Register::V(0xA), 0x1FF);
+    emu.set_register(Register::I, 0x123);
+    assert_eq!(emu.register(Register::V(0xA)), 0xFF);
+    assert_eq!(emu.register(Register::I), 0x123);
+    assert_eq!(emu.register(Register::Pc), 0x200);
+}
+
+#[test]
+fn remembers_recent_writes() {
+    // 200: i := 300, 202: save v0
+    // 204: i := 310, 206: save v1
+    // 208: i := 300, 20A: save v0
+    // 20C: jump 20C
+    let program = [0xA300, 0xF055, 0xA310, 0xF155, 0xA300, 0xF055, 0x120C];
+    let mut emu = emu_with(&program);
+    emu.run_frame(10).unwrap();
+    assert_eq!(emu.recent_writes().count(), 0);
+
+    let mut emu = emu_with(&program);
+    emu.debugger.track_writes = true;
+    emu.run_frame(10).unwrap();
+
+    // 0x300 was written again last
+    let writes: Vec<u16> = emu.recent_writes().collect();
+    assert_eq!(writes, [0x300, 0x311, 0x310]);
+}
@@ -0,0 +1,123 @@
+use chip8_core::*;
+
+mod common;
+use common::emu_with;
+
+fn eval(emu: &Emu, text: &str) -> ExprValue {
+    Expression::parse(text).unwrap().evaluate(emu).unwrap()
+}
+
+fn parse_error(text: &str) -> String {
+    Expression::parse(text).unwrap_err().to_string()
+}
+
+#[test]
+fn evaluates_against_the_state() {
+    let mut emu = Emu::new();
+    emu.registers[3] = 0x10;
+    emu.i_register = 0x300;
+    emu.ram[0x300] = 5;
+    emu.stack[0] = 0x204;
+    emu.stack_pointer = 1;
+    emu.delay_timer = 0;
+
+    assert_eq!(eval(&emu, "V3 == 0x10 && ram[I] > 4"), ExprValue::Bool(true));
+    assert_eq!(eval(&emu, "PC in 0x200..0x240 && DT == 0"), ExprValue::Bool(true));
+    assert_eq!(eval(&emu, "pc in 0x100..0x200"), ExprValue::Bool(false));
+    assert_eq!(eval(&emu, "pc in 0x100..=0x200"), ExprValue::Bool(true));
+    assert_eq!(eval(&emu, "stack[SP - 1] + 2"), ExprValue::Number(0x206));
+    assert_eq!(eval(&emu, "ram[i + 1] | 0b1000 << 1"), ExprValue::Number(0x10));
+    assert_eq!(eval(&emu, "2 + 3 * 4 == 14 || !true"), ExprValue::Bool(true));
+    assert_eq!(eval(&emu, "-(v3 / 3) % 4"), ExprValue::Number(-1));
+    assert_eq!(eval(&emu, "~0 & 0xFF"), ExprValue::Number(0xFF));
+}
+
+#[test]
+fn formats_values() {
+    assert_eq!(ExprValue::Number(0x2A).to_string(), "0x2A (42)");
+    assert_eq!(ExprValue::Number(-3).to_string(), "-3");
+    assert_eq!(ExprValue::Bool(false).to_string(), "false");
+}
+
+#[test]
+fn reports_bad_expressions() {
+    assert_eq!(parse_error(""), "column 1: empty expression");
+    assert_eq!(parse_error("V3 = 1"), "column 4: [=] is not a comparison, use [==]");
+    assert_eq!(parse_error("VG == 1"), "column 1: unknown name [VG], expected V0-VF, I, PC, SP, DT, ST, ram[...], stack[...] or a label");
+    assert_eq!(parse_error("V1 + true"), "column 6: [+] needs a number, found a bool");
+    assert_eq!(parse_error("V1 && V2"), "column 1: [&&] needs a bool, found a number");
+    assert_eq!(parse_error("1 < V2 < 3"), "column 8: comparisons can't be chained, combine them with [&&]");
+    assert_eq!(parse_error("ram[I"), "column 6: expected []], found [end of expression]");
+    assert_eq!(parse_error("(V1 == 2"), "column 9: expected [)], found [end of expression]");
+    assert_eq!(parse_error("V1 == 2 V2"), "column 9: unexpected [V2] after the expression");
+    assert_eq!(parse_error("PC in 0x200"), "column 12: expected [..] or [..=], found [end of expression]");
+    assert_eq!(parse_error("0xZZ"), "column 1: invalid number [0xZZ]");
+    assert_eq!(parse_error("V1 # 2"), "column 4: unexpected character [#]");
+
+    let error = Expression::parse_condition("V1 + 1", &Symbols::new()).unwrap_err();
+    assert_eq!(error.to_string(), "column 1: a condition must be a bool, found a number");
+}
+
+#[test]
+fn reports_evaluation_errors() {
+    let emu = Emu::new();
+    let error = Expression::parse("ram[0x10000]").unwrap().evaluate(&emu).unwrap_err();
+    assert_eq!(error.to_string(), "column 1: ram[65536] is out of range, the size is 65536");
+    let error = Expression::parse("stack[-1] == 0").unwrap().evaluate(&emu).unwrap_err();
+    assert_eq!(error.to_string(), "column 1: stack[-1] is out of range, the size is 16");
+    let error = Expression::parse("V0 / V1").unwrap().evaluate(&emu).unwrap_err();
+    assert_eq!(error.to_string(), "column 4: division by zero");
+
+    // the right side of && only runs when needed
+    assert_eq!(eval(&emu, "V1 != 0 && 10 / V1 > 2"), ExprValue::Bool(false));
+}
+
+#[test]
+fn conditional_breakpoints() {
+    // 200: v0 += 1
+    // 202: jump 200
+    let mut emu = emu_with(&[0x7001, 0x1200]);
+    emu.debugger.toggle_breakpoint(0x200);
+    emu.debugger.set_condition(0x200, Some(Expression::parse_condition("V0 == 5", &Symbols::new()).unwrap()));
+
+    assert_eq!(emu.run_until(100, |_| false), StopReason::Breakpoint { pc: 0x200 });
+    assert_eq!(emu.registers[0], 5);
+
+    // the condition is checked again after continuing
+    emu.debugger.set_condition(0x200, Some(Expression::parse_condition("V0 >= 7", &Symbols::new()).unwrap()));
+    assert_eq!(emu.run_until(100, |_| false), StopReason::Breakpoint { pc: 0x200 });
+    assert_eq!(emu.registers[0], 7);
+
+    // without a condition every pass stops
+    emu.debugger.set_condition(0x200, None);
+    assert_eq!(emu.run_until(100, |_| false), StopReason::Breakpoint { pc: 0x200 });
+    assert_eq!(emu.registers[0], 8);
+    assert!(emu.breakpoint_hit(0x200));
+    assert!(!emu.breakpoint_hit(0x202));
+}
+
+#[test]
+fn watch_values() {
+    let mut emu = emu_with(&[0x6A2A]);
+    emu.debugger.watches.push(Expression::parse("VA").unwrap());
+    emu.debugger.watches.push(Expression::parse("ram[0x10000]").unwrap());
+    emu.step_into();
+
+    let values = emu.watch_values();
+    assert_eq!(values[0].0.to_string(), "VA");
+    assert_eq!(values[0].1, Ok(ExprValue::Number(0x2A)));
+    assert!(values[1].1.is_err());
+}
+
+#[test]
+fn parses_breakpoint_specs() {
+    let (addr, condition) = parse_breakpoint("0x300", &Symbols::new()).unwrap();
+    assert_eq!((addr, condition.is_none()), (0x300, true));
+
+    let (addr, condition) = parse_breakpoint("2A4 if V0 == 1", &Symbols::new()).unwrap();
+    assert_eq!(addr, 0x2A4);
+    assert_eq!(condition.unwrap().to_string(), "V0 == 1");
+
+    assert_eq!(parse_breakpoint("xyz", &Symbols::new()).unwrap_err().to_string(), "column 1: invalid address or unknown label [xyz]");
+    assert_eq!(parse_breakpoint("300 if V0 +", &Symbols::new()).unwrap_err().to_string(), "column 12: expected a value, found [end of expression]");
+}
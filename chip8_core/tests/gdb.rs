@@ -0,0 +1,173 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use chip8_core::*;
+use chip8_core::gdb::GdbStub;
+
+// 200: v0 := 1
+// 202: call 20A
+// 204: v1 := 2
+// 206: jump 206
+// 208: nop
+// 20A: i := 300
+// 20C: save v1
+// 20E: return
+const PROGRAM: [u16; 8] = [0x6001, 0x220A, 0x6102, 0x1206, 0x0000, 0xA300, 0xF155, 0x00EE];
+
+// a minimal front-end, talks to a stub running in another thread
+struct Client {
+    stream: TcpStream,
+    server: thread::JoinHandle<Emu>,
+}
+
+impl Client {
+    fn connect() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let mut emu = Emu::new();
+            let bytes: Vec<u8> = PROGRAM.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+            emu.load(&bytes).unwrap();
+
+            let (stream, _) = listener.accept().unwrap();
+            let mut stub = GdbStub::new(stream, 10);
+            stub.unthrottled = true;
+            stub.run(&mut emu).unwrap();
+            emu
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_nodelay(true).unwrap();
+        Client { stream, server }
+    }
+
+    fn send(&mut self, data: &str) -> String {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", data, checksum).unwrap();
+        assert_eq!(self.read_byte(), b'+');
+
+        // $reply#xx
+        assert_eq!(self.read_byte(), b'$');
+        let mut reply = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => reply.push(byte),
+            }
+        }
+        let mut checksum = [0; 2];
+        self.stream.read_exact(&mut checksum).unwrap();
+        self.stream.write_all(b"+").unwrap();
+
+        String::from_utf8(reply).unwrap()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    fn detach(mut self) -> Emu {
+        assert_eq!(self.send("D"), "OK");
+        self.server.join().unwrap()
+    }
+}
+
+#[test]
+fn reads_and_writes_registers() {
+    let mut client = Client::connect();
+
+    assert_eq!(client.send("?"), "S05");
+    // V0-VF, I, PC 0x200 little endian, SP, DT, ST
+    assert_eq!(client.send("g"), format!("{}00000002000000", "00".repeat(16)));
+
+    assert_eq!(client.send("P3=2a"), "OK");
+    assert_eq!(client.send("P10=3412"), "OK");
+    assert_eq!(client.send("p3"), "2a");
+    assert_eq!(client.send("p10"), "3412");
+    // wrong size and unknown register
+    assert_eq!(client.send("P10=12"), "E01");
+    assert_eq!(client.send("p15"), "E01");
+
+    let emu = client.detach();
+    assert_eq!(emu.registers[3], 0x2A);
+    assert_eq!(emu.i_register, 0x1234);
+}
+
+#[test]
+fn unknown_packets_get_an_empty_reply() {
+    let mut client = Client::connect();
+
+    assert_eq!(client.send("é"), "");
+    assert_eq!(client.send("X"), "");
+    assert_eq!(client.send("?"), "S05");
+    client.detach();
+}
+
+#[test]
+fn reads_and_writes_memory() {
+    let mut client = Client::connect();
+
+    assert_eq!(client.send("m200,4"), "6001220a");
+    assert_eq!(client.send("M300,2:beef"), "OK");
+    assert_eq!(client.send("m300,2"), "beef");
+    assert_eq!(client.send("mffff,2"), "E01");
+
+    let emu = client.detach();
+    assert_eq!(emu.ram[0x300..0x302], [0xBE, 0xEF]);
+}
+
+#[test]
+fn breakpoints_step_and_continue() {
+    let mut client = Client::connect();
+
+    assert_eq!(client.send("s"), "S05");
+    assert_eq!(client.send("p11"), "0202");
+
+    assert_eq!(client.send("Z0,20c,2"), "OK");
+    assert_eq!(client.send("c"), "S05");
+    assert_eq!(client.send("p11"), "0c02");
+    assert_eq!(client.send("p12"), "01");
+
+    // continuing leaves the breakpoint
+    assert_eq!(client.send("z0,20c,2"), "OK");
+    assert_eq!(client.send("Z0,204,2"), "OK");
+    assert_eq!(client.send("c"), "S05");
+    assert_eq!(client.send("p11"), "0402");
+
+    let emu = client.detach();
+    assert_eq!(emu.registers[0], 1);
+    assert!(emu.debugger.breakpoints.is_empty());
+}
+
+#[test]
+fn write_watchpoint() {
+    let mut client = Client::connect();
+
+    assert_eq!(client.send("Z2,301,1"), "OK");
+    assert_eq!(client.send("c"), "T05watch:301;");
+    assert_eq!(client.send("p11"), "0e02");
+
+    client.detach();
+}
+
+#[test]
+fn interrupt_stops_continue() {
+    let mut client = Client::connect();
+
+    // the ROM ends in a loop at 206
+    write!(client.stream, "$c#63").unwrap();
+    assert_eq!(client.read_byte(), b'+');
+    client.stream.write_all(&[0x03]).unwrap();
+
+    let mut reply = [0; 7];
+    client.stream.read_exact(&mut reply).unwrap();
+    assert_eq!(&reply[..4], b"$S02");
+    client.stream.write_all(b"+").unwrap();
+
+    assert_eq!(client.send("p11"), "0602");
+    client.detach();
+}
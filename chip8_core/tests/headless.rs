@@ -0,0 +1,72 @@
+// Golden-image tests: run a test ROM in the headless runner and
+// compare the final framebuffer with a known good one.
+
+use std::fs;
+use std::process::Command;
+
+use chip8_core::*;
+
+fn headless(args: &[&str]) -> i32 {
+    let dir = env!("CARGO_MANIFEST_DIR");
+    let status = Command::new(env!("CARGO_BIN_EXE_headless"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .expect("headless runner did not start")
+        .status;
+    status.code().expect("headless runner was killed")
+}
+
+#[test]
+fn test_opcode_matches_golden_image() {
+    let status = headless(&["../roms/test_opcode.ch8", "--frames", "60", "--expect", "tests/golden/test_opcode.txt"]);
+    assert_eq!(status, 0);
+}
+
+#[test]
+fn wrong_framebuffer_fails() {
+    // the image of the first frames differs from the finished one
+    let status = headless(&["../roms/test_opcode.ch8", "--frames", "1", "--cycles", "1", "--expect", "tests/golden/test_opcode.txt"]);
+    assert_eq!(status, 3);
+}
+
+#[test]
+fn missing_rom_fails() {
+    assert_eq!(headless(&["does_not_exist.ch8"]), 1);
+}
+
+#[test]
+fn writes_collapsed_stacks() {
+    let path = std::env::temp_dir().join(format!("chip8_profile_{}.folded", std::process::id()));
+    let status = headless(&["../roms/test_opcode.ch8", "--frames", "60", "--ascii", "-", "--profile-stacks", path.to_str().unwrap()]);
+    assert_eq!(status, 0);
+
+    // test_opcode calls one subroutine at 242
+    let stacks = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(stacks, "200 597\n200;242 3\n");
+}
+
+#[test]
+fn movie_desync_fails() {
+    // two seconds without input, tampered in the second one
+    let mut emu = Emu::new();
+    emu.load(&fs::read("../roms/test_opcode.ch8").unwrap()).unwrap();
+    let mut movie = Movie::new(&emu, 10);
+    for _ in 0..120 {
+        emu.run_frame(10).unwrap();
+        movie.record_frame(0, &emu);
+    }
+
+    let path = std::env::temp_dir().join(format!("chip8_movie_{}.txt", std::process::id()));
+    let run = |movie: &Movie| {
+        fs::write(&path, movie.to_text()).unwrap();
+        headless(&["../roms/test_opcode.ch8", "--movie", path.to_str().unwrap()])
+    };
+
+    assert_eq!(run(&movie), 0);
+    // the whole movie is played, not only the default second
+    movie.frames[100].state_hash ^= 1;
+    assert_eq!(run(&movie), 4);
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,112 @@
+use chip8_core::*;
+
+mod common;
+use common::emu_with;
+
+// 200: v0 := random 0xFF
+// 202: v1 := key
+// 204: delay := v1
+// 206: jump 200
+const PROGRAM: [u16; 4] = [0xC0FF, 0xF10A, 0xF115, 0x1200];
+
+const KEYS: [u16; 5] = [0x0000, 0x0020, 0x0020, 0x0000, 0x8001];
+
+fn recorded_movie() -> Movie {
+    let mut emu = emu_with(&PROGRAM);
+    emu.set_seed(42);
+    emu.quirks = Quirks::cosmac_vip();
+    let mut movie = Movie::new(&emu, 8);
+
+    for keys in KEYS {
+        for id in 0..NUM_KEYS {
+            emu.keypress(id, keys & (1 << id) != 0);
+        }
+        emu.run_frame(8).unwrap();
+        movie.record_frame(keys, &emu);
+    }
+    movie
+}
+
+// plays the movie like the front-ends do, stops at the first desync
+fn play(movie: &Movie) -> Result<usize, MovieError> {
+    let mut emu = emu_with(&PROGRAM);
+    movie.apply(&mut emu)?;
+
+    let mut frame = 0;
+    while movie.press_keys(frame, &mut emu) {
+        emu.run_frame(movie.cycles_per_frame).unwrap();
+        movie.verify(frame, &emu)?;
+        frame += 1;
+    }
+    Ok(frame)
+}
+
+#[test]
+fn text_round_trip() {
+    let movie = recorded_movie();
+    let text = movie.to_text();
+    assert!(text.starts_with("chip8-movie 1\n"));
+    assert!(text.contains("\ndisplay_wait always\n"));
+    assert!(text.contains("\nframes\n0000 "));
+
+    assert_eq!(Movie::from_text(&text).unwrap(), movie);
+    assert_eq!(play(&Movie::from_text(&text).unwrap()), Ok(KEYS.len()));
+}
+
+#[test]
+fn comments_and_optional_settings() {
+    let movie = recorded_movie();
+    // movies from before the display wait quirks don't have them
+    let text: String = movie.to_text().lines()
+        .filter(|line| !line.starts_with("display_wait") && !line.starts_with("collision_counts_rows"))
+        .map(|line| format!("{}  # comment\n", line))
+        .collect();
+
+    let parsed = Movie::from_text(&text).unwrap();
+    assert_eq!(parsed.quirks.display_wait, DisplayWait::Off);
+    assert_eq!(parsed.frames, movie.frames);
+}
+
+#[test]
+fn reports_parse_errors() {
+    let text = recorded_movie().to_text();
+    let error = |text: &str| Movie::from_text(text).unwrap_err().to_string();
+
+    assert_eq!(error(""), "line 1: not a movie file");
+    assert_eq!(error("chip8-movie 2\n"), "line 1: movie version 2 is not supported (expected 1)");
+    assert_eq!(error(&text.replace("seed 42\n", "")), "line 1: missing seed");
+    assert_eq!(error(&text.replace("seed 42", "seed -1")), "line 3: invalid value [-1] for seed");
+    assert_eq!(error(&text.replace("shift_uses_vy true", "shift_uses_vy no")), "line 5: [no] is not true or false");
+    assert_eq!(error(&text.replace("display_wait always", "display_wait never")), "line 10: invalid value [never] for display_wait");
+    assert_eq!(error(&text.replace("cycles_per_frame 8", "speed 8")), "line 4: unknown setting [speed]");
+    assert_eq!(error(&text.replace("frames\n0000 ", "frames\nXXXX ")).split(':').next(), Some("line 13"));
+}
+
+#[test]
+fn movies_of_other_roms_are_refused() {
+    let movie = recorded_movie();
+    let mut other = emu_with(&[0x1200]);
+    let error = movie.apply(&mut other).unwrap_err();
+    assert_eq!(error, MovieError::RomMismatch { expected: movie.rom_hash, found: other.rom_hash() });
+    assert_eq!(error.to_string(), format!("movie was recorded with ROM {:016X}, loaded ROM is {:016X}", movie.rom_hash, other.rom_hash()));
+}
+
+#[test]
+fn playback_finds_the_first_desync() {
+    // another key in frame 2 changes the delay timer
+    let mut movie = recorded_movie();
+    let recorded = movie.frames[2].state_hash;
+    movie.frames[2].keys = 0x0040;
+
+    let Err(MovieError::Desync { frame, expected, found }) = play(&movie) else {
+        panic!("the movie should desync");
+    };
+    assert_eq!(frame, 2);
+    assert_eq!(expected, recorded);
+    assert_ne!(found, recorded);
+
+    // another seed changes V0 right away
+    let mut movie = recorded_movie();
+    movie.seed = 7;
+    assert!(matches!(play(&movie), Err(MovieError::Desync { frame: 0, .. })));
+}
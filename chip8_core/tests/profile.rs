@@ -0,0 +1,95 @@
+use chip8_core::*;
+
+mod common;
+use common::emu_with;
+
+// 200: draw
+// 202: draw
+// 204: v0 := key
+// 206: jump 206
+// 208: draw:  inner
+// 20A:        sprite v0 v1 5
+// 20C:        return
+// 20E: inner: return
+const PROGRAM: [u16; 8] = [0x2208, 0x2208, 0xF00A, 0x1206, 0x220E, 0xD015, 0x00EE, 0x00EE];
+
+fn symbols() -> Symbols {
+    Symbols::parse("200 main\n208 draw\n20E inner\n").unwrap()
+}
+
+#[test]
+fn counts_cycles_per_subroutine() {
+    let mut emu = emu_with(&PROGRAM);
+    emu.profiler = Some(Profiler::new());
+    emu.run_frame(20).unwrap();
+    emu.run_frame(20).unwrap();
+
+    let profiler = emu.profiler.as_ref().unwrap();
+    assert_eq!(profiler.cycles(), 40);
+    assert_eq!(profiler.pc_counts[&0x204], 30);
+    assert_eq!(profiler.pc_counts[&0x20A], 2);
+    assert_eq!(profiler.pattern_counts["FX0A"], 30);
+    assert_eq!(profiler.pattern_counts["2NNN"], 4);
+    assert_eq!(profiler.pattern_counts["00EE"], 4);
+
+    assert_eq!(profiler.subroutines(), vec![
+        SubroutineProfile { addr: 0x208, calls: 2, inclusive: 8, exclusive: 6 },
+        SubroutineProfile { addr: 0x20E, calls: 2, inclusive: 2, exclusive: 2 },
+    ]);
+    assert_eq!(profiler.collapsed_stacks(&symbols()), "main 32\nmain;draw 6\nmain;draw;inner 2\n");
+}
+
+#[test]
+fn counts_draws_and_key_waits_per_frame() {
+    let mut emu = emu_with(&PROGRAM);
+    emu.profiler = Some(Profiler::new());
+    emu.run_frame(20).unwrap();
+    emu.run_frame(20).unwrap();
+
+    let profiler = emu.profiler.as_ref().unwrap();
+    assert_eq!(profiler.frames, vec![
+        FrameProfile { cycles: 20, draws: 2, wait_cycles: 10 },
+        FrameProfile { cycles: 20, draws: 0, wait_cycles: 20 },
+    ]);
+
+    // a pressed key ends the wait
+    emu.keypress(3, true);
+    emu.run_frame(2).unwrap();
+    let profiler = emu.profiler.as_ref().unwrap();
+    assert_eq!(profiler.frames[2], FrameProfile { cycles: 2, draws: 0, wait_cycles: 0 });
+}
+
+#[test]
+fn starts_inside_a_subroutine() {
+    let mut emu = emu_with(&PROGRAM);
+    // stopped in inner, called from draw
+    emu.step_into();
+    emu.step_into();
+    emu.profiler = Some(Profiler::new());
+    for _ in 0..8 {
+        emu.tick().unwrap();
+    }
+
+    // the returns from the unknown calls count for the top level
+    let profiler = emu.profiler.as_ref().unwrap();
+    assert_eq!(profiler.collapsed_stacks(&Symbols::new()), "200 4\n200;208 3\n200;208;20E 1\n");
+}
+
+#[test]
+fn writes_a_report() {
+    let mut emu = emu_with(&PROGRAM);
+    emu.debugger.symbols = symbols();
+    emu.profiler = Some(Profiler::new());
+    emu.run_frame(20).unwrap();
+    emu.run_frame(20).unwrap();
+
+    let report = emu.profiler.as_ref().unwrap().report(&emu);
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[1], "cycles 40, frames 2");
+    assert!(lines.contains(&"        30  75.0%  0204  main+4           LD V0, K"));
+    assert!(lines.contains(&"         1   2.5%  0202  main+2           CALL draw"));
+    assert!(lines.contains(&"        30  75.0%  FX0A"));
+    assert!(lines.contains(&"       2          8  20.0%          6  15.0%  draw"));
+    assert!(lines.contains(&"draw calls      2, 1.0 per frame, at most 2 in frame 0"));
+    assert!(lines.contains(&"key wait cycles 30, 15.0 per frame, 1 frames only waited for a key"));
+}
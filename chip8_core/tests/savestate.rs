@@ -0,0 +1,120 @@
+use chip8_core::*;
+
+mod common;
+use common::emu_with;
+
+// 200: v0 := 0x2A
+// 202: i := 300
+// 204: bcd v0
+// 206: call 20A
+// 208: jump 208
+// 20A: sprite v0 v0 5
+// 20C: delay := v0
+// 20E: jump 20E
+const PROGRAM: [u16; 8] = [0x602A, 0xA300, 0xF033, 0x220A, 0x1208, 0xD005, 0xF015, 0x120E];
+
+// the state after the program ran, with something in every part
+fn running_emu() -> Emu {
+    let mut emu = emu_with(&PROGRAM);
+    emu.set_seed(7);
+    for _ in 0..8 {
+        emu.tick().unwrap();
+    }
+    emu
+}
+
+#[test]
+fn bytes_round_trip() {
+    let emu = running_emu();
+    let bytes = SaveState::capture(&emu).to_bytes();
+    assert_eq!(&bytes[..4], b"C8SS");
+
+    let state = SaveState::from_bytes(&bytes).unwrap();
+    assert_eq!(state.to_bytes(), bytes);
+    assert_eq!(state.hash(), SaveState::capture(&emu).hash());
+
+    let mut restored = emu_with(&PROGRAM);
+    state.restore(&mut restored).unwrap();
+    assert_eq!(restored.programm_counter, emu.programm_counter);
+    assert_eq!(restored.registers, emu.registers);
+    assert_eq!(restored.i_register, emu.i_register);
+    assert_eq!(restored.stack_pointer, 1);
+    assert_eq!(restored.stack, emu.stack);
+    assert_eq!(restored.delay_timer, 0x2A);
+    assert_eq!(restored.ram[0x300..0x303], [0, 4, 2]);
+    assert_eq!(restored.get_display(), emu.get_display());
+    assert_eq!(restored.rng.state(), emu.rng.state());
+}
+
+#[test]
+fn truncated_states_are_refused() {
+    let bytes = SaveState::capture(&running_emu()).to_bytes();
+
+    for len in [bytes.len() - 1, 20, 6] {
+        assert_eq!(SaveState::from_bytes(&bytes[..len]).err(), Some(SaveStateError::Truncated), "{} bytes", len);
+    }
+    assert_eq!(SaveState::from_bytes(&bytes[..2]).err(), Some(SaveStateError::BadMagic));
+}
+
+#[test]
+fn invalid_stack_pointers_are_refused() {
+    let mut bytes = SaveState::capture(&running_emu()).to_bytes();
+    // magic, version, ROM hash, PC, I
+    let sp = 4 + 2 + 8 + 2 + 2;
+    assert_eq!(bytes[sp..sp + 2], [1, 0]);
+
+    bytes[sp..sp + 2].copy_from_slice(&17u16.to_le_bytes());
+    assert_eq!(SaveState::from_bytes(&bytes).err(), Some(SaveStateError::InvalidStackPointer(17)));
+
+    // a full stack is fine
+    bytes[sp..sp + 2].copy_from_slice(&16u16.to_le_bytes());
+    assert!(SaveState::from_bytes(&bytes).is_ok());
+}
+
+#[test]
+fn other_versions_are_refused() {
+    let mut bytes = SaveState::capture(&running_emu()).to_bytes();
+    bytes[4..6].copy_from_slice(&1u16.to_le_bytes());
+    assert_eq!(SaveState::from_bytes(&bytes).err(), Some(SaveStateError::UnsupportedVersion(1)));
+
+    bytes[..4].copy_from_slice(b"C8XX");
+    assert_eq!(SaveState::from_bytes(&bytes).err(), Some(SaveStateError::BadMagic));
+}
+
+#[test]
+fn the_display_wait_is_saved() {
+    // 200: sprite v0 v0 1
+    // 202: sprite v0 v0 1
+    // 204: v1 := 1
+    let vip_emu = || {
+        let mut emu = emu_with(&[0xD001, 0xD001, 0x6101]);
+        emu.quirks = Quirks::cosmac_vip();
+        emu
+    };
+    let mut emu = vip_emu();
+    emu.tick().unwrap();
+    let drawn = SaveState::capture(&emu);
+    // the timers are 0, only the vertical blank changes
+    emu.tick_timers();
+    assert_ne!(SaveState::capture(&emu).hash(), drawn.hash());
+
+    // after the first sprite the second one waits for the next frame
+    let mut restored = vip_emu();
+    SaveState::from_bytes(&drawn.to_bytes()).unwrap().restore(&mut restored).unwrap();
+    restored.tick().unwrap();
+    restored.tick().unwrap();
+    assert_eq!(restored.programm_counter, 0x202);
+}
+
+#[test]
+fn states_of_other_roms_are_refused() {
+    let emu = running_emu();
+    let state = SaveState::from_bytes(&SaveState::capture(&emu).to_bytes()).unwrap();
+
+    let mut other = emu_with(&[0x1200]);
+    let error = state.restore(&mut other).err();
+    assert_eq!(error, Some(SaveStateError::RomMismatch { expected: other.rom_hash(), found: emu.rom_hash() }));
+    // nothing was restored
+    assert_eq!(other.programm_counter, 0x200);
+    assert_eq!(other.registers[0], 0);
+}
@@ -0,0 +1,158 @@
+use std::fs;
+
+use chip8_core::*;
+use chip8_core::disasm;
+
+mod common;
+use common::Buffer;
+
+const SYMBOL_FILE: &str = "# made by hand
+200 main
+0x20A draw_player
+
+2A0 sprite
+";
+
+fn symbols() -> Symbols {
+    Symbols::parse(SYMBOL_FILE).unwrap()
+}
+
+#[test]
+fn parses_the_text_format() {
+    let symbols = symbols();
+    assert_eq!(symbols.len(), 3);
+    assert_eq!(symbols.label(0x20A), Some("draw_player"));
+    assert_eq!(symbols.addr_of("sprite"), Some(0x2A0));
+
+    let error = Symbols::parse("200 main\n300\n").unwrap_err();
+    assert_eq!(error.to_string(), "line 2: expected [addr label], found [300]");
+    let error = Symbols::parse("2G0 main\n").unwrap_err();
+    assert_eq!(error.to_string(), "line 1: invalid address [2G0]");
+    let error = Symbols::parse("200 main\n300 main\n").unwrap_err();
+    assert_eq!(error.to_string(), "line 2: label [main] is defined twice");
+}
+
+#[test]
+fn describes_and_resolves_addresses() {
+    let symbols = symbols();
+    assert_eq!(symbols.describe(0x20A), Some("draw_player".to_string()));
+    assert_eq!(symbols.describe(0x20E), Some("draw_player+4".to_string()));
+    assert_eq!(symbols.describe(0x100), None);
+    assert_eq!(symbols.format(0x100), "100");
+
+    assert_eq!(symbols.resolve("draw_player"), Some(0x20A));
+    assert_eq!(symbols.resolve("draw_player+4"), Some(0x20E));
+    assert_eq!(symbols.resolve("sprite + 0x10"), Some(0x2B0));
+    assert_eq!(symbols.resolve("0x300"), Some(0x300));
+    assert_eq!(symbols.resolve("nothing"), None);
+
+    assert_eq!(symbols.mnemonic(&Instruction::Call(0x20A)), "CALL draw_player");
+    assert_eq!(symbols.mnemonic(&Instruction::LoadLongI(0x2A0)), "LD I, LONG sprite");
+    assert_eq!(symbols.mnemonic(&Instruction::Jump(0x20C)), "JP 0x20C");
+}
+
+#[test]
+fn loads_octo_sources_next_to_the_rom() {
+    let dir = std::env::temp_dir().join(format!("chip8_symbols_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let rom_path = dir.join("game.ch8");
+    fs::write(&rom_path, [0x12, 0x00]).unwrap();
+    let rom_path = rom_path.to_str().unwrap();
+
+    assert_eq!(Symbols::find_next_to(rom_path), None);
+
+    let source_path = dir.join("game.8o");
+    fs::write(&source_path, ": main\n  v0 := 1\n: forever\n  jump forever\n").unwrap();
+    let found = Symbols::find_next_to(rom_path).unwrap();
+    assert_eq!(found, source_path.to_str().unwrap());
+
+    let symbols = Symbols::load(&found).unwrap();
+    assert_eq!(symbols.label(0x202), Some("forever"));
+
+    // a .sym file comes first
+    fs::write(dir.join("game.sym"), "200 start\n").unwrap();
+    let symbols = Symbols::load(&Symbols::find_next_to(rom_path).unwrap()).unwrap();
+    assert_eq!(symbols.label(0x200), Some("start"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn breakpoints_and_expressions_use_labels() {
+    let symbols = symbols();
+    let (addr, condition) = parse_breakpoint("draw_player+2 if I == sprite", &symbols).unwrap();
+    assert_eq!(addr, 0x20C);
+
+    let mut emu = Emu::new();
+    emu.i_register = 0x2A0;
+    assert!(condition.unwrap().is_true(&emu).unwrap());
+
+    let value = Expression::parse_with("ram[sprite + 1]", &symbols).unwrap();
+    emu.ram[0x2A1] = 7;
+    assert_eq!(value.evaluate(&emu), Ok(ExprValue::Number(7)));
+
+    // registers win over labels
+    let mut symbols = symbols;
+    symbols.insert(0x400, "pc");
+    assert_eq!(Expression::parse_with("pc", &symbols).unwrap().evaluate(&emu), Ok(ExprValue::Number(0x200)));
+}
+
+#[test]
+fn expressions_use_octo_labels_with_dashes() {
+    let symbols = Symbols::parse("20A draw-player
+2A0 player
+2B0 player-sprite-data
+").unwrap();
+    let mut emu = Emu::new();
+    emu.registers[1] = 3;
+    let eval = |text: &str| Expression::parse_with(text, &symbols).unwrap().evaluate(&emu);
+
+    assert_eq!(eval("draw-player"), Ok(ExprValue::Number(0x20A)));
+    assert_eq!(eval("draw-player-1"), Ok(ExprValue::Number(0x209)));
+    assert_eq!(eval("player-sprite-data + 1"), Ok(ExprValue::Number(0x2B1)));
+    // without such a label - is a minus
+    let error = Expression::parse_with("player-sprite", &symbols).unwrap_err();
+    assert_eq!(error.column, 8);
+    assert_eq!(eval("V1-1"), Ok(ExprValue::Number(2)));
+    assert_eq!(eval("player-v1"), Ok(ExprValue::Number(0x29D)));
+}
+
+#[test]
+fn backtrace_names_the_calls() {
+    // 200: call 20A
+    // 202: jump 202
+    // ...
+    // 20A: call 2A0
+    let mut emu = Emu::new();
+    let mut rom = vec![0; 0xA2];
+    rom[0..4].copy_from_slice(&[0x22, 0x0A, 0x12, 0x02]);
+    rom[0x0A..0x0C].copy_from_slice(&[0x22, 0xA0]);
+    rom[0xA0..0xA2].copy_from_slice(&[0x12, 0xA0]);
+    emu.load(&rom).unwrap();
+    emu.debugger.symbols = symbols();
+
+    emu.step_into();
+    emu.step_into();
+    assert_eq!(emu.backtrace(), ["draw_player", "main"]);
+}
+
+#[test]
+fn traces_and_disassembly_show_labels() {
+    let rom = [0x60, 0x01, 0x12, 0x02];
+    let mut emu = Emu::new();
+    emu.load(&rom).unwrap();
+    emu.debugger.symbols = Symbols::parse("200 main\n202 forever\n").unwrap();
+    let buffer = Buffer::default();
+    emu.tracer = Some(Tracer::new(buffer.clone()));
+    emu.run_frame(2).unwrap();
+
+    let lines = buffer.lines();
+    assert!(lines[1].ends_with("st=00 sym=main ; LD V0, 0x01"));
+    assert!(lines[2].ends_with("st=00 sym=forever ; JP forever"));
+
+    let mut disassembly = disasm::disassemble(&rom);
+    disassembly.apply_symbols(&emu.debugger.symbols);
+    let source = disassembly.to_octo();
+    assert!(source.contains(": forever\n\tjump forever"));
+    assert_eq!(asm::assemble(&source).unwrap().bytes, rom);
+}
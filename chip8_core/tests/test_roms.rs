@@ -0,0 +1,60 @@
+// Runs ROMs from roms/ for a fixed number of frames and compares a
+// hash of the framebuffer with the one of a known good run. When a
+// change to the emulator is intended, print the new hashes with
+// `cargo test --test test_roms -- --nocapture` and update the table.
+
+use chip8_core::*;
+
+// ROM, quirk preset, frames, cycles per frame, framebuffer hash
+const GOLDEN: &[(&str, &str, u32, u32, u64)] = &[
+    ("test_opcode.ch8", "default", 60, 10, 0x8F21671912C12851),
+    ("test_opcode.ch8", "cosmac-vip", 60, 10, 0x8F21671912C12851),
+    ("Sierpinski [Sergey Naydenov, 2010].ch8", "default", 300, 10, 0xBCCA218C7BE17022),
+    ("Trip8 Demo (2008) [Revival Studios].ch8", "default", 300, 10, 0x62DB4FC335D4793A),
+    ("Zero Demo [zeroZshadow, 2007].ch8", "default", 300, 10, 0xA23F6124A7AA595D),
+    ("Particle Demo [zeroZshadow, 2008].ch8", "default", 300, 10, 0x503FAB86C0F3C4F1),
+    ("Maze [David Winter, 199x].ch8", "default", 300, 10, 0x24A55566BEEDA325),
+    ("Brix [Andreas Gustafsson, 1990].ch8", "default", 300, 10, 0x30E6EFE81EDD6D10),
+    ("Space Invaders [David Winter].ch8", "default", 300, 10, 0xBF5F144E5444B9BD),
+];
+
+// FNV-1a
+fn framebuffer_hash(emulator: &Emu) -> u64 {
+    let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+    for pixel in emulator.get_display() {
+        hash ^= *pixel as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+    }
+    hash
+}
+
+fn run_rom(name: &str, preset: &str, frames: u32, cycles_per_frame: u32) -> Emu {
+    let path = format!("{}/../roms/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let rom = std::fs::read(&path).unwrap_or_else(|error| panic!("unable to read {}: {}", path, error));
+
+    let mut emulator = Emu::new();
+    emulator.quirks = Quirks::from_name(preset).unwrap();
+    emulator.set_seed(0);
+    emulator.load(&rom).unwrap();
+    for _ in 0..frames {
+        emulator.run_frame(cycles_per_frame).unwrap();
+    }
+    emulator
+}
+
+#[test]
+fn framebuffers_match_golden_hashes() {
+    let mut failed = Vec::new();
+
+    for (name, preset, frames, cycles_per_frame, expected) in GOLDEN {
+        let emulator = run_rom(name, preset, *frames, *cycles_per_frame);
+        let found = framebuffer_hash(&emulator);
+        println!("(\"{}\", \"{}\", {}, {}, 0x{:016X}),", name, preset, frames, cycles_per_frame, found);
+
+        if found != *expected {
+            failed.push(format!("{} ({}): {:016X}, expected {:016X}\n{}", name, preset, found, expected, screenshot::to_ascii(&emulator)));
+        }
+    }
+
+    assert!(failed.is_empty(), "framebuffers differ:\n{}", failed.join("\n"));
+}
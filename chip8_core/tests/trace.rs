@@ -0,0 +1,160 @@
+use chip8_core::*;
+use chip8_core::tracediff::{self, DiffOptions, DiffResult, Difference, TraceReader};
+
+mod common;
+use common::{emu_with, Buffer};
+
+// 200: v0 := 1
+// 202: i := 300
+// 204: F000 0ABC
+// 206: (address)
+// 208: jump 208
+const PROGRAM: [u16; 5] = [0x6001, 0xA300, 0xF000, 0x0ABC, 0x1208];
+
+#[test]
+fn traces_every_instruction() {
+    let mut emu = emu_with(&PROGRAM);
+    let buffer = Buffer::default();
+    emu.tracer = Some(Tracer::new(buffer.clone()));
+
+    emu.run_frame(4).unwrap();
+    emu.tracer.take().unwrap().finish().unwrap();
+
+    let lines = buffer.lines();
+    assert_eq!(lines, [
+        TRACE_HEADER.to_string(),
+        "cycle=0 frame=0 pc=0200 op=6001 v=00000000000000000000000000000000 i=0000 sp=0 dt=00 st=00 ; LD V0, 0x01".to_string(),
+        "cycle=1 frame=0 pc=0202 op=A300 v=01000000000000000000000000000000 i=0000 sp=0 dt=00 st=00 ; LD I, 0x300".to_string(),
+        "cycle=2 frame=0 pc=0204 op=F000 v=01000000000000000000000000000000 i=0300 sp=0 dt=00 st=00 ; LD I, LONG 0x0ABC".to_string(),
+        "cycle=3 frame=0 pc=0208 op=1208 v=01000000000000000000000000000000 i=0ABC sp=0 dt=00 st=00 ; JP 0x208".to_string(),
+    ]);
+}
+
+#[test]
+fn filters_by_address_and_frame() {
+    let mut emu = emu_with(&PROGRAM);
+    let buffer = Buffer::default();
+    let mut tracer = Tracer::new(buffer.clone());
+    tracer.addresses = parse_address_range("208");
+    tracer.frames = parse_frame_range("2-3");
+    emu.tracer = Some(tracer);
+
+    for _ in 0..6 {
+        emu.run_frame(4).unwrap();
+    }
+
+    // the loop at 208 runs 4 times per frame, frames 2 and 3 are traced
+    let lines = buffer.lines();
+    assert_eq!(lines.len(), 1 + 8);
+    assert!(lines[1].starts_with("cycle=8 frame=2 pc=0208"));
+    assert!(lines[8].starts_with("cycle=15 frame=3 pc=0208"));
+}
+
+#[test]
+fn parses_ranges() {
+    assert_eq!(parse_address_range("200-2FF"), Some(0x200..=0x2FF));
+    assert_eq!(parse_address_range("0x300"), Some(0x300..=0x300));
+    assert_eq!(parse_address_range("300-200"), None);
+    assert_eq!(parse_frame_range("10-20"), Some(10..=20));
+    assert_eq!(parse_frame_range("x"), None);
+}
+
+fn diff_texts(ours: &str, reference: &str, options: DiffOptions) -> DiffResult {
+    tracediff::diff(TraceReader::new(ours.as_bytes()), TraceReader::new(reference.as_bytes()), options).unwrap()
+}
+
+#[test]
+fn memory_writes_follow_their_instruction() {
+    // 200: v0 := 5, 202: i := 300, 204: save v0
+    let mut emu = emu_with(&[0x6005, 0xA300, 0xF055]);
+    let buffer = Buffer::default();
+    emu.tracer = Some(Tracer::new(buffer.clone()));
+    emu.run_frame(3).unwrap();
+
+    let lines = buffer.lines();
+    assert!(lines[3].contains("pc=0204"));
+    assert_eq!(lines[4], "write addr=0300 value=05");
+
+    let text = lines.join("\n");
+    let entries: Vec<_> = TraceReader::new(text.as_bytes()).map(Result::unwrap).collect();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[2].writes, [(0x300, 0x05)]);
+    assert_eq!(entries[2].registers.unwrap()[0], 5);
+}
+
+const OURS: &str = "# chip8-trace 1
+cycle=0 frame=0 pc=0200 op=8014 v=00000000000000000000000000000000 i=0000 sp=0 dt=05 st=00 ; ADD V0, V1
+cycle=1 frame=0 pc=0202 op=F055 v=00000000000000000000000000000001 i=0300 sp=0 dt=05 st=00 ; LD [I], V0
+write addr=0300 value=00
+";
+
+#[test]
+fn finds_the_first_divergence() {
+    let reference = OURS.replace("000001 i=0300", "000000 i=0300").replace("dt=05", "dt=04");
+
+    let DiffResult::Diverged(divergence) = diff_texts(OURS, &reference, DiffOptions::default()) else {
+        panic!("traces should diverge");
+    };
+    assert_eq!(divergence.index, 0);
+    assert_eq!(divergence.differences, [Difference::Register { name: "DT".to_string(), ours: 5, reference: 4 }]);
+
+    // VF is set by the previous instruction
+    let options = DiffOptions { ignore_timers: true, ..DiffOptions::default() };
+    let DiffResult::Diverged(divergence) = diff_texts(OURS, &reference, options) else {
+        panic!("traces should diverge");
+    };
+    assert_eq!(divergence.index, 1);
+    assert!(divergence.previous.unwrap().0.text.contains("ADD V0, V1"));
+    assert_eq!(divergence.differences, [Difference::Register { name: "VF".to_string(), ours: 1, reference: 0 }]);
+
+    let options = DiffOptions { ignore_timers: true, ignore_vf: true, ..DiffOptions::default() };
+    assert!(matches!(diff_texts(OURS, &reference, options), DiffResult::Identical { instructions: 2 }));
+}
+
+#[test]
+fn compares_memory_and_length() {
+    let reference = OURS.replace("write addr=0300 value=00", "write addr=0301 value=00");
+    let DiffResult::Diverged(divergence) = diff_texts(OURS, &reference, DiffOptions::default()) else {
+        panic!("traces should diverge");
+    };
+    assert_eq!(divergence.differences, [
+        Difference::Memory { addr: 0x300, ours: Some(0), reference: None },
+        Difference::Memory { addr: 0x301, ours: None, reference: Some(0) },
+    ]);
+
+    let options = DiffOptions { ignore_memory: true, ..DiffOptions::default() };
+    assert!(matches!(diff_texts(OURS, &reference, options), DiffResult::Identical { .. }));
+
+    let shorter: String = OURS.lines().take(2).map(|line| format!("{}\n", line)).collect();
+    assert!(matches!(diff_texts(OURS, &shorter, DiffOptions::default()), DiffResult::Length { instructions: 1, ours_ended: false }));
+}
+
+#[test]
+fn foreign_traces_only_need_some_fields() {
+    // without write lines memory can't be compared
+    let reference = "pc=0200 op=8014\npc=0202 op=F055 ; whatever\n";
+    let options = DiffOptions { ignore_memory: true, ..DiffOptions::default() };
+    assert!(matches!(diff_texts(OURS, reference, options), DiffResult::Identical { instructions: 2 }));
+
+    let broken = "pc=XYZ\n";
+    let error = TraceReader::new(broken.as_bytes()).next().unwrap().unwrap_err();
+    assert_eq!(error.to_string(), "line 1: invalid value [pc=XYZ]");
+
+    // 32 bytes, but not 32 hex digits
+    let broken = "v=€00000000000000000000000000000\n";
+    let error = TraceReader::new(broken.as_bytes()).next().unwrap().unwrap_err();
+    assert_eq!(error.to_string(), "line 1: invalid value [v=€00000000000000000000000000000]");
+}
+
+#[test]
+fn reads_both_trace_versions() {
+    // version 2 adds sym and labels in the mnemonic
+    let ours = OURS.replace("# chip8-trace 1", TRACE_HEADER)
+        .replace("st=00 ; ADD", "st=00 sym=main ; ADD")
+        .replace("st=00 ; LD", "st=00 sym=main+2 ; LD");
+    assert!(matches!(diff_texts(&ours, OURS, DiffOptions::default()), DiffResult::Identical { instructions: 2 }));
+
+    let newer = OURS.replace("# chip8-trace 1", "# chip8-trace 3");
+    let error = TraceReader::new(newer.as_bytes()).next().unwrap().unwrap_err();
+    assert_eq!(error.to_string(), "line 1: unsupported trace version [3]");
+}
@@ -1,17 +1,23 @@
 use chip8_core::*;
 
-use std::env;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Read;
 use std::process;
 
-use crate::savestates;
 use crate::config;
+use crate::args::{self, Args};
 
 use crate::graphics::GraphicsManager;
 use crate::input::InputManager;
 use crate::audio::AudioManager;
-use crate::savestates::SaveState;
+use crate::savestates::SaveSlots;
+use crate::movies::MovieSession;
+use crate::memview::MemoryViewer;
+
+// frames to catch up on at most, after the window was stalled
+const MAX_FRAMES_PER_UPDATE: u32 = 5;
+// step over / step out give up after this many instructions
+const MAX_STEP_CYCLES: u64 = 1_000_000;
 
 const WELCOME: &str = r#"
         The Rust
@@ -27,14 +33,21 @@ pub struct AppManager {
 
     emulator: Emu,
     clock_timer: f32,
+    // set when the ROM crashed the emulator, stops execution until reset
+    fault: Option<EmuError>,
+    // why the debugger paused the emulator, shown in the debugger panel
+    debug_stop: Option<StopReason>,
+    rewind_buffer: RewindBuffer,
+    movie: Option<MovieSession>,
+    memory_viewer: MemoryViewer,
 
-    emu_savestate: savestates::SaveState,
+    save_slots: SaveSlots,
     config: config::Config,
     audio_manager: AudioManager,
     input_manager: InputManager,
     graphics_manager: GraphicsManager,
 
-    args: Vec<String>,
+    args: Args,
 }
 
 impl AppManager {
@@ -43,17 +56,18 @@ impl AppManager {
         // print welcome message
         println!("\x1b[1;31m {} \x1b[0m", WELCOME);
         
-        let arguments: Vec<_> = env::args().collect();
-            if arguments.len() != 2 {
-                println!("ERROR: invalid args!");
-                process::exit(0);
-            }
+        let arguments = args::parse_args();
         
         let mut instance = AppManager {
             
             clock_timer: 0.,
             emulator: Emu::new(),
-            emu_savestate: SaveState::default(),    
+            fault: None,
+            debug_stop: None,
+            rewind_buffer: RewindBuffer::new(1),
+            movie: None,
+            memory_viewer: MemoryViewer::new(),
+            save_slots: SaveSlots::new(),
             config: config::read_config(),
             input_manager: InputManager::new(),
             audio_manager: AudioManager::new(),
@@ -62,6 +76,18 @@ impl AppManager {
             args: arguments,
         };
 
+        instance.apply_quirks();
+
+        if let Some(seed) = instance.args.seed {
+            instance.emulator.set_seed(seed);
+        }
+        println!("INFO: RNG seed is {} (rerun with --seed to reproduce)", instance.emulator.seed());
+
+        instance.rewind_buffer = RewindBuffer::with_seconds(instance.config.rewind_seconds);
+        instance.start_trace();
+        instance.start_profile();
+        instance.apply_debugger_args();
+
         instance.audio_manager.load_values_from_config(&instance.config);
         instance.audio_manager.play_async_beep();
 
@@ -75,41 +101,254 @@ impl AppManager {
         instance
     }
 
+    // the command line overrides the preset from the config file
+    fn apply_quirks(&mut self) {
+        let preset = self.args.quirks.clone().unwrap_or(self.config.quirks.clone());
+
+        match Quirks::from_name(&preset) {
+            Some(quirks) => {
+                self.emulator.quirks = quirks;
+                println!("INFO: Using quirk preset [{}]", preset);
+            },
+            None => {
+                println!("ERROR: Unknown quirk preset [{}]! Valid presets: {}", preset, QUIRK_PRESETS.join(", "));
+                process::exit(0);
+            }
+        }
+    }
+
+    // --record and --play, needs the ROM to be loaded
+    pub fn start_movie(&mut self) {
+        if let Some(path) = self.args.record.clone() {
+            self.movie = Some(MovieSession::record(path, &self.emulator, self.config.cycles_per_frame));
+        }
+        if let Some(path) = self.args.play.clone() {
+            self.movie = Some(MovieSession::play(path, &mut self.emulator));
+        }
+    }
+
+    // --symbols, --break and --watch, the debugger survives resets
+    fn apply_debugger_args(&mut self) {
+        self.load_symbols();
+        let debugger = &mut self.emulator.debugger;
+
+        for spec in &self.args.breakpoints {
+            match parse_breakpoint(spec, &debugger.symbols) {
+                Ok((addr, condition)) => {
+                    match &condition {
+                        Some(condition) => println!("INFO: Breakpoint set at {} if {}", debugger.symbols.format(addr), condition),
+                        None => println!("INFO: Breakpoint set at {}", debugger.symbols.format(addr)),
+                    }
+                    debugger.breakpoints.insert(addr);
+                    debugger.set_condition(addr, condition);
+                },
+                Err(error) => {
+                    println!("ERROR: Invalid breakpoint [{}], {}!", spec, error);
+                    process::exit(0);
+                }
+            }
+        }
+
+        for text in &self.args.watches {
+            match Expression::parse_with(text, &debugger.symbols) {
+                Ok(expression) => debugger.watches.push(expression),
+                Err(error) => {
+                    println!("ERROR: Invalid watch expression [{}], {}!", text, error);
+                    process::exit(0);
+                }
+            }
+        }
+    }
+
+    // --symbols, or a symbol file next to the ROM
+    fn load_symbols(&mut self) {
+        let (path, explicit) = match (self.args.symbols.clone(), Symbols::find_next_to(&self.args.rom_path)) {
+            (Some(path), _) => (path, true),
+            (None, Some(path)) => (path, false),
+            (None, None) => return,
+        };
+
+        match Symbols::load(&path) {
+            Ok(symbols) => {
+                println!("INFO: Loaded {} symbols from [{}]", symbols.len(), path);
+                self.emulator.debugger.symbols = symbols;
+            },
+            Err(error) if explicit => {
+                println!("ERROR: Symbols [{}] could not be loaded: {}", path, error);
+                process::exit(0);
+            },
+            Err(error) => println!("INFO: Ignoring symbols [{}]: {}", path, error),
+        }
+    }
+
+    // --trace, keeps running across resets
+    fn start_trace(&mut self) {
+        let Some(path) = self.args.trace.clone() else {
+            return;
+        };
+        match Tracer::to_file(&path) {
+            Ok(mut tracer) => {
+                tracer.addresses = self.args.trace_range.clone();
+                tracer.frames = self.args.trace_frames.clone();
+                self.emulator.tracer = Some(tracer);
+                println!("INFO: Tracing instructions to [{}]", path);
+            },
+            Err(error) => {
+                println!("ERROR: Trace [{}] could not be created: {}", path, error);
+                process::exit(0);
+            }
+        }
+    }
+
+    fn stop_trace(&mut self) {
+        if let Some(tracer) = self.emulator.tracer.take() {
+            if let Err(error) = tracer.finish() {
+                println!("ERROR: Trace could not be written: {}", error);
+            }
+        }
+    }
+
+    // --profile and --profile-stacks, written on exit
+    fn start_profile(&mut self) {
+        if self.args.profile.is_some() || self.args.profile_stacks.is_some() {
+            self.emulator.profiler = Some(Profiler::new());
+            println!("INFO: Profiling instructions");
+        }
+    }
+
+    fn stop_profile(&mut self) {
+        let Some(profiler) = self.emulator.profiler.take() else {
+            return;
+        };
+        let outputs = [
+            (&self.args.profile, profiler.report(&self.emulator)),
+            (&self.args.profile_stacks, profiler.collapsed_stacks(&self.emulator.debugger.symbols)),
+        ];
+        for (path, text) in outputs {
+            if let Some(path) = path {
+                match fs::write(path, text) {
+                    Ok(()) => println!("INFO: Profile written to [{}]", path),
+                    Err(error) => println!("ERROR: Profile [{}] could not be written: {}", path, error),
+                }
+            }
+        }
+    }
+
+    fn stop_movie(&mut self) {
+        if let Some(movie) = self.movie.take() {
+            movie.finish();
+        }
+    }
+
+    fn is_playing_movie(&self) -> bool {
+        self.movie.as_ref().is_some_and(|movie| movie.is_playing())
+    }
+
     pub fn main_loop(&mut self) {
         while !self.graphics_manager.rl.window_should_close() {
 
-            let mut visuals_modified = self.emulator.tick();
-            
-            if self.update_clocks() {
-                // BEEP
-                self.audio_manager.play_async_beep();
+            // keys typed into the memory viewer go nowhere else
+            let editable = self.movie.is_none();
+            let capturing = self.memory_viewer.handle_input(&mut self.emulator, &mut self.graphics_manager.rl, editable);
+
+            // during movie playback the keypad comes from the movie
+            if !self.is_playing_movie() && !capturing {
+                self.input_manager.handle_game_input(&mut self.emulator, &self.graphics_manager.rl);
             }
 
+            let mut visuals_modified = self.run_frames();
         
-            self.input_manager.handle_game_input(&mut self.emulator, &self.graphics_manager.rl);
-            let actions = self.input_manager.handle_emu_input(&self.graphics_manager.rl);
-            visuals_modified |= self.handle_action(actions);
+            if !capturing {
+                let actions = self.input_manager.handle_emu_input(&self.graphics_manager.rl);
+                visuals_modified |= self.handle_action(actions);
+            }
     
-            self.graphics_manager.render_game(&self.args, &self.config, &self.emulator, visuals_modified);
+            self.graphics_manager.render_game(&self.args.rom_path, &self.config, &self.emulator, self.fault.as_ref(), self.debug_stop.as_ref(), &self.memory_viewer, visuals_modified);
         }
+
+        self.stop_movie();
+        self.stop_trace();
+        self.stop_profile();
     }
 
-        
-    fn update_clocks(&mut self) -> bool{
+    // runs as many 60 Hz emulator frames as fit into the time since the
+    // last call, independent of the render fps. Leftover time is kept.
+    // While REWIND is held every frame steps back one snapshot instead.
+    fn run_frames(&mut self) -> bool {
+        // rewinding would break a movie
+        let rewinding = self.movie.is_none()
+            && self.input_manager.is_action_down("REWIND", &self.graphics_manager.rl);
+
         self.clock_timer += self.graphics_manager.rl.get_frame_time();
-        
-        let mut beep = false;
 
-        if self.clock_timer >= 1. / (self.config.tps as f32) {
-            beep = self.emulator.tick_timers();
-            self.clock_timer = 0.;
+        let frame_duration = 1. / FRAMES_PER_SECOND as f32;
+        let mut visuals_modified = false;
+        let mut frames = 0;
+
+        while self.clock_timer >= frame_duration {
+            self.clock_timer -= frame_duration;
+
+            frames += 1;
+            if frames > MAX_FRAMES_PER_UPDATE {
+                self.clock_timer = 0.;
+                break;
+            }
+
+            if rewinding {
+                self.audio_manager.stop_buzzer();
+                if self.rewind_buffer.step_back(&mut self.emulator) {
+                    self.fault = None;
+                    visuals_modified = true;
+                }
+                continue;
+            }
+
+            if self.fault.is_some() {
+                self.audio_manager.stop_buzzer();
+                continue;
+            }
+
+            // paused frames are not part of a movie
+            let running = !self.emulator.is_paused;
+            let mut cycles_per_frame = self.config.cycles_per_frame;
+
+            if let Some(movie) = self.movie.as_mut() {
+                cycles_per_frame = movie.cycles_per_frame();
+                if running && !movie.before_frame(&mut self.emulator) {
+                    // playback is over, the keyboard takes over
+                    self.movie = None;
+                }
+            }
+
+            match self.emulator.run_frame(cycles_per_frame) {
+                Ok(frame) => {
+                    visuals_modified |= frame.visuals_modified;
+                    self.audio_manager.update_buzzer(frame.buzzer_active, &self.emulator);
+                    if let Some(stop) = frame.stop {
+                        println!("INFO: Debugger stopped: {}", stop);
+                        self.emulator.is_paused = true;
+                        self.debug_stop = Some(stop);
+                    }
+                    if running {
+                        self.rewind_buffer.push(&self.emulator);
+                        if let Some(movie) = self.movie.as_mut() {
+                            movie.after_frame(&self.emulator);
+                        }
+                    }
+                },
+                Err(error) => {
+                    println!("ERROR: Emulator fault: {}", error);
+                    self.fault = Some(error);
+                    self.audio_manager.stop_buzzer();
+                }
+            }
         }
 
-        beep
+        visuals_modified
     }
 
     pub fn load_rom(&mut self, path: Option<String>) {
-        let mut load_path = self.args[1].clone();
+        let mut load_path = self.args.rom_path.clone();
         if path.is_some() {
             load_path = path.expect("WTF: You really shouldnt see this");
         }
@@ -120,10 +359,41 @@ impl AppManager {
         rom.read_to_end(&mut buffer).expect("ERROR: File could not be read");
         // println!("buffer: {:?}", buffer);
 
-        self.emulator.load(&buffer);
-        println!("INFO: Loaded ROM successfully");
+        self.fault = None;
+        self.rewind_buffer.clear();
+        match self.emulator.load(&buffer) {
+            Ok(()) => println!("INFO: Loaded ROM successfully"),
+            Err(error) => {
+                println!("ERROR: ROM could not be loaded: {}", error);
+                self.fault = Some(error);
+            }
+        }
+    }
+
+    // single steps pause the emulator and run outside of run_frames
+    fn debug_step(&mut self, action: &str) {
+        if self.movie.is_some() {
+            println!("ERROR: The debugger can't step while a movie is recorded or played");
+            return;
+        }
+        if self.fault.is_some() {
+            return;
+        }
+
+        self.emulator.is_paused = true;
+        self.emulator.debugger.cycles_per_frame = self.config.cycles_per_frame;
+
+        let stop = match action {
+            "STEP_OVER" => self.emulator.step_over(MAX_STEP_CYCLES),
+            "STEP_OUT" => self.emulator.step_out(MAX_STEP_CYCLES),
+            _ => self.emulator.step_into(),
+        };
 
-        self.emu_savestate = savestates::make_save(&self.emulator);
+        if let StopReason::Fault(error) = stop {
+            println!("ERROR: Emulator fault: {}", error);
+            self.fault = Some(error);
+        }
+        self.debug_stop = Some(stop);
     }
 
     fn handle_action(&mut self, actions: Vec<String>) ->bool {
@@ -133,7 +403,11 @@ impl AppManager {
                 "RESET" => {
                     println!("ACTION: RESET EMULATOR");
                     self.emulator.reset();
+                    self.debug_stop = None;
                     self.load_rom(None);
+                    if let Some(movie) = self.movie.as_mut() {
+                        movie.restart(&self.emulator);
+                    }
                 },
                 "NEXT_THEME" => {
                     println!("ACTION: Switched theme");
@@ -147,18 +421,53 @@ impl AppManager {
                 
                 "EXIT" => {
                     println!("ACTION: Exiting game");
+                    self.stop_movie();
+                    self.stop_trace();
+                    self.stop_profile();
                     process::exit(0);
                 }
                 "PAUSE" => {
                     self.emulator.is_paused ^= true;
+                    self.debug_stop = None;
                 },
-                "LOAD" => {
-                    savestates::load_save(&self.emu_savestate, &mut self.emulator);
+                "STEP" | "STEP_OVER" | "STEP_OUT" => {
+                    self.debug_step(&action);
                     visuals_modified = true;
                 },
+                "CONTINUE" => {
+                    self.emulator.is_paused = false;
+                    self.debug_stop = None;
+                },
+                "BREAKPOINT" => {
+                    let pc = self.emulator.programm_counter;
+                    let location = self.emulator.debugger.symbols.format(pc);
+                    if self.emulator.debugger.toggle_breakpoint(pc) {
+                        println!("INFO: Breakpoint set at {}", location);
+                    }
+                    else {
+                        println!("INFO: Breakpoint at {} removed", location);
+                    }
+                },
+                "MEMORY" => {
+                    self.memory_viewer.toggle(&mut self.emulator);
+                },
+                "LOAD" if self.movie.is_some() => {
+                    println!("ERROR: Savestates can't be loaded while a movie is recorded or played");
+                },
+                "LOAD" => {
+                    if self.save_slots.load(&self.args.rom_path, &mut self.emulator) {
+                        self.fault = None;
+                        visuals_modified = true;
+                    }
+                },
                 "SAVE" => {
-                    self.emu_savestate = savestates::make_save(&self.emulator);
+                    self.save_slots.save(&self.args.rom_path, &self.emulator);
+                },
+                "NEXT_SLOT" => {
+                    self.save_slots.next_slot();
                 },
+                // held key, handled in run_frames
+                "REWIND" => {},
                 _ => {
                     print!("ERROR: Unimplemented ACTION");
                 }
@@ -0,0 +1,155 @@
+use std::env;
+use std::ops::RangeInclusive;
+use std::process;
+
+use chip8_core::{parse_address_range, parse_frame_range};
+
+const USAGE: &str = "usage: desktop <rom_path> [--quirks <preset>] [--seed <number>] [--record <movie> | --play <movie>]
+               [--trace <path>] [--trace-range <addr>[-<addr>]] [--trace-frames <frame>[-<frame>]]
+               [--symbols <path>] [--break \"<addr> [if <condition>]\"]... [--watch <expression>]...
+               [--profile <path>] [--profile-stacks <path>]";
+
+pub struct Args {
+    pub rom_path: String,
+    pub quirks: Option<String>,
+    pub seed: Option<u64>,
+    pub record: Option<String>,
+    pub play: Option<String>,
+    pub trace: Option<String>,
+    pub trace_range: Option<RangeInclusive<u16>>,
+    pub trace_frames: Option<RangeInclusive<u64>>,
+    pub symbols: Option<String>,
+    // parsed once the symbols are loaded
+    pub breakpoints: Vec<String>,
+    pub watches: Vec<String>,
+    pub profile: Option<String>,
+    pub profile_stacks: Option<String>,
+}
+
+pub fn parse_args() -> Args {
+    let arguments: Vec<String> = env::args().skip(1).collect();
+
+    let mut rom_path = None;
+    let mut quirks = None;
+    let mut seed = None;
+    let mut record = None;
+    let mut play = None;
+    let mut trace = None;
+    let mut trace_range = None;
+    let mut trace_frames = None;
+    let mut symbols = None;
+    let mut breakpoints = Vec::new();
+    let mut watches = Vec::new();
+    let mut profile = None;
+    let mut profile_stacks = None;
+
+    let mut iter = arguments.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--quirks" => {
+                quirks = Some(expect_value(iter.next(), "--quirks"));
+            },
+            "--seed" => {
+                let value = expect_value(iter.next(), "--seed");
+                match value.parse::<u64>() {
+                    Ok(number) => seed = Some(number),
+                    Err(_) => {
+                        println!("ERROR: Invalid seed [{}]!\n{}", value, USAGE);
+                        process::exit(0);
+                    }
+                }
+            },
+            "--record" => {
+                record = Some(expect_value(iter.next(), "--record"));
+            },
+            "--play" => {
+                play = Some(expect_value(iter.next(), "--play"));
+            },
+            "--trace" => {
+                trace = Some(expect_value(iter.next(), "--trace"));
+            },
+            "--trace-range" => {
+                let value = expect_value(iter.next(), "--trace-range");
+                match parse_address_range(&value) {
+                    Some(range) => trace_range = Some(range),
+                    None => {
+                        println!("ERROR: Invalid address range [{}]!\n{}", value, USAGE);
+                        process::exit(0);
+                    }
+                }
+            },
+            "--trace-frames" => {
+                let value = expect_value(iter.next(), "--trace-frames");
+                match parse_frame_range(&value) {
+                    Some(range) => trace_frames = Some(range),
+                    None => {
+                        println!("ERROR: Invalid frame range [{}]!\n{}", value, USAGE);
+                        process::exit(0);
+                    }
+                }
+            },
+            "--symbols" => {
+                symbols = Some(expect_value(iter.next(), "--symbols"));
+            },
+            "--break" => {
+                breakpoints.push(expect_value(iter.next(), "--break"));
+            },
+            "--watch" => {
+                watches.push(expect_value(iter.next(), "--watch"));
+            },
+            "--profile" => {
+                profile = Some(expect_value(iter.next(), "--profile"));
+            },
+            "--profile-stacks" => {
+                profile_stacks = Some(expect_value(iter.next(), "--profile-stacks"));
+            },
+            _ if arg.starts_with("--") => {
+                println!("ERROR: Unknown option [{}]!\n{}", arg, USAGE);
+                process::exit(0);
+            },
+            _ => {
+                if rom_path.is_some() {
+                    println!("ERROR: invalid args!\n{}", USAGE);
+                    process::exit(0);
+                }
+                rom_path = Some(arg);
+            }
+        }
+    }
+
+    let Some(rom_path) = rom_path else {
+        println!("ERROR: invalid args!\n{}", USAGE);
+        process::exit(0);
+    };
+
+    if record.is_some() && play.is_some() {
+        println!("ERROR: --record and --play can't be used together!\n{}", USAGE);
+        process::exit(0);
+    }
+
+    Args {
+        rom_path,
+        quirks,
+        seed,
+        record,
+        play,
+        trace,
+        trace_range,
+        trace_frames,
+        symbols,
+        breakpoints,
+        watches,
+        profile,
+        profile_stacks,
+    }
+}
+
+fn expect_value(value: Option<String>, option: &str) -> String {
+    match value {
+        Some(value) => value,
+        None => {
+            println!("ERROR: Missing value for [{}]!\n{}", option, USAGE);
+            process::exit(0);
+        }
+    }
+}
@@ -1,14 +1,103 @@
-use rodio::{OutputStream, Sink};
-use std::{f32::consts::PI, sync::{Arc, Mutex}};
+use chip8_core::{Emu, AUDIO_PATTERN_SIZE};
+use rodio::{OutputStream, Sink, Source};
+use std::{f32::consts::PI, sync::{Arc, Mutex}, time::Duration};
 
 use crate::config::Config;
 
+const SAMPLE_RATE: u32 = 44100;
+// length of the fade in and out of the buzzer, avoids clicks
+const FADE_SECONDS: f32 = 0.005;
+// how often the buzzer source looks at the shared state
+const STATE_POLL_SAMPLES: u32 = 64;
+
+// shared between the emulator thread and the audio thread
+#[derive(Clone, Copy)]
+struct BuzzerState {
+    active: bool,
+    frequency: f32,
+    // XO-CHIP sample buffer and its playback rate, None plays the sine tone
+    pattern: Option<([u8; AUDIO_PATTERN_SIZE], f32)>,
+}
+
+// endless source that plays the tone while the buzzer is active
+// and silence otherwise
+struct Buzzer {
+    state: Arc<Mutex<BuzzerState>>,
+    current: BuzzerState,
+    samples_until_poll: u32,
+
+    gain: f32,
+    phase: f32,
+}
+
+impl Iterator for Buzzer {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.samples_until_poll == 0 {
+            self.current = *self.state.lock().unwrap();
+            self.samples_until_poll = STATE_POLL_SAMPLES;
+        }
+        self.samples_until_poll -= 1;
+
+        // move the gain towards on / off
+        let fade_step = 1. / (FADE_SECONDS * SAMPLE_RATE as f32);
+        let target = if self.current.active { 1. } else { 0. };
+        if self.gain < target {
+            self.gain = (self.gain + fade_step).min(target);
+        } else if self.gain > target {
+            self.gain = (self.gain - fade_step).max(target);
+        }
+
+        if self.gain == 0. {
+            self.phase = 0.;
+            return Some(0.);
+        }
+
+        let sample = match self.current.pattern {
+            Some((pattern, rate)) => {
+                // phase counts bits of the 128 bit pattern
+                let bit = self.phase as usize % (AUDIO_PATTERN_SIZE * 8);
+                self.phase = (self.phase + rate / SAMPLE_RATE as f32) % (AUDIO_PATTERN_SIZE * 8) as f32;
+                if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 { 1. } else { -1. }
+            },
+            None => {
+                self.phase = (self.phase + self.current.frequency / SAMPLE_RATE as f32) % 1.;
+                (self.phase * 2.0 * PI).sin()
+            }
+        };
+
+        Some(sample * self.gain)
+    }
+}
+
+impl Source for Buzzer {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
 pub struct AudioManager {
-    frequency: f32, 
+    frequency: f32,
     duration: f32,
     volume: f32,
     _stream: Arc<Mutex<OutputStream>>,
     sink: Arc<Mutex<Sink>>,
+
+    buzzer_sink: Sink,
+    buzzer_state: Arc<Mutex<BuzzerState>>,
 }
 
 impl AudioManager {
@@ -16,6 +105,22 @@ impl AudioManager {
         let (stream, stream_handle) = OutputStream::try_default().unwrap();
         let sink = Sink::try_new(&stream_handle).unwrap();
 
+        let buzzer_state = Arc::new(Mutex::new(BuzzerState {
+            active: false,
+            frequency: 0.,
+            pattern: None,
+        }));
+
+        let buzzer_sink = Sink::try_new(&stream_handle).unwrap();
+        buzzer_sink.append(Buzzer {
+            state: buzzer_state.clone(),
+            current: *buzzer_state.lock().unwrap(),
+            samples_until_poll: 0,
+
+            gain: 0.,
+            phase: 0.,
+        });
+
         AudioManager {
             frequency: 0.,
             duration: 0.,
@@ -23,6 +128,9 @@ impl AudioManager {
 
             _stream: Arc::new(Mutex::new(stream)),
             sink: Arc::new(Mutex::new(sink)),
+
+            buzzer_sink,
+            buzzer_state,
         }
     }
 
@@ -30,6 +138,26 @@ impl AudioManager {
         self.frequency = config.frequency;
         self.duration = config.duration;
         self.volume = config.volume;
+
+        self.buzzer_sink.set_volume(self.volume);
+        self.buzzer_state.lock().unwrap().frequency = self.frequency;
+    }
+
+    // called once per emulator frame
+    pub fn update_buzzer(&self, active: bool, emulator: &Emu) {
+        let mut state = self.buzzer_state.lock().unwrap();
+        state.active = active;
+
+        // ROMs that never load an audio pattern get the plain tone
+        state.pattern = if emulator.audio_pattern.iter().any(|byte| *byte != 0) {
+            Some((emulator.audio_pattern, emulator.audio_playback_rate()))
+        } else {
+            None
+        };
+    }
+
+    pub fn stop_buzzer(&self) {
+        self.buzzer_state.lock().unwrap().active = false;
     }
 
     pub fn play_async_beep(&self) {
@@ -48,7 +176,7 @@ impl AudioManager {
             let samples: Vec<f32> = (0..(44100.0 * duration) as usize)
                 .map(|t| (t as f32 * frequency * 2.0 * PI / 44100.0).sin())
                 .collect();
-        
+
             // Play the sound
             sink.append(rodio::buffer::SamplesBuffer::new(1, 44100, samples));
 
@@ -56,4 +184,4 @@ impl AudioManager {
             sink.sleep_until_end();
         });
     }
-}
\ No newline at end of file
+}
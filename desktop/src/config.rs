@@ -3,14 +3,32 @@ use std::fs;
 use std::process;
 
 
-pub const VALID_ACTIONS: [&str; 7] = [
+pub const VALID_ACTIONS: [&str; 15] = [
     "PAUSE",
     "RESET",
     "NEXT_THEME",
     "EXIT",
     "LOAD",
     "SAVE",
+    "NEXT_SLOT",
+    "REWIND",
     "HONK",
+    "STEP",
+    "STEP_OVER",
+    "STEP_OUT",
+    "CONTINUE",
+    "BREAKPOINT",
+    "MEMORY",
+];
+
+// shown in the debugger panel instead of the pause menu
+pub const DEBUGGER_ACTIONS: [&str; 6] = [
+    "STEP",
+    "STEP_OVER",
+    "STEP_OUT",
+    "CONTINUE",
+    "BREAKPOINT",
+    "MEMORY",
 ];
 
 #[derive(Deserialize, Debug)]
@@ -21,7 +39,11 @@ pub struct Config {
     pub show_fps: bool,
     
     pub max_fps: u32,
-    pub tps: u32,
+    pub cycles_per_frame: u32,
+
+    pub quirks: String,
+
+    pub rewind_seconds: f32,
 
     pub frequency: f32,
     pub duration: f32,
@@ -56,10 +78,18 @@ pub fn get_readable_action_name(action: &str) -> &str {
         "EXIT" => { "exit game" },
         "LOAD" => { "load savestate" },
         "SAVE" => { "create savestate"},
+        "NEXT_SLOT" => { "next save slot" },
+        "REWIND" => { "rewind (hold)" },
         "NEXT_THEME" => { "change theme" },
         "HONK" => { "honk" },
         "RESET" => { "reset the game" },
         "PAUSE" => { "continue" }
+        "STEP" => { "step" },
+        "STEP_OVER" => { "step over" },
+        "STEP_OUT" => { "step out" },
+        "CONTINUE" => { "run" },
+        "BREAKPOINT" => { "breakpoint at PC" },
+        "MEMORY" => { "memory viewer" },
         _ => {
             println!("ERROR: Invalid action");
             "INVALID ACTION"
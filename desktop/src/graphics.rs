@@ -2,18 +2,35 @@ use raylib::prelude::*;
 use chip8_core::*;
 use std::collections::HashMap;
 
-use crate::{config::Config, config::get_readable_action_name, themes::ThemeManager};
+use crate::{config::Config, config::get_readable_action_name, config::DEBUGGER_ACTIONS, themes::ThemeManager};
+use crate::memview::{MemoryInput, MemoryViewer, MEMORY_COLUMNS, MEMORY_ROWS};
 
 const WIN_SCALE_FAC: u32 = 15;
 const WIN_WIDTH: u32 = (SCREEN_WIDTH as u32) * WIN_SCALE_FAC;
 const WIN_HEIGHT: u32 = (SCREEN_HEIGHT as u32) * WIN_SCALE_FAC;
 
+// the debugger panel is shown right of the game while paused
+const PANEL_WIDTH: u32 = 300;
+const PANEL_FONT: i32 = 18;
+const PANEL_LINE: i32 = 20;
+// lines of the disassembly and the watches share the space above the keys
+const DISASM_LINES: usize = 9;
+const MAX_WATCH_LINES: usize = 4;
+
+// the memory viewer covers the game, the hex view is left of the sprite view
+const MEMORY_HEX_X: i32 = 70;
+const MEMORY_CELL: i32 = 30;
+const MEMORY_TOP: i32 = 44;
+const SPRITE_X: i32 = 600;
+const SPRITE_PIXEL: i32 = 14;
+
 pub struct GraphicsManager {  
     pub rl: RaylibHandle,
     pub thread: RaylibThread,
     pub canvas: Image,
 
     pub theme_manager: ThemeManager,
+    panel_visible: bool,
 }
 
 impl GraphicsManager {
@@ -34,22 +51,40 @@ impl GraphicsManager {
             canvas: Image::gen_image_color(SCREEN_WIDTH as i32, SCREEN_HEIGHT as i32, Color::GREENYELLOW),
             
             theme_manager: ThemeManager::new(),
+            panel_visible: false,
         }
     }
 
 
-    pub fn render_game(&mut self, args: &[String], config: &Config, emulator: &Emu, framebuffer_modified: bool) {
-        let texture = self.rl.load_texture_from_image(&self.thread, &self.canvas).unwrap();
+    pub fn render_game(&mut self, rom_path: &str, config: &Config, emulator: &Emu, fault: Option<&EmuError>, debug_stop: Option<&StopReason>, memory_viewer: &MemoryViewer, framebuffer_modified: bool) {
+
+        // make room for the debugger panel
+        if emulator.is_paused != self.panel_visible {
+            self.panel_visible = emulator.is_paused;
+            let width = if self.panel_visible { WIN_WIDTH + PANEL_WIDTH } else { WIN_WIDTH };
+            self.rl.set_window_size(width as i32, WIN_HEIGHT as i32);
+        }
 
-        if framebuffer_modified {
+        let screen_width = emulator.screen_width();
+        let screen_height = emulator.screen_height();
+
+        // the canvas has to follow the emulators display mode (lores / hires)
+        let mode_changed = self.canvas.width() != screen_width as i32;
+        if mode_changed {
+            self.canvas = Image::gen_image_color(screen_width as i32, screen_height as i32, self.get_ui_col("BG".to_string()));
+        }
+
+        if framebuffer_modified || mode_changed {
             let screenbuffer = emulator.get_display();
             
-            for x in 0..SCREEN_WIDTH {
-                for y in 0..SCREEN_HEIGHT {
-                    let pixel_color = if screenbuffer[x + SCREEN_WIDTH * y] {
-                        self.get_ui_col("FG".to_string())
-                    } else {
-                        self.get_ui_col("BG".to_string())
+            for x in 0..screen_width {
+                for y in 0..screen_height {
+                    // combine the XO-CHIP bitplanes
+                    let pixel_color = match screenbuffer[x + screen_width * y] {
+                        0 => self.get_ui_col("BG".to_string()),
+                        1 => self.get_ui_col("FG".to_string()),
+                        2 => self.get_ui_col("FG2".to_string()),
+                        _ => self.get_ui_col("BLEND".to_string()),
                     };
                     
                     self.canvas.draw_pixel(x as i32, y as i32, pixel_color);
@@ -59,35 +94,300 @@ impl GraphicsManager {
 
         
 
-        let rom_path = args[1].clone();
+        let texture = self.rl.load_texture_from_image(&self.thread, &self.canvas).unwrap();
+        let scale = WIN_WIDTH as f32 / screen_width as f32;
+
         let text_col = self.get_ui_col("TEXT".to_string());
         let fg_col = self.get_ui_col("FG".to_string());
         let bg_col = self.get_ui_col("BG".to_string());
+        let fg2_col = self.get_ui_col("FG2".to_string());
 
         let mut d = self.rl.begin_drawing(&self.thread);
         
         d.clear_background(Color::BLACK);
 
-        d.draw_texture_ex(&texture, Vector2::new(0.,0.), 0., (WIN_SCALE_FAC) as f32, Color::WHITE);
+        d.draw_texture_ex(&texture, Vector2::new(0.,0.), 0., scale, Color::WHITE);
         
 
-        let text_width = d.measure_text(rom_path.as_str(), 20) as u32;
+        let text_width = d.measure_text(rom_path, 20) as u32;
         let x_pos = ((WIN_WIDTH - text_width) as f32) * 0.5;
         if config.show_path {
-            d.draw_text(&args[1], x_pos as i32, 12, 20, text_col);
+            d.draw_text(rom_path, x_pos as i32, 12, 20, text_col);
         }
         if config.show_fps {
             d.draw_text(&format!("{}", d.get_fps()), 10, 10, 20, text_col);
         }
 
-        if emulator.is_paused {
+        if memory_viewer.visible {
+            Self::render_memory_viewer(&mut d, [bg_col, fg_col, text_col, fg2_col], memory_viewer, emulator);
+        }
+
+        if self.panel_visible {
+            Self::render_debugger(&mut d, bg_col, fg_col, text_col, config, emulator, debug_stop);
+        }
+
+        if let Some(error) = fault {
+            Self::render_fault(d, bg_col, fg_col, text_col, error);
+        }
+        // stopped by the debugger or looking at memory, keep the view
+        else if emulator.is_paused && debug_stop.is_none() && !memory_viewer.visible {
             Self::render_pause_menu(d,bg_col,fg_col,text_col, config);
         }
     }
 
+    fn render_debugger(d: &mut RaylibDrawHandle, bg_col: Color, fg_col: Color, txt_col: Color, config: &Config, emulator: &Emu, debug_stop: Option<&StopReason>) {
+        let x = WIN_WIDTH as i32;
+        let mut y = 8;
+
+        d.draw_rectangle(x, 0, PANEL_WIDTH as i32, WIN_HEIGHT as i32, bg_col);
+        d.draw_line(x, 0, x, WIN_HEIGHT as i32, fg_col);
+
+        let status = match debug_stop {
+            Some(reason) => format!("{}", reason),
+            None => "paused".to_string(),
+        };
+        d.draw_text("Debugger", x + 10, y, 24, fg_col);
+        y += 28;
+        d.draw_text(&status, x + 10, y, PANEL_FONT, txt_col);
+        y += PANEL_LINE + 6;
+
+        // V0 - VF in four columns
+        for row in 0..4 {
+            for col in 0..4 {
+                let reg = row * 4 + col;
+                let text = format!("V{:X} {:02X}", reg, emulator.registers[reg]);
+                d.draw_text(&text, x + 10 + col as i32 * 70, y, PANEL_FONT, txt_col);
+            }
+            y += PANEL_LINE;
+        }
+
+        let text = format!("I {:04X}  PC {:04X}  SP {:X}", emulator.i_register, emulator.programm_counter, emulator.stack_pointer);
+        d.draw_text(&text, x + 10, y, PANEL_FONT, txt_col);
+        y += PANEL_LINE;
+        let text = format!("DT {:02X}  ST {:02X}", emulator.delay_timer, emulator.sound_timer);
+        d.draw_text(&text, x + 10, y, PANEL_FONT, txt_col);
+        y += PANEL_LINE + 6;
+
+        // innermost call first, 6 addresses or 2 labels per line
+        let symbols = &emulator.debugger.symbols;
+        let stack: Vec<String> = emulator.call_stack().iter().rev().map(|addr| symbols.format(*addr)).collect();
+        let per_line = if symbols.is_empty() { 6 } else { 2 };
+        d.draw_text("Stack:", x + 10, y, PANEL_FONT, fg_col);
+        for (line, entries) in stack.chunks(per_line).take(2).enumerate() {
+            d.draw_text(&entries.join(" "), x + 80, y + line as i32 * PANEL_LINE, PANEL_FONT, txt_col);
+        }
+        y += 2 * PANEL_LINE + 6;
+
+        let watches = emulator.watch_values();
+        let watch_lines = watches.len().min(MAX_WATCH_LINES);
+
+        // labels take a line of their own
+        let mut lines_left = DISASM_LINES - watch_lines;
+        for (addr, text) in Self::disassembly_window(emulator, lines_left) {
+            if let Some(label) = symbols.label(addr) {
+                if lines_left < 2 {
+                    break;
+                }
+                d.draw_text(&format!("{}:", label), x + 10, y, PANEL_FONT, fg_col);
+                y += PANEL_LINE;
+                lines_left -= 1;
+            }
+            if lines_left == 0 {
+                break;
+            }
+            lines_left -= 1;
+
+            let marker = if addr == emulator.programm_counter { ">" } else { " " };
+            // ? for a breakpoint with a condition
+            let breakpoint = match (emulator.debugger.breakpoints.contains(&addr), emulator.debugger.conditions.contains_key(&addr)) {
+                (true, true) => "?",
+                (true, false) => "*",
+                _ => " ",
+            };
+            let color = if addr == emulator.programm_counter { fg_col } else { txt_col };
+            d.draw_text(&format!("{}{} {:03X} {}", breakpoint, marker, addr, text), x + 10, y, PANEL_FONT, color);
+            y += PANEL_LINE;
+        }
+
+        for (expression, value) in watches.iter().take(watch_lines) {
+            let value = match value {
+                Ok(value) => value.to_string(),
+                Err(error) => error.message.clone(),
+            };
+            d.draw_text(&format!("{} = {}", expression, value), x + 10, y, PANEL_FONT - 2, fg_col);
+            y += PANEL_LINE;
+        }
+
+        // key bindings of the debugger
+        let keys: Vec<String> = config.emulator_input.iter()
+            .filter(|pair| DEBUGGER_ACTIONS.contains(&pair[0].as_str()))
+            .map(|pair| format!("[{}] {}", pair[1], get_readable_action_name(&pair[0])))
+            .collect();
+        let key_lines = keys.chunks(2).len() as i32;
+        for (line, pair) in keys.chunks(2).enumerate() {
+            let y = WIN_HEIGHT as i32 - (key_lines - line as i32) * PANEL_LINE - 4;
+            d.draw_text(&pair.join("  "), x + 10, y, PANEL_FONT - 2, txt_col);
+        }
+    }
+
+    // colors are background, foreground, text and the second plane
+    fn render_memory_viewer(d: &mut RaylibDrawHandle, colors: [Color; 4], viewer: &MemoryViewer, emulator: &Emu) {
+        let [bg_col, fg_col, txt_col, fg2_col] = colors;
+        let cursor = viewer.cursor;
+
+        d.draw_rectangle(0, 0, WIN_WIDTH as i32, WIN_HEIGHT as i32, bg_col);
+        d.draw_text("Memory", 10, 8, 24, fg_col);
+        let header = format!("cursor {:04X} = {:02X}   PC {:04X}   I {:04X}", cursor, emulator.ram[cursor as usize], emulator.programm_counter, emulator.i_register);
+        d.draw_text(&header, 110, 12, PANEL_FONT, txt_col);
+
+        // legend
+        let legend_x = SPRITE_X;
+        d.draw_rectangle_lines(legend_x, 12, 16, 16, fg_col);
+        d.draw_text("PC", legend_x + 22, 12, PANEL_FONT, txt_col);
+        d.draw_rectangle_lines(legend_x + 60, 12, 16, 16, txt_col);
+        d.draw_text("I", legend_x + 82, 12, PANEL_FONT, txt_col);
+        d.draw_rectangle(legend_x + 110, 12, 16, 16, fg2_col);
+        d.draw_text("written", legend_x + 132, 12, PANEL_FONT, txt_col);
+
+        // the newest write is the brightest
+        let writes: HashMap<u16, usize> = emulator.recent_writes().enumerate().map(|(age, addr)| (addr, age)).collect();
+        let pc = emulator.programm_counter;
+        let pc_end = pc.saturating_add(emulator.instruction_at(pc).map_or(2, |instruction| instruction.size()));
+
+        for row in 0..MEMORY_ROWS {
+            let row_addr = viewer.top as usize + row * MEMORY_COLUMNS;
+            if row_addr >= RAM_SIZE {
+                break;
+            }
+            let y = MEMORY_TOP + row as i32 * PANEL_LINE;
+            d.draw_text(&format!("{:04X}", row_addr), 10, y, PANEL_FONT, fg_col);
+
+            for col in 0..MEMORY_COLUMNS {
+                let addr = (row_addr + col) as u16;
+                let x = MEMORY_HEX_X + col as i32 * MEMORY_CELL;
+                let mut text = format!("{:02X}", emulator.ram[addr as usize]);
+                let mut color = txt_col;
+
+                if let Some(age) = writes.get(&addr) {
+                    let alpha = 255 - (age * 192 / RECENT_WRITES) as u8;
+                    d.draw_rectangle(x - 4, y - 1, MEMORY_CELL - 2, PANEL_LINE, Color::new(fg2_col.r, fg2_col.g, fg2_col.b, alpha));
+                }
+                if addr == cursor {
+                    d.draw_rectangle(x - 4, y - 1, MEMORY_CELL - 2, PANEL_LINE, fg_col);
+                    color = bg_col;
+                    if let MemoryInput::Edit(digits) = &viewer.input {
+                        text = format!("{:_<2}", digits);
+                    }
+                }
+                if (pc..pc_end).contains(&addr) {
+                    d.draw_rectangle_lines(x - 4, y - 1, MEMORY_CELL - 2, PANEL_LINE, fg_col);
+                }
+                if addr == emulator.i_register {
+                    d.draw_rectangle_lines(x - 6, y - 3, MEMORY_CELL + 2, PANEL_LINE + 4, txt_col);
+                }
+                d.draw_text(&text, x, y, PANEL_FONT, color);
+            }
+        }
+
+        // what DXYN would draw, one byte per row
+        let (start, rows) = viewer.sprite_region(emulator);
+        let source = if viewer.sprite_at_cursor { "cursor" } else { "I" };
+        d.draw_text(&format!("Sprite at {:04X} ({}), {} rows", start, source, rows), SPRITE_X, MEMORY_TOP, PANEL_FONT, fg_col);
+
+        for row in 0..rows {
+            let addr = start as usize + row;
+            if addr >= RAM_SIZE {
+                break;
+            }
+            let byte = emulator.ram[addr];
+            let y = MEMORY_TOP + (row as i32 + 1) * PANEL_LINE;
+
+            for bit in 0..8 {
+                let x = SPRITE_X + bit * SPRITE_PIXEL;
+                if byte & (0x80 >> bit) != 0 {
+                    d.draw_rectangle(x, y, SPRITE_PIXEL - 1, PANEL_LINE - 2, fg_col);
+                }
+                else {
+                    d.draw_rectangle_lines(x, y, SPRITE_PIXEL - 1, PANEL_LINE - 2, fg2_col);
+                }
+            }
+            d.draw_text(&format!("{:02X}", byte), SPRITE_X + 8 * SPRITE_PIXEL + 10, y, PANEL_FONT, txt_col);
+        }
+
+        let status = match &viewer.input {
+            MemoryInput::Edit(_) => format!("editing {:04X}, type hex digits, ENTER when done", cursor),
+            MemoryInput::Goto(text) => format!("go to: {}_", text),
+            MemoryInput::Browse => viewer.message.clone().unwrap_or_default(),
+        };
+        let controls = if emulator.is_paused {
+            "arrows, PgUp/PgDn move   ENTER edit   / go to (address, label or expression)   TAB sprite at cursor or I"
+        } else {
+            "live view, pause to move and edit"
+        };
+        d.draw_text(&status, 10, WIN_HEIGHT as i32 - 2 * PANEL_LINE - 4, PANEL_FONT, fg_col);
+        d.draw_text(controls, 10, WIN_HEIGHT as i32 - PANEL_LINE - 4, PANEL_FONT - 2, txt_col);
+    }
+
+    // instructions around PC. The start is a guess, there is no way
+    // to know where instructions begin before PC
+    fn disassembly_window(emulator: &Emu, count: usize) -> Vec<(u16, String)> {
+        let pc = emulator.programm_counter;
+        // a third of the lines before PC
+        let mut addr = pc.saturating_sub(2 * (count / 3) as u16);
+        let mut lines = Vec::new();
+
+        while lines.len() < count && (addr as usize) + 1 < RAM_SIZE {
+            // don't walk past PC when guessing the start
+            if addr < pc && addr.wrapping_add(4) > pc {
+                if let Some(Instruction::LoadLongI(_)) = emulator.instruction_at(addr) {
+                    lines.push((addr, format!("{:02X}", emulator.ram[addr as usize])));
+                    addr += 1;
+                    continue;
+                }
+            }
+
+            match emulator.instruction_at(addr) {
+                Some(instruction) => {
+                    lines.push((addr, emulator.debugger.symbols.mnemonic(&instruction)));
+                    addr = addr.wrapping_add(instruction.size());
+                },
+                None => {
+                    let word = ((emulator.ram[addr as usize] as u16) << 8) | emulator.ram[addr as usize + 1] as u16;
+                    lines.push((addr, format!("{:04X}", word)));
+                    addr = addr.wrapping_add(2);
+                }
+            }
+        }
+
+        lines
+    }
+
+    fn render_fault(mut d: RaylibDrawHandle, bg_col: Color, fg_col: Color, txt_col: Color, error: &EmuError) {
+        let message = format!("{}", error);
+
+        // draw box
+        let fm_width: i32 = (d.measure_text(&message, 20) + 40).max(320);
+        let fm_height: i32 = 130;
+
+        let fm_x = ((WIN_WIDTH as i32 - fm_width) as f32 * 0.5) as i32;
+        let fm_y = ((WIN_HEIGHT as i32 - fm_height) as f32 * 0.5) as i32;
+
+        d.draw_rectangle(fm_x, fm_y, fm_width, fm_height, bg_col);
+        d.draw_rectangle_lines(fm_x, fm_y, fm_width, fm_height, fg_col);
+
+        // draw text
+        d.draw_text("Emulator fault:", fm_x + 20, fm_y + 5, 32, fg_col);
+        d.draw_text(&message, fm_x + 20, fm_y + 55, 20, txt_col);
+        d.draw_text("reset or load a savestate", fm_x + 20, fm_y + 95, 20, txt_col);
+    }
+
     fn render_pause_menu(mut d: RaylibDrawHandle, bg_col: Color, fg_col: Color, txt_col: Color, config: &Config) {
 
-        let all_options = config.emulator_input.clone();
+        // the debugger keys are listed in the debugger panel
+        let all_options: Vec<Vec<String>> = config.emulator_input.iter()
+            .filter(|pair| !DEBUGGER_ACTIONS.contains(&pair[0].as_str()))
+            .cloned()
+            .collect();
         let num_options = all_options.len();
 
         // draw box
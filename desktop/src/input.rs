@@ -180,4 +180,12 @@ impl InputManager {
         actions_buffer
     }
 
+    // for actions that last as long as their key is held
+    pub fn is_action_down(&self, action: &str, rl: &RaylibHandle) -> bool {
+        match self.emu_keymap.get(action) {
+            Some(ray_key) => rl.is_key_down(*ray_key),
+            None => false,
+        }
+    }
+
 }
@@ -1,12 +1,15 @@
 mod app;
+mod args;
 mod audio;
 
 mod savestates;
+mod movies;
 mod config;
 
 mod input;
 mod themes;
 mod graphics;
+mod memview;
 
 use app::AppManager;
 
@@ -14,6 +17,7 @@ fn main() {
     let mut ui = AppManager::new();
 
     ui.load_rom(None);
+    ui.start_movie();
 
     ui.main_loop();
 }
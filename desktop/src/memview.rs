@@ -0,0 +1,188 @@
+use chip8_core::*;
+
+use raylib::prelude::*;
+use raylib::consts::KeyboardKey::*;
+
+// bytes per row and rows of the hex view
+pub const MEMORY_COLUMNS: usize = 16;
+pub const MEMORY_ROWS: usize = 16;
+// sprite rows shown when PC is not at a DXYN
+pub const SPRITE_ROWS: usize = 16;
+
+const PAGE_SIZE: u16 = (MEMORY_COLUMNS * MEMORY_ROWS) as u16;
+
+// what the typed keys go to
+pub enum MemoryInput {
+    Browse,
+    Edit(String),   // hex digits of the byte at the cursor
+    Goto(String),   // an expression like 300, I or draw+4
+}
+
+// Hex view of the RAM, toggled with the MEMORY action. Works as a live
+// view while the ROM runs, while paused the cursor moves and bytes can
+// be edited. The sprite view shows the bytes at I as 8 pixel rows.
+pub struct MemoryViewer {
+    pub visible: bool,
+    pub cursor: u16,
+    pub top: u16,           // first address shown, a multiple of 16
+    pub sprite_at_cursor: bool,     // the sprite view follows the cursor instead of I
+    pub input: MemoryInput,
+    pub message: Option<String>,    // shown in the status line
+}
+
+impl MemoryViewer {
+    pub fn new() -> Self {
+        MemoryViewer {
+            visible: false,
+            cursor: 0x200,
+            top: 0x200,
+            sprite_at_cursor: false,
+            input: MemoryInput::Browse,
+            message: None,
+        }
+    }
+
+    pub fn toggle(&mut self, emulator: &mut Emu) {
+        self.visible ^= true;
+        self.input = MemoryInput::Browse;
+        self.message = None;
+        emulator.debugger.track_writes = self.visible;
+    }
+
+    // true while typed keys belong to the viewer, the game and the
+    // other actions don't get them
+    pub fn is_capturing(&self) -> bool {
+        self.visible && !matches!(self.input, MemoryInput::Browse)
+    }
+
+    // the first sprite byte and the number of rows
+    pub fn sprite_region(&self, emulator: &Emu) -> (u16, usize) {
+        let rows = match emulator.instruction_at(emulator.programm_counter) {
+            Some(Instruction::Draw(_, _, n)) if n > 0 => n as usize,
+            _ => SPRITE_ROWS,
+        };
+        let start = if self.sprite_at_cursor { self.cursor } else { emulator.i_register };
+        (start, rows)
+    }
+
+    // navigation and editing only work while paused, `editable` is false
+    // while a movie runs. Returns true if the keys of this frame were
+    // taken by the viewer
+    pub fn handle_input(&mut self, emulator: &mut Emu, rl: &mut RaylibHandle, editable: bool) -> bool {
+        if !self.visible || !emulator.is_paused {
+            return false;
+        }
+        let was_capturing = self.is_capturing();
+
+        self.input = match std::mem::replace(&mut self.input, MemoryInput::Browse) {
+            MemoryInput::Browse => self.browse(rl, editable),
+            MemoryInput::Edit(mut digits) => {
+                while let Some(c) = rl.get_char_pressed() {
+                    if c.is_ascii_hexdigit() {
+                        digits.push(c);
+                    }
+                }
+                if digits.len() >= 2 {
+                    // two digits write the byte, editing goes on with the next one
+                    emulator.ram[self.cursor as usize] = u8::from_str_radix(&digits[..2], 16).unwrap_or(0);
+                    self.move_cursor(1);
+                    digits.clear();
+                }
+
+                if rl.is_key_pressed(KEY_ENTER) || rl.is_key_pressed(KEY_ESCAPE) {
+                    MemoryInput::Browse
+                } else {
+                    MemoryInput::Edit(digits)
+                }
+            },
+            MemoryInput::Goto(mut text) => {
+                while let Some(c) = rl.get_char_pressed() {
+                    text.push(c);
+                }
+                if rl.is_key_pressed(KEY_BACKSPACE) {
+                    text.pop();
+                }
+
+                if rl.is_key_pressed(KEY_ENTER) {
+                    self.goto(emulator, &text);
+                    MemoryInput::Browse
+                } else if rl.is_key_pressed(KEY_ESCAPE) {
+                    MemoryInput::Browse
+                } else {
+                    MemoryInput::Goto(text)
+                }
+            },
+        };
+
+        was_capturing || self.is_capturing()
+    }
+
+    fn browse(&mut self, rl: &mut RaylibHandle, editable: bool) -> MemoryInput {
+        let columns = MEMORY_COLUMNS as i32;
+        let moves = [
+            (KEY_LEFT, -1), (KEY_RIGHT, 1), (KEY_UP, -columns), (KEY_DOWN, columns),
+            (KEY_PAGE_UP, -(PAGE_SIZE as i32)), (KEY_PAGE_DOWN, PAGE_SIZE as i32),
+        ];
+        for (key, offset) in moves {
+            if rl.is_key_pressed(key) {
+                self.move_cursor(offset);
+            }
+        }
+
+        if rl.is_key_pressed(KEY_TAB) {
+            self.sprite_at_cursor ^= true;
+        }
+        if rl.is_key_pressed(KEY_SLASH) {
+            self.message = None;
+            // the slash itself is typed too
+            while rl.get_char_pressed().is_some() {}
+            return MemoryInput::Goto(String::new());
+        }
+        if rl.is_key_pressed(KEY_ENTER) {
+            if editable {
+                self.message = None;
+                return MemoryInput::Edit(String::new());
+            }
+            self.message = Some("memory can't be edited while a movie runs".to_string());
+        }
+        MemoryInput::Browse
+    }
+
+    // addresses are expressions, so I, PC, labels and hex numbers work
+    fn goto(&mut self, emulator: &Emu, text: &str) {
+        // plain hex digits are an address, unless they are a label like "add"
+        let text = text.trim();
+        let is_hex = !text.is_empty() && text.chars().all(|c| c.is_ascii_hexdigit());
+        let text = if is_hex && emulator.debugger.symbols.addr_of(text).is_none() {
+            format!("0x{}", text)
+        } else {
+            text.to_string()
+        };
+
+        let value = Expression::parse_with(&text, &emulator.debugger.symbols)
+            .and_then(|expression| expression.evaluate(emulator));
+        match value {
+            Ok(ExprValue::Number(addr)) if (0..RAM_SIZE as i64).contains(&addr) => {
+                self.cursor = addr as u16;
+                self.scroll_to_cursor();
+            },
+            Ok(value) => self.message = Some(format!("{} is not an address", value)),
+            Err(error) => self.message = Some(error.to_string()),
+        }
+    }
+
+    fn move_cursor(&mut self, offset: i32) {
+        self.cursor = (self.cursor as i32 + offset).clamp(0, RAM_SIZE as i32 - 1) as u16;
+        self.scroll_to_cursor();
+    }
+
+    fn scroll_to_cursor(&mut self) {
+        let row = self.cursor - self.cursor % MEMORY_COLUMNS as u16;
+        if row < self.top {
+            self.top = row;
+        }
+        else if row >= self.top.saturating_add(PAGE_SIZE) {
+            self.top = row + MEMORY_COLUMNS as u16 - PAGE_SIZE;
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use chip8_core::*;
+
+use std::fs;
+use std::process;
+
+#[derive(PartialEq)]
+enum MovieMode {
+    Recording,
+    Playing,
+}
+
+// a movie that is recorded or played back, see chip8_core::Movie
+pub struct MovieSession {
+    mode: MovieMode,
+    path: String,
+    movie: Movie,
+    frame: usize,
+    desynced: bool,
+}
+
+impl MovieSession {
+    // call right after the ROM was loaded
+    pub fn record(path: String, emulator: &Emu, cycles_per_frame: u32) -> Self {
+        println!("INFO: Recording movie to [{}]", path);
+
+        MovieSession {
+            mode: MovieMode::Recording,
+            path,
+            movie: Movie::new(emulator, cycles_per_frame),
+            frame: 0,
+            desynced: false,
+        }
+    }
+
+    // call right after the ROM was loaded. Overrides seed and quirks
+    pub fn play(path: String, emulator: &mut Emu) -> Self {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) => {
+                println!("ERROR: Movie [{}] could not be read: {}", path, error);
+                process::exit(0);
+            }
+        };
+
+        let movie = match Movie::from_text(&text).and_then(|movie| movie.apply(emulator).map(|_| movie)) {
+            Ok(movie) => movie,
+            Err(error) => {
+                println!("ERROR: Movie [{}] can't be played: {}", path, error);
+                process::exit(0);
+            }
+        };
+        println!("INFO: Playing movie [{}] ({} frames), keyboard input is ignored", path, movie.frames.len());
+
+        MovieSession {
+            mode: MovieMode::Playing,
+            path,
+            movie,
+            frame: 0,
+            desynced: false,
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.mode == MovieMode::Playing
+    }
+
+    pub fn cycles_per_frame(&self) -> u32 {
+        self.movie.cycles_per_frame
+    }
+
+    // after a reset the movie starts over
+    pub fn restart(&mut self, emulator: &Emu) {
+        self.frame = 0;
+        self.desynced = false;
+        if self.mode == MovieMode::Recording {
+            self.movie = Movie::new(emulator, self.movie.cycles_per_frame);
+        }
+    }
+
+    // during playback the keypad of the frame comes from the movie.
+    // Returns false once the playback is over
+    pub fn before_frame(&mut self, emulator: &mut Emu) -> bool {
+        match self.mode {
+            MovieMode::Recording => true,
+            MovieMode::Playing => {
+                let playing = self.movie.press_keys(self.frame, emulator);
+                if !playing {
+                    println!("INFO: Movie finished after {} frames", self.frame);
+                }
+                playing
+            }
+        }
+    }
+
+    pub fn after_frame(&mut self, emulator: &Emu) {
+        match self.mode {
+            // the keypad was set by InputManager::handle_game_input
+            MovieMode::Recording => self.movie.record_frame(emulator.keypad(), emulator),
+            MovieMode::Playing => {
+                if let Err(error) = self.movie.verify(self.frame, emulator) {
+                    // only the first desync is interesting
+                    if !self.desynced {
+                        println!("ERROR: Movie [{}]: {}", self.path, error);
+                        self.desynced = true;
+                    }
+                }
+            }
+        }
+        self.frame += 1;
+    }
+
+    // writes the recording to disk
+    pub fn finish(&self) {
+        if self.mode != MovieMode::Recording {
+            return;
+        }
+        match fs::write(&self.path, self.movie.to_text()) {
+            Ok(()) => println!("INFO: Movie with {} frames written to [{}]", self.movie.frames.len(), self.path),
+            Err(error) => println!("ERROR: Movie could not be written to [{}]: {}", self.path, error),
+        }
+    }
+}
@@ -1,60 +1,70 @@
 use chip8_core::*;
 
-pub struct SaveState {
-    programm_counter: u16,  // to keep count at which instruction we are
-    ram: [u8; chip8_core::RAM_SIZE],
-    registers: [u8; chip8_core::REGISTER_COUNT],
-    i_register: u16,
-    stack_pointer: u16,
-    screen: [bool; SCREEN_WIDTH * SCREEN_HEIGHT],
-    stack: [u16; chip8_core::STACK_SIZE],
-    delay_timer: u8,    // performs any action after finished
-    sound_timer: u8,    // plays sound after finished
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// savestates are written to SAVESTATE_DIR/<rom name>.<slot>.state
+pub const SAVESTATE_DIR: &str = "savestates";
+pub const SAVE_SLOTS: usize = 10;
+
+pub struct SaveSlots {
+    pub selected: usize,
 }
 
-impl Default for SaveState {
-    fn default() -> Self {
-        SaveState {
-            programm_counter: 0,  // to keep count at which instruction we are
-            ram: [0; chip8_core::RAM_SIZE],
-            registers: [0; chip8_core::REGISTER_COUNT],
-            screen: [false; SCREEN_WIDTH * SCREEN_HEIGHT],
-            i_register: 0,
-            stack_pointer: 0,
-            stack: [0; chip8_core::STACK_SIZE],
-            delay_timer: 0,    // performs any action after finished
-            sound_timer: 0,    // plays sound after finished
+impl SaveSlots {
+    pub fn new() -> Self {
+        SaveSlots {
+            selected: 0,
         }
     }
-}
 
-pub fn make_save(emulator: &Emu) -> SaveState {
-    println!("INFO: SaveState recorded!");
-
-    SaveState {
-        programm_counter: emulator.programm_counter,  
-        ram: emulator.ram,
-        registers: emulator.registers,
-        i_register: emulator.i_register,
-        screen: emulator.screen,
-        stack_pointer: emulator.stack_pointer,
-        stack: emulator.stack,
-        delay_timer: emulator.delay_timer,     
-        sound_timer: emulator.sound_timer, 
+    pub fn next_slot(&mut self) {
+        self.selected = (self.selected + 1) % SAVE_SLOTS;
+        println!("INFO: Selected savestate slot {}", self.selected);
     }
-}
 
-pub fn load_save(save: &SaveState, emulator: &mut Emu) {
-    println!("INFO: SaveState loaded!");
-
-    emulator.programm_counter = save.programm_counter;
-    emulator.ram = save.ram;
-    emulator.registers = save.registers;
-    emulator.i_register = save.i_register;
-    emulator.screen = save.screen;
-    emulator.stack_pointer = save.stack_pointer;
-    emulator.stack = save.stack;
-    emulator.delay_timer = save.delay_timer;
-    emulator.sound_timer = save.sound_timer;
-}
+    fn slot_path(&self, rom_path: &str) -> PathBuf {
+        let rom_name = Path::new(rom_path)
+            .file_stem()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or("rom".to_string());
+
+        Path::new(SAVESTATE_DIR).join(format!("{}.{}.state", rom_name, self.selected))
+    }
+
+    pub fn save(&self, rom_path: &str, emulator: &Emu) {
+        let path = self.slot_path(rom_path);
+        let bytes = SaveState::capture(emulator).to_bytes();
 
+        let result = fs::create_dir_all(SAVESTATE_DIR).and_then(|_| fs::write(&path, bytes));
+        match result {
+            Ok(()) => println!("INFO: SaveState written to slot {} [{}]", self.selected, path.display()),
+            Err(error) => println!("ERROR: SaveState could not be written to [{}]: {}", path.display(), error),
+        }
+    }
+
+    // returns false if the emulator was left untouched
+    pub fn load(&self, rom_path: &str, emulator: &mut Emu) -> bool {
+        let path = self.slot_path(rom_path);
+
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(_) => {
+                println!("INFO: Savestate slot {} is empty", self.selected);
+                return false;
+            }
+        };
+
+        let result = SaveState::from_bytes(&bytes).and_then(|save| save.restore(emulator));
+        match result {
+            Ok(()) => {
+                println!("INFO: SaveState loaded from slot {}", self.selected);
+                true
+            },
+            Err(error) => {
+                println!("ERROR: SaveState [{}] could not be loaded: {}", path.display(), error);
+                false
+            }
+        }
+    }
+}
@@ -16,16 +16,23 @@ impl ThemeManager {
     }
     
     pub fn parse_themes(&mut self, config: &config::Config) {
-        // input: Vec<Vec3<String>>
+        // input: Vec<Vec3<String>> or Vec<Vec5<String>>
 
         let mut final_themes: Vec<HashMap<String, String>> = Vec::new();
 
         for theme in &config.themes {
+            // themes without XO-CHIP plane colors reuse
+            // the text color for plane 2 and FG for both planes
+            let fg2 = theme.get(3).unwrap_or(&theme[2]);
+            let blend = theme.get(4).unwrap_or(&theme[1]);
+
             final_themes.push(
                 HashMap::from([
                     ("BG".to_string(), theme[0].to_string()),
                     ("FG".to_string(), theme[1].to_string()),
-                    ("TEXT".to_string(), theme[2].to_string())
+                    ("TEXT".to_string(), theme[2].to_string()),
+                    ("FG2".to_string(), fg2.to_string()),
+                    ("BLEND".to_string(), blend.to_string())
                 ])
             );
         }